@@ -1,13 +1,24 @@
+use std::io;
+
 use ndarray::Array2;
 
 
+// Distance kept from the bounds of the output range when reversing, so the input stays finite
+const REVERSE_EPSILON: f64 = 1e-7;
+
 
+// Written with the names of name()
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Activation {
     Identity,
     Binary(f64),
     Sigmoid,
+    #[serde(rename = "tanh")]
     TanH,
+    #[serde(rename = "relu")]
     ReLU,
+    #[serde(rename = "leaky_relu")]
     LeakyReLU(f64),
     Softmax,
     LogSoftmax,
@@ -15,6 +26,19 @@ pub enum Activation {
 
 
 impl Activation {
+    pub fn name(&self) -> String {
+        match *self {
+            Activation::Identity => "identity".to_owned(),
+            Activation::Binary(threshold) => format!("binary({})", threshold),
+            Activation::Sigmoid => "sigmoid".to_owned(),
+            Activation::TanH => "tanh".to_owned(),
+            Activation::ReLU => "relu".to_owned(),
+            Activation::LeakyReLU(slope) => format!("leaky_relu({})", slope),
+            Activation::Softmax => "softmax".to_owned(),
+            Activation::LogSoftmax => "log_softmax".to_owned(),
+        }
+    }
+
     pub fn compute(&self, array: &Array2<f64>) -> Array2<f64> {
         match *self {
             Activation::Identity => {
@@ -39,7 +63,7 @@ impl Activation {
                 let mut result = array.clone();
                 let mut inter = array.clone();
                 for i in 0..result.rows() {
-                    let max_value = array.slice(s![i, ..]).iter().cloned().fold(0./0., f64::max);
+                    let max_value = array.slice(s![i, ..]).iter().cloned().fold(f64::NAN, f64::max);
                     inter.slice_mut(s![i, ..]).assign(&array.slice(s![i, ..]).map(|v| (v - max_value).exp()));
                     let sum = inter.slice(s![i, ..]).scalar_sum();
                     result.slice_mut(s![i, ..]).assign(&inter.slice(s![i, ..]).map(|v| v / sum));
@@ -52,7 +76,7 @@ impl Activation {
 
                 // First implementation
                 for i in 0..array.rows() {
-                    let log_sum_exp = (array.slice(s![i, ..]).map(|v| v.exp()).scalar_sum() as f64).ln();
+                    let log_sum_exp = array.slice(s![i, ..]).map(|v| v.exp()).scalar_sum().ln();
                     result.slice_mut(s![i, ..]).assign(&array.slice(s![i, ..]).map(|v| v - log_sum_exp));
                 }
 
@@ -78,8 +102,8 @@ impl Activation {
             Activation::Identity => {
                 array.map(|_| 1.0)
             },
-            Activation::Binary(threshold) => {
-                array.map(|v| 0.0)
+            Activation::Binary(_) => {
+                array.map(|_| 0.0)
             },
             Activation::Sigmoid => {
                 self.compute(array).map(|v| v * (1.0 - v))
@@ -111,8 +135,21 @@ impl Activation {
                 result
             },
             Activation::LogSoftmax => {
-                Activation::Softmax.compute_derivative(array)
-                // TODO : implement
+                // WARNING : must be called by compute_loss() function only
+                // Jacobian of log softmax, array must be the softmax output
+                assert_eq!(array.rows(), 1);
+                let mut result = Array2::<f64>::zeros((array.cols(), array.cols()));
+                for i in 0..array.cols() {
+                    for j in 0..array.cols() {
+                        let kronecker_delta = if i == j {
+                            1.0
+                        } else {
+                            0.0
+                        };
+                        result[[i, j]] = kronecker_delta - array[[0, j]];
+                    }
+                }
+                result
             }
         }
     }
@@ -121,15 +158,13 @@ impl Activation {
         assert_eq!(objective_derivative.rows(), array.rows(), "Objective and array does not have same amount of rows");
         assert_eq!(objective_derivative.cols(), array.cols(), "Objective and array does not have same amount of columns");
 
-        let mut loss = Array2::<f64>::zeros((objective_derivative.rows(), objective_derivative.cols()));
-
-
         match *self {
-            Activation::Softmax => {
+            Activation::Softmax | Activation::LogSoftmax => {
 
                 let mut result = objective_derivative.clone();
                 for i in 0..objective_derivative.rows() {
-                    let activation_derivative = self.compute_derivative(&self.compute(&array.slice(s![i..i+1, ..]).to_owned()));
+                    let softmax = Activation::Softmax.compute(&array.slice(s![i..i+1, ..]).to_owned());
+                    let activation_derivative = self.compute_derivative(&softmax);
                     //let activation_derivative = self.compute_derivative(&array.slice(s![i..i+1, ..]).to_owned());
 
 
@@ -141,23 +176,42 @@ impl Activation {
                             )
                         );
                 }
-                loss = result;
+                result
             },
             _ => {
-                //loss = objective_derivative * &self.compute_derivative(&self.compute(array));
-                loss = objective_derivative * &self.compute_derivative(array);
+                objective_derivative * &self.compute_derivative(array)
             }
         }
-        loss
     }
 
-    pub fn compute_reverse(&self, array: &Array2<f64>) -> Array2<f64> {     // Is that really useful for heatmap ?
+    // Input giving the array as output, values outside of the output range are clamped to it first
+    // Softmax and LogSoftmax outputs only define their input up to a constant added to every value of a row
+    pub fn compute_reverse(&self, array: &Array2<f64>) -> io::Result<Array2<f64>> {
         match *self {
+            Activation::Identity | Activation::LogSoftmax => {
+                Ok(array.clone())
+            },
+            Activation::Binary(_) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "Binary activation can not be reversed"))
+            },
+            Activation::Sigmoid => {
+                Ok(array.map(|v| {
+                    let v = v.clamp(REVERSE_EPSILON, 1.0 - REVERSE_EPSILON);
+                    (v / (1.0 - v)).ln()
+                }))
+            },
+            Activation::TanH => {
+                Ok(array.map(|v| v.clamp(-1.0 + REVERSE_EPSILON, 1.0 - REVERSE_EPSILON).atanh()))
+            },
+            Activation::ReLU => {
+                Ok(array.map(|v| v.max(0.0)))
+            },
+            Activation::LeakyReLU(slope) => {
+                Ok(array.map(|v| if *v >= 0.0 { *v } else if slope > 0.0 { v / slope } else { 0.0 }))
+            },
             Activation::Softmax => {
-                array.clone()
+                Ok(array.map(|v| v.max(REVERSE_EPSILON).ln()))
             },
-            _ => array.clone(),
-            //_ => unreachable!(),
         }
     }
 
@@ -194,6 +248,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn reverse() {
+        let input = arr2(&[[-2.0, -0.5, 0.0, 0.3, 4.0]]);
+        for activation in &[Activation::Identity, Activation::Sigmoid, Activation::TanH, Activation::LeakyReLU(0.3)] {
+            let reversed = activation.compute_reverse(&activation.compute(&input)).unwrap();
+            assert!(reversed.all_close(&input, 1e-9));
+        }
+
+        // Only defined on the positive domain
+        let reversed = Activation::ReLU.compute_reverse(&Activation::ReLU.compute(&input)).unwrap();
+        assert_eq!(reversed, arr2(&[[0.0, 0.0, 0.0, 0.3, 4.0]]));
+
+        // Up to a constant
+        for activation in &[Activation::Softmax, Activation::LogSoftmax] {
+            let difference = activation.compute_reverse(&activation.compute(&input)).unwrap() - &input;
+            assert!(difference.iter().all(|v| (v - difference[[0, 0]]).abs() < 1e-9));
+        }
+
+        // Outside of the output range
+        assert!(Activation::Sigmoid.compute_reverse(&arr2(&[[1.0, -0.1]])).unwrap().iter().all(|v| v.is_finite()));
+        assert!(Activation::TanH.compute_reverse(&arr2(&[[1.5, -1.0]])).unwrap().iter().all(|v| v.is_finite()));
+        assert!(Activation::Binary(0.5).compute_reverse(&input).is_err());
+    }
+
     #[test]
     fn binary() {
         test_activation_function(
@@ -329,14 +407,34 @@ mod tests {
         );
     }
 
+    // Softmax derivatives are a Jacobian per row, only applied through compute_loss() :
+    // with a gradient of 1 on output j, row r of the loss holds the derivatives of output j of row r
+    fn test_jacobian(activation_function: Activation, input: &Array2<f64>) {
+        let epsilon = 1e-6;
+        for j in 0..input.cols() {
+            let mut objective_derivative = Array2::<f64>::zeros(input.dim());
+            objective_derivative.column_mut(j).fill(1.0);
+            let loss = activation_function.compute_loss(&objective_derivative, input);
+
+            for ((row, col), analytical) in loss.indexed_iter() {
+                let mut plus = input.clone();
+                plus[[row, col]] += epsilon;
+                let mut minus = input.clone();
+                minus[[row, col]] -= epsilon;
+                let numerical = (activation_function.compute(&plus)[[row, j]] - activation_function.compute(&minus)[[row, j]]) / (2.0 * epsilon);
+                assert!((analytical - numerical).abs() < 1e-6, "Derivative of output {} with regard to input {} of row {}", j, col, row);
+            }
+        }
+    }
+
     #[test]
     fn softmax() {
-        test_activation_function(
-            Activation::Softmax,
-            arr2(&[
-                [-5., -1., 0., -0.1],
-                [1., 0.1, 0.01, 11.],
-            ]),
+        let input = arr2(&[
+            [-5., -1., 0., -0.1],
+            [1., 0.1, 0.01, 11.],
+        ]);
+        assert_eq!(
+            Activation::Softmax.compute(&input),
             arr2(&[
                 [
                     0.002955946738114491,
@@ -351,11 +449,10 @@ mod tests {
                     0.9999192787970065,
                 ],
             ]),
-            arr2(&[
-                [-1.5, -0.5, -0.25, -0.275],
-                [0.0, -0.225, -0.2475, 2.5],
-            ]),
         );
+
+        test_jacobian(Activation::Softmax, &input);
+        test_jacobian(Activation::LogSoftmax, &input);
     }
 
 
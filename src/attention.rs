@@ -0,0 +1,612 @@
+// Multi-head self-attention and Transformer encoder block
+//
+// Each row of the input is one sequence, flattened token after token :
+// [t0_f0, t0_f1, ..., t1_f0, t1_f1, ...]
+// so these layers keep the same (batch, features) layout as the dense layers and can be chained with them.
+
+use rand::distributions::Range;
+use ndarray::{Array2, ArrayView1, Axis};
+use ndarray_rand::RandomExt;
+
+use activation::Activation;
+use layer::Dense;
+
+
+// Value added to the attention scores of masked positions, softmax turns it into a zero weight
+const MASKED_SCORE: f64 = -1e9;
+
+
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+pub struct AttentionMask {
+    // A token can only attend to itself and to the tokens before it
+    pub causal: bool,
+    // Tokens with only zero features are considered as padding and are never attended to.
+    // Padding is found on the input of the first attention layer, then given to the following ones
+    // since their inputs are no longer zero on the padding tokens, see padding().
+    pub padding: bool,
+}
+
+
+// Intermediate values of a forward pass, needed by backward()
+pub struct AttentionCache {
+    queries: Array2<f64>,
+    keys: Array2<f64>,
+    values: Array2<f64>,
+    attention_weights: Vec<Array2<f64>>,    // One (sequence_length, sequence_length) matrix per sample and head
+    context: Array2<f64>,
+}
+
+impl AttentionCache {
+    fn empty() -> Self {
+        Self {
+            queries: Array2::<f64>::zeros((1, 1)),
+            keys: Array2::<f64>::zeros((1, 1)),
+            values: Array2::<f64>::zeros((1, 1)),
+            attention_weights: Vec::new(),
+            context: Array2::<f64>::zeros((1, 1)),
+        }
+    }
+}
+
+pub struct MultiHeadAttention {
+    sequence_length: usize,
+    model_size: usize,
+    heads: usize,
+    pub mask: AttentionMask,
+    pub query_weights: Array2<f64>,
+    pub key_weights: Array2<f64>,
+    pub value_weights: Array2<f64>,
+    pub output_weights: Array2<f64>,
+    pub query_bias: Array2<f64>,
+    pub key_bias: Array2<f64>,
+    pub value_bias: Array2<f64>,
+    pub output_bias: Array2<f64>,
+    cache: AttentionCache,
+    pub activities: Array2<f64>,
+}
+
+impl MultiHeadAttention {
+    pub fn new(sequence_length: usize, model_size: usize, heads: usize, mask: AttentionMask) -> Self {
+        // Reported as Error::Heads by NeuralNetworkBuilder::try_build()
+        assert!(heads > 0, "Attention needs at least one head");
+        assert_eq!(model_size % heads, 0, "Model size must be a multiple of the number of heads");
+
+        let limit = 1.0 / (model_size as f64).sqrt();
+        let random_weights = || Array2::<f64>::random((model_size, model_size), Range::new(-limit, limit));
+
+        Self {
+            sequence_length,
+            model_size,
+            heads,
+            mask,
+            query_weights: random_weights(),
+            key_weights: random_weights(),
+            value_weights: random_weights(),
+            output_weights: random_weights(),
+            query_bias: Array2::<f64>::zeros((1, model_size)),
+            key_bias: Array2::<f64>::zeros((1, model_size)),
+            value_bias: Array2::<f64>::zeros((1, model_size)),
+            output_bias: Array2::<f64>::zeros((1, model_size)),
+            cache: AttentionCache::empty(),
+            activities: Array2::<f64>::zeros((1, 1)),
+        }
+    }
+
+    pub fn sequence_length(&self) -> usize {
+        self.sequence_length
+    }
+
+    pub fn model_size(&self) -> usize {
+        self.model_size
+    }
+
+    pub fn heads(&self) -> usize {
+        self.heads
+    }
+
+    fn head_size(&self) -> usize {
+        self.model_size / self.heads
+    }
+
+    // Padding of the input, unless a previous layer already found it for sequences of the same length
+    pub fn padding(&self, input: &Array2<f64>, previous: Option<Array2<bool>>) -> Option<Array2<bool>> {
+        let is_known = previous.as_ref().is_some_and(|padding| padding.dim() == (input.rows(), self.sequence_length));
+        if self.mask.padding && !is_known {
+            Some(find_padding(input, self.sequence_length))
+        } else {
+            previous
+        }
+    }
+
+    // Without a padding given, it is found on the input
+    pub fn calculate_activities(&mut self, input: &Array2<f64>, padding: Option<&Array2<bool>>) {
+        let (cache, activities) = self.forward(input, padding);
+        self.cache = cache;
+        self.activities = activities;
+    }
+
+    // Same as calculate_activities() without keeping intermediate values
+    pub fn predict(&self, input: &Array2<f64>, padding: Option<&Array2<bool>>) -> Array2<f64> {
+        self.forward(input, padding).1
+    }
+
+    // Same as calculate_activities(), returning the intermediate values instead of keeping them
+    pub fn forward(&self, input: &Array2<f64>, padding: Option<&Array2<bool>>) -> (AttentionCache, Array2<f64>) {
+        assert_eq!(input.cols(), self.sequence_length * self.model_size, "Input does not match sequence length and model size");
+
+        let padding = if self.mask.padding {
+            Some(padding.cloned().unwrap_or_else(|| find_padding(input, self.sequence_length)))
+        } else {
+            None
+        };
+        if let Some(ref padding) = padding {
+            assert_eq!(padding.dim(), (input.rows(), self.sequence_length), "Padding does not match the input");
+        }
+
+        let tokens = to_tokens(input, self.model_size);
+        let queries = tokens.dot(&self.query_weights) + &self.query_bias;
+        let keys = tokens.dot(&self.key_weights) + &self.key_bias;
+        let values = tokens.dot(&self.value_weights) + &self.value_bias;
+
+        let length = self.sequence_length;
+        let head_size = self.head_size();
+        let scale = 1.0 / (head_size as f64).sqrt();
+
+        let mut context = Array2::<f64>::zeros(tokens.dim());
+        let mut attention_weights = Vec::with_capacity(input.rows() * self.heads);
+
+        for sample in 0..input.rows() {
+            let rows = sample * length..(sample + 1) * length;
+            let mask = self.mask_for(padding.as_ref().map(|padding| padding.row(sample)));
+            // Softmax would spread these queries uniformly over future and padding tokens, they attend to nothing instead
+            let fully_masked: Vec<bool> = mask.genrows().into_iter().map(|row| row.iter().all(|v| *v == MASKED_SCORE)).collect();
+
+            for head in 0..self.heads {
+                let columns = head * head_size..(head + 1) * head_size;
+                let query = queries.slice(s![rows.clone(), columns.clone()]);
+                let key = keys.slice(s![rows.clone(), columns.clone()]);
+                let value = values.slice(s![rows.clone(), columns.clone()]);
+
+                let scores = query.dot(&key.t()) * scale + &mask;
+                let mut weights = Activation::Softmax.compute(&scores);
+                for (i, masked) in fully_masked.iter().enumerate() {
+                    if *masked {
+                        weights.row_mut(i).fill(0.0);
+                    }
+                }
+
+                context.slice_mut(s![rows.clone(), columns]).assign(&weights.dot(&value));
+                attention_weights.push(weights);
+            }
+        }
+
+        let output = context.dot(&self.output_weights) + &self.output_bias;
+        let activities = to_sequences(&output, input.rows());
+
+        (AttentionCache { queries, keys, values, attention_weights, context }, activities)
+    }
+
+    // Additive mask (0 or MASKED_SCORE) applied on the attention scores of one sequence
+    fn mask_for(&self, padding: Option<ArrayView1<bool>>) -> Array2<f64> {
+        let mut mask = Array2::<f64>::zeros((self.sequence_length, self.sequence_length));
+        for i in 0..self.sequence_length {
+            for j in 0..self.sequence_length {
+                let is_future = self.mask.causal && j > i;
+                let is_padding = padding.as_ref().is_some_and(|padding| padding[j]);
+                if is_future || is_padding {
+                    mask[[i, j]] = MASKED_SCORE;
+                }
+            }
+        }
+        mask
+    }
+
+    pub fn parameters(&self) -> Vec<&Array2<f64>> {
+        vec![
+            &self.query_weights, &self.key_weights, &self.value_weights, &self.output_weights,
+            &self.query_bias, &self.key_bias, &self.value_bias, &self.output_bias,
+        ]
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f64>> {
+        vec![
+            &mut self.query_weights, &mut self.key_weights, &mut self.value_weights, &mut self.output_weights,
+            &mut self.query_bias, &mut self.key_bias, &mut self.value_bias, &mut self.output_bias,
+        ]
+    }
+
+    pub fn attention_weights(&self, sample: usize, head: usize) -> &Array2<f64> {
+        &self.cache.attention_weights[sample * self.heads + head]
+    }
+
+    pub fn compute_gradients(&self, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        self.backward(&self.cache, input, delta)
+    }
+
+    // Same as compute_gradients(), from the intermediate values of forward()
+    pub fn backward(&self, cache: &AttentionCache, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        let tokens = to_tokens(input, self.model_size);
+        let delta = to_tokens(delta, self.model_size);
+
+        let diff_output_weights = cache.context.t().dot(&delta);
+        let diff_output_bias = sum_rows(&delta);
+        let delta_context = delta.dot(&self.output_weights.t());
+
+        let length = self.sequence_length;
+        let head_size = self.head_size();
+        let scale = 1.0 / (head_size as f64).sqrt();
+
+        let mut delta_queries = Array2::<f64>::zeros(tokens.dim());
+        let mut delta_keys = Array2::<f64>::zeros(tokens.dim());
+        let mut delta_values = Array2::<f64>::zeros(tokens.dim());
+
+        for sample in 0..input.rows() {
+            let rows = sample * length..(sample + 1) * length;
+
+            for head in 0..self.heads {
+                let columns = head * head_size..(head + 1) * head_size;
+                let weights = &cache.attention_weights[sample * self.heads + head];
+                let query = cache.queries.slice(s![rows.clone(), columns.clone()]);
+                let key = cache.keys.slice(s![rows.clone(), columns.clone()]);
+                let value = cache.values.slice(s![rows.clone(), columns.clone()]);
+                let head_delta = delta_context.slice(s![rows.clone(), columns.clone()]);
+
+                let delta_weights = head_delta.dot(&value.t());
+                let delta_value = weights.t().dot(&head_delta);
+
+                // Softmax derivative applied row by row
+                let row_sums = (&delta_weights * weights).sum_axis(Axis(1)).insert_axis(Axis(1));
+                let delta_scores = weights * &(&delta_weights - &row_sums) * scale;
+
+                delta_queries.slice_mut(s![rows.clone(), columns.clone()]).assign(&delta_scores.dot(&key));
+                delta_keys.slice_mut(s![rows.clone(), columns.clone()]).assign(&delta_scores.t().dot(&query));
+                delta_values.slice_mut(s![rows.clone(), columns]).assign(&delta_value);
+            }
+        }
+
+        let previous_delta = delta_queries.dot(&self.query_weights.t())
+            + delta_keys.dot(&self.key_weights.t())
+            + delta_values.dot(&self.value_weights.t());
+
+        let gradients = vec![
+            tokens.t().dot(&delta_queries),
+            tokens.t().dot(&delta_keys),
+            tokens.t().dot(&delta_values),
+            diff_output_weights,
+            sum_rows(&delta_queries),
+            sum_rows(&delta_keys),
+            sum_rows(&delta_values),
+            diff_output_bias,
+        ];
+
+        (to_sequences(&previous_delta, input.rows()), gradients)
+    }
+}
+
+
+// Normalize every token over its features, then scale and shift
+pub struct LayerNorm {
+    pub gain: Array2<f64>,
+    pub shift: Array2<f64>,
+    epsilon: f64,
+}
+
+pub struct NormCache {
+    normalized: Array2<f64>,
+    deviation: Array2<f64>,
+}
+
+impl NormCache {
+    fn empty() -> Self {
+        Self {
+            normalized: Array2::<f64>::zeros((1, 1)),
+            deviation: Array2::<f64>::zeros((1, 1)),
+        }
+    }
+}
+
+impl LayerNorm {
+    pub fn new(model_size: usize) -> Self {
+        let mut gain = Array2::<f64>::zeros((1, model_size));
+        gain.fill(1.0);
+
+        Self {
+            gain,
+            shift: Array2::<f64>::zeros((1, model_size)),
+            epsilon: 1e-5,
+        }
+    }
+
+    // Input and output are (tokens, model_size) matrices
+    pub fn forward(&self, tokens: &Array2<f64>) -> (NormCache, Array2<f64>) {
+        let size = tokens.cols() as f64;
+        let mean = (tokens.sum_axis(Axis(1)) / size).insert_axis(Axis(1));
+        let centered = tokens - &mean;
+        let variance = (centered.map(|v| v * v).sum_axis(Axis(1)) / size).insert_axis(Axis(1));
+
+        let deviation = variance.map(|v| (v + self.epsilon).sqrt());
+        let normalized = centered / &deviation;
+        let result = &normalized * &self.gain + &self.shift;
+        (NormCache { normalized, deviation }, result)
+    }
+
+    pub fn predict(&self, tokens: &Array2<f64>) -> Array2<f64> {
+        self.forward(tokens).1
+    }
+
+    pub fn backward(&self, cache: &NormCache, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        let size = delta.cols() as f64;
+        let delta_normalized = delta * &self.gain;
+
+        let mean_delta = (delta_normalized.sum_axis(Axis(1)) / size).insert_axis(Axis(1));
+        let mean_delta_normalized = ((&delta_normalized * &cache.normalized).sum_axis(Axis(1)) / size).insert_axis(Axis(1));
+        let previous_delta = (delta_normalized - &mean_delta - &(&cache.normalized * &mean_delta_normalized)) / &cache.deviation;
+
+        (previous_delta, vec![sum_rows(&(delta * &cache.normalized)), sum_rows(delta)])
+    }
+}
+
+
+// Post-norm encoder block :
+// x = LayerNorm(x + MultiHeadAttention(x))
+// x = LayerNorm(x + FeedForward(x))
+// The feed forward network is applied on every token separately
+pub struct TransformerEncoder {
+    pub attention: MultiHeadAttention,
+    pub attention_norm: LayerNorm,
+    pub feed_forward_hidden: Dense,
+    pub feed_forward_output: Dense,
+    pub feed_forward_norm: LayerNorm,
+    cache: EncoderCache,
+    pub activities: Array2<f64>,
+}
+
+// Intermediate values of a forward pass, needed by backward()
+pub struct EncoderCache {
+    attention: AttentionCache,
+    attention_norm: NormCache,
+    normalized_attention: Array2<f64>,
+    // Outputs of the feed forward layers before their activation, then activities of the hidden one
+    hidden_output: Array2<f64>,
+    hidden: Array2<f64>,
+    feed_forward_output: Array2<f64>,
+    feed_forward_norm: NormCache,
+}
+
+impl TransformerEncoder {
+    pub fn new(sequence_length: usize, model_size: usize, heads: usize, feed_forward_size: usize, mask: AttentionMask) -> Self {
+        // Reported as Error::ZeroSize by NeuralNetworkBuilder::try_build()
+        assert!(feed_forward_size > 0, "Feed forward network needs at least one neuron");
+
+        Self {
+            attention: MultiHeadAttention::new(sequence_length, model_size, heads, mask),
+            attention_norm: LayerNorm::new(model_size),
+            feed_forward_hidden: Dense::new(feed_forward_size, model_size, Activation::ReLU),
+            feed_forward_output: Dense::new(model_size, feed_forward_size, Activation::Identity),
+            feed_forward_norm: LayerNorm::new(model_size),
+            cache: EncoderCache {
+                attention: AttentionCache::empty(),
+                attention_norm: NormCache::empty(),
+                normalized_attention: Array2::<f64>::zeros((1, 1)),
+                hidden_output: Array2::<f64>::zeros((1, 1)),
+                hidden: Array2::<f64>::zeros((1, 1)),
+                feed_forward_output: Array2::<f64>::zeros((1, 1)),
+                feed_forward_norm: NormCache::empty(),
+            },
+            activities: Array2::<f64>::zeros((1, 1)),
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<&Array2<f64>> {
+        let mut parameters = self.attention.parameters();
+        parameters.extend(vec![
+            &self.attention_norm.gain, &self.attention_norm.shift,
+            &self.feed_forward_hidden.weights, &self.feed_forward_hidden.bias,
+            &self.feed_forward_output.weights, &self.feed_forward_output.bias,
+            &self.feed_forward_norm.gain, &self.feed_forward_norm.shift,
+        ]);
+        parameters
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f64>> {
+        let mut parameters = self.attention.parameters_mut();
+        parameters.extend(vec![
+            &mut self.attention_norm.gain, &mut self.attention_norm.shift,
+            &mut self.feed_forward_hidden.weights, &mut self.feed_forward_hidden.bias,
+            &mut self.feed_forward_output.weights, &mut self.feed_forward_output.bias,
+            &mut self.feed_forward_norm.gain, &mut self.feed_forward_norm.shift,
+        ]);
+        parameters
+    }
+
+    pub fn padding(&self, input: &Array2<f64>, previous: Option<Array2<bool>>) -> Option<Array2<bool>> {
+        self.attention.padding(input, previous)
+    }
+
+    pub fn calculate_activities(&mut self, input: &Array2<f64>, padding: Option<&Array2<bool>>) {
+        let (cache, activities) = self.forward(input, padding);
+        self.cache = cache;
+        self.activities = activities;
+    }
+
+    pub fn predict(&self, input: &Array2<f64>, padding: Option<&Array2<bool>>) -> Array2<f64> {
+        self.forward(input, padding).1
+    }
+
+    // Same as calculate_activities(), returning the intermediate values instead of keeping them
+    pub fn forward(&self, input: &Array2<f64>, padding: Option<&Array2<bool>>) -> (EncoderCache, Array2<f64>) {
+        let model_size = self.attention.model_size();
+
+        let (attention, attention_activities) = self.attention.forward(input, padding);
+        let residual = to_tokens(&(input + &attention_activities), model_size);
+        let (attention_norm, normalized_attention) = self.attention_norm.forward(&residual);
+
+        let (hidden_output, hidden) = self.feed_forward_hidden.forward(&normalized_attention);
+        let (feed_forward_output, feed_forward) = self.feed_forward_output.forward(&hidden);
+        let residual = &normalized_attention + &feed_forward;
+        let (feed_forward_norm, output) = self.feed_forward_norm.forward(&residual);
+
+        let cache = EncoderCache { attention, attention_norm, normalized_attention, hidden_output, hidden, feed_forward_output, feed_forward_norm };
+        (cache, to_sequences(&output, input.rows()))
+    }
+
+    // Attention weights of the last calculate_activities(), see MultiHeadAttention::attention_weights()
+    pub fn attention_weights(&self, sample: usize, head: usize) -> &Array2<f64> {
+        &self.cache.attention.attention_weights[sample * self.attention.heads() + head]
+    }
+
+    pub fn compute_gradients(&self, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        self.backward(&self.cache, input, delta)
+    }
+
+    // Same as compute_gradients(), from the intermediate values of forward()
+    pub fn backward(&self, cache: &EncoderCache, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        let model_size = self.attention.model_size();
+
+        let (delta, feed_forward_norm_gradients) = self.feed_forward_norm.backward(&cache.feed_forward_norm, &to_tokens(delta, model_size));
+        let (feed_forward_delta, feed_forward_output_gradients) = self.feed_forward_output.backward(&cache.feed_forward_output, &cache.hidden, &delta);
+        let (feed_forward_delta, feed_forward_hidden_gradients) = self.feed_forward_hidden.backward(&cache.hidden_output, &cache.normalized_attention, &feed_forward_delta);
+
+        let (delta, attention_norm_gradients) = self.attention_norm.backward(&cache.attention_norm, &(delta + feed_forward_delta));
+        let delta = to_sequences(&delta, input.rows());
+        let (attention_delta, mut gradients) = self.attention.backward(&cache.attention, input, &delta);
+
+        // Same order as parameters()
+        gradients.extend(attention_norm_gradients);
+        gradients.extend(feed_forward_hidden_gradients);
+        gradients.extend(feed_forward_output_gradients);
+        gradients.extend(feed_forward_norm_gradients);
+
+        (delta + attention_delta, gradients)
+    }
+}
+
+
+// Tokens with only zero features, (samples, sequence_length)
+pub fn find_padding(input: &Array2<f64>, sequence_length: usize) -> Array2<bool> {
+    let model_size = input.cols() / sequence_length;
+    Array2::from_shape_fn((input.rows(), sequence_length), |(sample, token)| {
+        input.slice(s![sample, token * model_size..(token + 1) * model_size]).iter().all(|v| *v == 0.0)
+    })
+}
+
+// (samples, sequence_length * model_size) => (samples * sequence_length, model_size)
+fn to_tokens(sequences: &Array2<f64>, model_size: usize) -> Array2<f64> {
+    let rows = sequences.rows() * sequences.cols() / model_size;
+    Array2::from_shape_vec((rows, model_size), sequences.iter().cloned().collect()).unwrap()
+}
+
+// (samples * sequence_length, model_size) => (samples, sequence_length * model_size)
+fn to_sequences(tokens: &Array2<f64>, samples: usize) -> Array2<f64> {
+    let columns = tokens.rows() * tokens.cols() / samples;
+    Array2::from_shape_vec((samples, columns), tokens.iter().cloned().collect()).unwrap()
+}
+
+fn sum_rows(array: &Array2<f64>) -> Array2<f64> {
+    array.sum_axis(Axis(0)).insert_axis(Axis(0))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use layer::Layer;
+    use super::*;
+
+    #[test]
+    fn attention_weights_sum_to_one() {
+        let mut attention = MultiHeadAttention::new(3, 4, 2, AttentionMask::default());
+        attention.calculate_activities(&arr2(&[[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0, 1.1, 1.2]]), None);
+
+        for head in 0..2 {
+            for row in attention.attention_weights(0, head).genrows() {
+                assert!((row.scalar_sum() - 1.0).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn causal_mask_ignores_future_tokens() {
+        let mask = AttentionMask { causal: true, padding: false };
+        let mut attention = MultiHeadAttention::new(3, 2, 1, mask);
+
+        attention.calculate_activities(&arr2(&[[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]]), None);
+        let first = attention.activities.clone();
+        attention.calculate_activities(&arr2(&[[1.0, 2.0, 3.0, 4.0, -5.0, 0.5]]), None);
+        let second = attention.activities.clone();
+
+        // The first two tokens never see the last one
+        for j in 0..4 {
+            assert!((first[[0, j]] - second[[0, j]]).abs() < 1e-12);
+        }
+        assert_eq!(attention.attention_weights(0, 0)[[0, 1]], 0.0);
+    }
+
+    #[test]
+    fn causal_mask_holds_with_leading_padding() {
+        let mask = AttentionMask { causal: true, padding: true };
+        let mut attention = MultiHeadAttention::new(3, 2, 1, mask);
+
+        attention.calculate_activities(&arr2(&[[0.0, 0.0, 3.0, 4.0, 5.0, 6.0]]), None);
+        let first = attention.activities.clone();
+        // The first query can only see itself, a padding token
+        assert!(attention.attention_weights(0, 0).row(0).iter().all(|weight| *weight == 0.0));
+        attention.calculate_activities(&arr2(&[[0.0, 0.0, 3.0, 4.0, -5.0, 0.5]]), None);
+        let second = attention.activities.clone();
+
+        for j in 0..4 {
+            assert!((first[[0, j]] - second[[0, j]]).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn padding_mask_ignores_zero_tokens() {
+        let mask = AttentionMask { causal: false, padding: true };
+        let mut attention = MultiHeadAttention::new(3, 2, 1, mask);
+        attention.calculate_activities(&arr2(&[[1.0, 2.0, 0.0, 0.0, 5.0, 6.0]]), None);
+
+        for i in 0..3 {
+            assert_eq!(attention.attention_weights(0, 0)[[i, 1]], 0.0);
+        }
+    }
+
+    #[test]
+    fn padding_is_kept_by_stacked_encoders() {
+        let mask = AttentionMask { causal: false, padding: true };
+        let mut network = NeuralNetworkBuilder::new(6)
+            .transformer_encoder(3, 1, 4, mask)
+            .transformer_encoder(3, 1, 4, mask)
+            .build();
+        let input = arr2(&[[1.0, 2.0, 0.0, 0.0, 5.0, 6.0]]);
+        let output = network.feed_forward(&input);
+
+        // The first encoder outputs non zero features for the padding token
+        let first = match network.layers()[0] {
+            Layer::TransformerEncoder(ref layer) => layer,
+            _ => panic!("Wrong layer"),
+        };
+        assert!(first.activities.slice(s![0, 2..4]).iter().any(|v| *v != 0.0));
+
+        match network.layers()[1] {
+            Layer::TransformerEncoder(ref layer) => {
+                for i in 0..3 {
+                    assert_eq!(layer.attention_weights(0, 0)[[i, 1]], 0.0);
+                }
+            },
+            _ => panic!("Wrong layer"),
+        }
+        assert!(network.predict(&input).all_close(&output, 1e-12));
+    }
+
+    #[test]
+    fn layer_norm_output_is_normalized() {
+        let norm = LayerNorm::new(4);
+        let result = norm.predict(&arr2(&[[1.0, 2.0, 3.0, 4.0], [-3.0, 0.0, 10.0, 1.0]]));
+
+        for row in result.genrows() {
+            assert!(row.scalar_sum().abs() < 1e-9);
+            assert!((row.map(|v| v * v).scalar_sum() / 4.0 - 1.0).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,236 @@
+// Random image augmentation, applied to every batch during training
+//
+// Every row is a height×width image. Augmentations are applied in the order they were added,
+// each row getting its own random parameters. The same seed always gives the same images.
+// Geometric augmentations sample the source image with bilinear interpolation, pixels coming from outside are 0.
+
+use ndarray::{Array2, ArrayView1};
+use rand::Rng;
+use rand::distributions::Normal;
+use rand::prng::XorShiftRng;
+
+use data::seeded_rng;
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum Augmentation {
+    // Maximum shift in pixels, along both axes
+    Shift(usize),
+    // Maximum angle in degrees, clockwise or not
+    Rotation(f64),
+    // Zoom factor between the minimum and the maximum
+    Scale(f64, f64),
+    // Displacement of every pixel, random then smoothed by a gaussian of deviation sigma and multiplied by alpha
+    Elastic { alpha: f64, sigma: f64 },
+    // Probability of mirroring the image
+    HorizontalFlip(f64),
+    // Standard deviation of the noise added to every pixel
+    GaussianNoise(f64),
+    // Side of a square set to 0, its center is anywhere in the image
+    Cutout(usize),
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Augmenter {
+    pub height: usize,
+    pub width: usize,
+    pub augmentations: Vec<Augmentation>,
+    rng: XorShiftRng,
+}
+
+impl Augmenter {
+    pub fn new(height: usize, width: usize, seed: u64) -> Self {
+        Self {
+            height,
+            width,
+            augmentations: Vec::new(),
+            rng: seeded_rng(seed),
+        }
+    }
+
+    pub fn then(mut self, augmentation: Augmentation) -> Self {
+        self.augmentations.push(augmentation);
+        self
+    }
+
+    pub fn augment(&mut self, batch: &Array2<f64>) -> Array2<f64> {
+        assert_eq!(batch.cols(), self.height * self.width, "Rows are not {}×{} images", self.height, self.width);
+
+        let mut augmented = Array2::<f64>::zeros(batch.dim());
+        for (i, row) in batch.genrows().into_iter().enumerate() {
+            let image = self.augment_image(row);
+            augmented.row_mut(i).assign(&image.into_shape(self.height * self.width).unwrap());
+        }
+        augmented
+    }
+
+    fn augment_image(&mut self, row: ArrayView1<f64>) -> Array2<f64> {
+        let mut image = row.to_owned().into_shape((self.height, self.width)).unwrap();
+        let center = ((self.height - 1) as f64 / 2.0, (self.width - 1) as f64 / 2.0);
+
+        for augmentation in self.augmentations.clone() {
+            image = match augmentation {
+                Augmentation::Shift(maximum) => {
+                    let maximum = maximum as i64;
+                    let dy = self.rng.gen_range(-maximum, maximum + 1) as f64;
+                    let dx = self.rng.gen_range(-maximum, maximum + 1) as f64;
+                    resample(&image, |y, x| (y - dy, x - dx))
+                },
+                Augmentation::Rotation(maximum) => {
+                    let angle = uniform(&mut self.rng, -maximum, maximum).to_radians();
+                    let (sin, cos) = angle.sin_cos();
+                    resample(&image, |y, x| {
+                        let (y, x) = (y - center.0, x - center.1);
+                        (cos * y - sin * x + center.0, sin * y + cos * x + center.1)
+                    })
+                },
+                Augmentation::Scale(minimum, maximum) => {
+                    let factor = uniform(&mut self.rng, minimum, maximum);
+                    resample(&image, |y, x| ((y - center.0) / factor + center.0, (x - center.1) / factor + center.1))
+                },
+                Augmentation::Elastic { alpha, sigma } => {
+                    let dy = self.displacement_field(alpha, sigma);
+                    let dx = self.displacement_field(alpha, sigma);
+                    resample(&image, |y, x| (y + dy[[y as usize, x as usize]], x + dx[[y as usize, x as usize]]))
+                },
+                Augmentation::HorizontalFlip(probability) => {
+                    if self.rng.gen::<f64>() < probability {
+                        let width = self.width;
+                        Array2::from_shape_fn(image.dim(), |(y, x)| image[[y, width - 1 - x]])
+                    } else {
+                        image
+                    }
+                },
+                Augmentation::GaussianNoise(deviation) => {
+                    let normal = Normal::new(0.0, deviation);
+                    let rng = &mut self.rng;
+                    image.mapv(|v| v + rng.sample(normal))
+                },
+                Augmentation::Cutout(size) => {
+                    let y = self.rng.gen_range(0, self.height) as i64 - size as i64 / 2;
+                    let x = self.rng.gen_range(0, self.width) as i64 - size as i64 / 2;
+                    for i in y.max(0)..(y + size as i64).min(self.height as i64) {
+                        for j in x.max(0)..(x + size as i64).min(self.width as i64) {
+                            image[[i as usize, j as usize]] = 0.0;
+                        }
+                    }
+                    image
+                },
+            };
+        }
+        image
+    }
+
+    fn displacement_field(&mut self, alpha: f64, sigma: f64) -> Array2<f64> {
+        let rng = &mut self.rng;
+        let field = Array2::from_shape_fn((self.height, self.width), |_| uniform(rng, -1.0, 1.0));
+        gaussian_blur(&field, sigma) * alpha
+    }
+}
+
+
+fn uniform(rng: &mut XorShiftRng, minimum: f64, maximum: f64) -> f64 {
+    if minimum < maximum { rng.gen_range(minimum, maximum) } else { minimum }
+}
+
+// Value of every pixel is read at the source position given by the mapping
+fn resample<F: Fn(f64, f64) -> (f64, f64)>(image: &Array2<f64>, source: F) -> Array2<f64> {
+    Array2::from_shape_fn(image.dim(), |(y, x)| {
+        let (y, x) = source(y as f64, x as f64);
+        bilinear(image, y, x)
+    })
+}
+
+fn bilinear(image: &Array2<f64>, y: f64, x: f64) -> f64 {
+    let (y0, x0) = (y.floor(), x.floor());
+    let (fy, fx) = (y - y0, x - x0);
+    let pixel = |y: f64, x: f64| {
+        if y < 0.0 || x < 0.0 || y >= image.rows() as f64 || x >= image.cols() as f64 {
+            0.0
+        } else {
+            image[[y as usize, x as usize]]
+        }
+    };
+
+    pixel(y0, x0) * (1.0 - fy) * (1.0 - fx)
+        + pixel(y0, x0 + 1.0) * (1.0 - fy) * fx
+        + pixel(y0 + 1.0, x0) * fy * (1.0 - fx)
+        + pixel(y0 + 1.0, x0 + 1.0) * fy * fx
+}
+
+// Separable convolution, the kernel is cut at three deviations and renormalized at the borders
+fn gaussian_blur(image: &Array2<f64>, sigma: f64) -> Array2<f64> {
+    if sigma <= 0.0 {
+        return image.clone();
+    }
+    let radius = (3.0 * sigma).ceil() as i64;
+    let kernel: Vec<f64> = (-radius..=radius).map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp()).collect();
+
+    let (height, width) = (image.rows() as i64, image.cols() as i64);
+    let convolve = |image: &Array2<f64>, vertical: bool| {
+        Array2::from_shape_fn(image.dim(), |(y, x)| {
+            let (mut total, mut weights) = (0.0, 0.0);
+            for (k, weight) in kernel.iter().enumerate() {
+                let d = k as i64 - radius;
+                let (sy, sx) = if vertical { (y as i64 + d, x as i64) } else { (y as i64, x as i64 + d) };
+                if sy >= 0 && sy < height && sx >= 0 && sx < width {
+                    total += weight * image[[sy as usize, sx as usize]];
+                    weights += weight;
+                }
+            }
+            total / weights
+        })
+    };
+    convolve(&convolve(image, true), false)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use super::*;
+
+    fn batch() -> Array2<f64> {
+        Array2::from_shape_fn((3, 20), |(i, j)| ((i * 20 + j) % 7) as f64 / 7.0)
+    }
+
+    #[test]
+    fn same_seed_same_images() {
+        let augmenter = || Augmenter::new(4, 5, 9)
+            .then(Augmentation::Shift(1))
+            .then(Augmentation::Rotation(15.0))
+            .then(Augmentation::Scale(0.9, 1.1))
+            .then(Augmentation::Elastic { alpha: 0.5, sigma: 1.0 })
+            .then(Augmentation::HorizontalFlip(0.5))
+            .then(Augmentation::GaussianNoise(0.1))
+            .then(Augmentation::Cutout(2));
+
+        let (mut first, mut second) = (augmenter(), augmenter());
+        let augmented = first.augment(&batch());
+        assert_eq!(augmented, second.augment(&batch()));
+        assert_eq!(augmented.dim(), (3, 20));
+        assert!(augmented != batch());
+        // The random generator moves on, the next batch is augmented differently
+        assert!(first.augment(&batch()) != augmented);
+    }
+
+    #[test]
+    fn deterministic_augmentations() {
+        let image = arr2(&[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        let row = image.clone().into_shape((1, 6)).unwrap();
+
+        let mut identity = Augmenter::new(2, 3, 0)
+            .then(Augmentation::Shift(0))
+            .then(Augmentation::Rotation(0.0))
+            .then(Augmentation::Scale(1.0, 1.0))
+            .then(Augmentation::HorizontalFlip(0.0));
+        assert!(identity.augment(&row).all_close(&row, 1e-12));
+
+        let mut flip = Augmenter::new(2, 3, 0).then(Augmentation::HorizontalFlip(1.0));
+        assert_eq!(flip.augment(&row), arr2(&[[3.0, 2.0, 1.0, 6.0, 5.0, 4.0]]));
+
+        let mut cutout = Augmenter::new(2, 3, 0).then(Augmentation::Cutout(1));
+        assert_eq!(cutout.augment(&row).iter().filter(|v| **v == 0.0).count(), 1);
+    }
+}
@@ -1,12 +1,17 @@
-
 use network::NeuralNetwork;
-use layer::Layer;
+use error::{Error, Result};
+use layer::{Layer, Dense, Initializer, Regularization};
 use activation::Activation;
+use attention::{AttentionMask, MultiHeadAttention, TransformerEncoder};
 
 
 pub struct NeuralNetworkBuilder {
     last_layer_outputs: usize,
     layers: Vec<Layer>,
+    initializer: Initializer,
+    regularization: Regularization,
+    // First invalid layer, returned by try_build()
+    error: Option<Error>,
 }
 
 impl NeuralNetworkBuilder {
@@ -14,19 +19,93 @@ impl NeuralNetworkBuilder {
         Self {
             last_layer_outputs: inputs,
             layers: Vec::new(),
+            initializer: Initializer::default(),
+            regularization: Regularization::default(),
+            error: if inputs == 0 { Some(Error::NoInputs) } else { None },
         }
     }
 
     pub fn layer(mut self, neurons: usize, activation_function: Activation) -> Self {
-        self.layers.push(Layer::new(neurons, self.last_layer_outputs, activation_function));
+        if neurons == 0 {
+            let layer = self.layers.len();
+            return self.invalid(Error::ZeroSize { layer });
+        }
+        let mut layer = Dense::initialized(neurons, self.last_layer_outputs, activation_function, self.initializer);
+        layer.regularization = self.regularization;
+        self.layers.push(Layer::Dense(layer));
         self.last_layer_outputs = neurons;
         self
     }
 
-    pub fn build(mut self) -> NeuralNetwork {
-        assert!(!self.layers.is_empty(), "No layers defined");
-        NeuralNetwork::new(self.layers)
+    // Used by the dense layers added after it
+    pub fn initializer(mut self, initializer: Initializer) -> Self {
+        self.initializer = initializer;
+        self
     }
 
-}
+    // Used by the dense layers added after it
+    pub fn regularization(mut self, regularization: Regularization) -> Self {
+        self.regularization = regularization;
+        self
+    }
+
+    // Previous outputs are read as sequences of sequence_length tokens, output has the same size
+    pub fn attention(mut self, sequence_length: usize, heads: usize, mask: AttentionMask) -> Self {
+        let model_size = match self.model_size(sequence_length, heads) {
+            Ok(model_size) => model_size,
+            Err(error) => return self.invalid(error),
+        };
+        self.layers.push(Layer::MultiHeadAttention(MultiHeadAttention::new(sequence_length, model_size, heads, mask)));
+        self
+    }
+
+    pub fn transformer_encoder(mut self, sequence_length: usize, heads: usize, feed_forward_size: usize, mask: AttentionMask) -> Self {
+        let model_size = match self.model_size(sequence_length, heads) {
+            Ok(model_size) => model_size,
+            Err(error) => return self.invalid(error),
+        };
+        if feed_forward_size == 0 {
+            let layer = self.layers.len();
+            return self.invalid(Error::ZeroSize { layer });
+        }
+        self.layers.push(Layer::TransformerEncoder(TransformerEncoder::new(sequence_length, model_size, heads, feed_forward_size, mask)));
+        self
+    }
+
+    // Size of a token of the previous outputs, split between the heads
+    fn model_size(&self, sequence_length: usize, heads: usize) -> Result<usize> {
+        let layer = self.layers.len();
+        let inputs = self.last_layer_outputs;
+        if sequence_length == 0 || !inputs.is_multiple_of(sequence_length) {
+            return Err(Error::SequenceLength { layer, inputs, sequence_length });
+        }
+        let model_size = inputs / sequence_length;
+        if heads == 0 || !model_size.is_multiple_of(heads) {
+            return Err(Error::Heads { layer, model_size, heads });
+        }
+        Ok(model_size)
+    }
+
+    // The layer is left out, the error is kept for try_build() unless an earlier layer was already invalid
+    fn invalid(mut self, error: Error) -> Self {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+        self
+    }
 
+    pub fn build(self) -> NeuralNetwork {
+        self.try_build().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_build(self) -> Result<NeuralNetwork> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.layers.is_empty() {
+            return Err(Error::NoLayers);
+        }
+        Ok(NeuralNetwork::new(self.layers))
+    }
+
+}
@@ -0,0 +1,181 @@
+// Classification helpers on top of predict()
+//
+// Like the metrics, a network with one output is a binary classifier : the output is the probability of class 1.
+
+use std::io;
+
+use ndarray::{Array2, ArrayView1, Axis, stack};
+
+use network::NeuralNetwork;
+use layer::Layer;
+use activation::Activation;
+
+
+pub struct Prediction {
+    pub class: usize,
+    pub label: Option<String>,
+    pub probability: f64,
+}
+
+impl NeuralNetwork {
+    // Probabilities of every class, the outputs are normalized with a softmax (a sigmoid for a single output)
+    // unless the last layer already gives probabilities. A single output gives the columns [1 - p, p].
+    pub fn predict_proba(&self, input: &Array2<f64>) -> Array2<f64> {
+        let output = self.predict(input);
+        let activation = match self.layers().last() {
+            Some(Layer::Dense(layer)) => Some(layer.activation_function),
+            _ => None,
+        };
+
+        if output.cols() == 1 {
+            let probability = match activation {
+                Some(Activation::Sigmoid) => output,
+                _ => Activation::Sigmoid.compute(&output),
+            };
+            let complement = probability.map(|p| 1.0 - p);
+            return stack(Axis(1), &[complement.view(), probability.view()]).unwrap();
+        }
+
+        match activation {
+            Some(Activation::Softmax) | Some(Activation::Sigmoid) => output,
+            Some(Activation::LogSoftmax) => output.map(|v| v.exp()),
+            _ => Activation::Softmax.compute(&output),
+        }
+    }
+
+    // Most probable class, so the classes always agree with predict_proba()
+    pub fn predict_classes(&self, input: &Array2<f64>) -> Vec<usize> {
+        self.predict_proba(input).genrows().into_iter().map(argmax).collect()
+    }
+
+    // Name of the predicted class of every row, requires labels to be set
+    pub fn predict_labels(&self, input: &Array2<f64>) -> io::Result<Vec<String>> {
+        let labels = match self.labels {
+            Some(ref labels) => labels,
+            None => return Err(invalid_input("No labels defined".to_owned())),
+        };
+        self.predict_classes(input).into_iter()
+            .map(|class| labels.get(class).cloned().ok_or_else(|| invalid_input(format!("No label for class {}, {} labels defined", class, labels.len()))))
+            .collect()
+    }
+
+    // The k most probable classes of every row, most probable first
+    pub fn predict_top_k(&self, input: &Array2<f64>, k: usize) -> Vec<Vec<Prediction>> {
+        let probabilities = self.predict_proba(input);
+        probabilities.genrows().into_iter().map(|row| {
+            top_k(row, k).into_iter().map(|class| Prediction {
+                class,
+                label: self.labels.as_ref().and_then(|labels| labels.get(class).cloned()),
+                probability: row[class],
+            }).collect()
+        }).collect()
+    }
+
+    // One label per output, or two labels for a single output
+    pub fn set_labels(&mut self, labels: &[&str]) -> io::Result<()> {
+        let classes = self.classes();
+        if labels.len() != classes {
+            return Err(invalid_input(format!("{} labels given for {} classes", labels.len(), classes)));
+        }
+        self.labels = Some(labels.iter().map(|label| label.to_string()).collect());
+        Ok(())
+    }
+
+    pub fn classes(&self) -> usize {
+        self.layers().last().map_or(0, |layer| layer.outputs().max(2))
+    }
+}
+
+// Index of the greatest value, the first one in case of equality
+pub fn argmax(row: ArrayView1<f64>) -> usize {
+    let mut index = 0;
+    for i in 1..row.len() {
+        if row[i] > row[index] {
+            index = i;
+        }
+    }
+    index
+}
+
+// Indexes of the k greatest values, greatest first
+pub fn top_k(row: ArrayView1<f64>, k: usize) -> Vec<usize> {
+    let mut indexes: Vec<usize> = (0..row.len()).collect();
+    indexes.sort_by(|a, b| row[*b].partial_cmp(&row[*a]).unwrap_or(::std::cmp::Ordering::Equal));
+    indexes.truncate(k);
+    indexes
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use super::*;
+
+    #[test]
+    fn classes_and_top_k() {
+        let mut network = NeuralNetworkBuilder::new(2)
+            .layer(3, Activation::Identity)
+            .build();
+        network.parameters_mut()[0][0].assign(&arr2(&[[1.0, 0.0, 0.5], [0.0, 1.0, 0.5]]));
+        network.parameters_mut()[0][1].fill(0.0);
+        assert!(network.predict_labels(&arr2(&[[2.0, 0.0]])).is_err());
+        assert!(network.set_labels(&["cat", "dog"]).is_err());
+        network.set_labels(&["cat", "dog", "bird"]).unwrap();
+
+        let input = arr2(&[[2.0, 0.0], [0.0, 3.0]]);
+        assert_eq!(network.predict_classes(&input), vec![0, 1]);
+        assert_eq!(network.predict_labels(&input).unwrap(), vec!["cat", "dog"]);
+
+        let probabilities = network.predict_proba(&input);
+        for row in probabilities.genrows() {
+            assert!((row.scalar_sum() - 1.0).abs() < 1e-12);
+        }
+
+        let top = network.predict_top_k(&input, 2);
+        assert_eq!(top[0].iter().map(|p| p.class).collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(top[1][1].label, Some("bird".to_owned()));
+        assert_eq!(top[1][0].probability, probabilities[[1, 1]]);
+    }
+
+    #[test]
+    fn single_output_is_binary() {
+        let mut network = NeuralNetworkBuilder::new(1)
+            .layer(1, Activation::Sigmoid)
+            .build();
+        network.parameters_mut()[0][0].fill(1.0);
+        network.parameters_mut()[0][1].fill(0.0);
+        assert!(network.set_labels(&["negative"]).is_err());
+        network.set_labels(&["negative", "positive"]).unwrap();
+
+        let input = arr2(&[[-2.0], [3.0]]);
+        assert_eq!(network.predict_classes(&input), vec![0, 1]);
+        assert_eq!(network.predict_labels(&input).unwrap(), vec!["negative", "positive"]);
+
+        let p = 1.0 / (1.0 + (-3.0f64).exp());
+        let probabilities = network.predict_proba(&input);
+        assert_eq!(probabilities.dim(), (2, 2));
+        assert!((probabilities[[1, 0]] - (1.0 - p)).abs() < 1e-12 && (probabilities[[1, 1]] - p).abs() < 1e-12);
+        assert!(probabilities[[0, 0]] > 0.5);
+
+        let top = network.predict_top_k(&input, 1);
+        assert_eq!(top[1][0].label, Some("positive".to_owned()));
+
+        // Logits are positive for class 1, even below 0.5
+        let mut network = NeuralNetworkBuilder::new(1)
+            .layer(1, Activation::Identity)
+            .build();
+        network.parameters_mut()[0][0].fill(1.0);
+        network.parameters_mut()[0][1].fill(0.0);
+        let input = arr2(&[[-0.3], [0.3], [2.0]]);
+        let probabilities = network.predict_proba(&input);
+        assert!(probabilities[[1, 1]] > 0.5);
+        let classes: Vec<usize> = probabilities.genrows().into_iter().map(argmax).collect();
+        assert_eq!(network.predict_classes(&input), classes);
+        assert_eq!(classes, vec![0, 1, 1]);
+    }
+}
@@ -0,0 +1,738 @@
+// Command line interface : train, evaluate, predict and inspect models without writing Rust
+//
+// Datasets are CSV files (--features and --target columns, see tabular::CsvLoader) or IDX files (--data for the
+// inputs, --targets for the labels). The column encoding of a CSV file is fitted when training and saved with
+// the model, so evaluate and predict encode their files exactly the same way.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use ndarray::Array2;
+use serde_json;
+
+use network::NeuralNetwork;
+use activation::Activation;
+use objective::Objective;
+use optimizer::Optimizer;
+use schedule::LearningRateSchedule;
+use training::{Trainer, TrainingConfig};
+use metrics::{Metric, Average, Evaluation};
+use attention::AttentionMask;
+use layer::Regularization;
+use config::{ModelConfig, LayerConfig};
+use classification::argmax;
+use preprocessing::{Scaler, StandardScaler, MinMaxScaler, RobustScaler};
+use split::train_validation_test_split;
+use tabular::{CsvLoader, ColumnType, ColumnEncoder, TabularEncoder};
+use idx::read_idx_matrix;
+
+
+pub const USAGE: &str = "Usage: neural_network <command> [--option value]...
+
+Commands:
+  train       Train a new network and save it
+  evaluate    Loss and metrics of a saved network on a dataset
+  predict     Outputs of a saved network, as CSV
+  inspect     Layers, labels and scalers of a saved network
+  experiment  Run a built-in experiment: mnist, softmax, addition, equal, sequence or basic
+
+Data options:
+  --data PATH             CSV file, or IDX file of the inputs
+  --format csv|idx        Guessed from the extension of --data when absent
+  --features A,B:one_hot  CSV feature columns, numeric unless :one_hot or :index is given
+  --target C:one_hot      CSV target columns, same format as --features
+  --delimiter CHAR        CSV delimiter, default ,
+  --headers true|false    First line of the CSV file holds the column names, default true
+  --targets PATH          IDX file of the targets
+  --classes N             IDX targets are class indices, turned into N one hot columns
+  --scale X               IDX inputs are multiplied by X, e.g. 0.00392 for pixels
+
+Model options:
+  --model PATH            Binary model file, JSON when the extension is .json
+
+Training options:
+  --config PATH           TOML or JSON model and training configuration, instead of the options below
+  --save-config PATH      Write the configuration of the run, TOML or JSON depending on the extension
+  --architecture SPEC     Inputs then layers, e.g. 784,512:relu,10:softmax
+                          or 32,attention(4,2),transformer_encoder(4,2,16),2:softmax
+  --objective NAME        e.g. cross_entropy, mean_squared_error, huber(1), quantile(0.9)
+  --optimizer NAME        sgd (default), momentum(0.9), rmsprop, adam
+  --learning-rate X       Default 0.01
+  --schedule NAME         constant (default), step_decay(decay,steps), exponential_decay(decay),
+                          cosine_annealing(minimum,steps), starting from the learning rate
+  --batch-size N          Default 32
+  --epochs N              Default 10
+  --seed N                Default 0
+  --shuffle true|false    Default true
+  --drop-last true|false  Default false
+  --validation X          Fraction of the rows held out for validation, default 0
+  --scale-inputs NAME     none (default), standard, min_max or robust, saved with the model
+  --scale-targets NAME    Same as --scale-inputs
+  --metrics A,B           e.g. accuracy,f1_macro,top_3_accuracy,rmse
+  --history PATH          JSON file of the loss and metrics of every epoch
+
+train --config can be combined with --validation, --scale-inputs, --scale-targets, --history and --save-config.
+evaluate takes --model, the data options, --objective and --metrics.
+predict takes --model, the data options and --output PATH, standard output when absent.
+inspect takes --model.";
+
+const DATA_OPTIONS: [&str; 9] = ["data", "format", "features", "target", "delimiter", "headers", "targets", "classes", "scale"];
+// Replaced by --config
+const TRAINING_OPTIONS: [&str; 11] = ["architecture", "objective", "optimizer", "learning-rate", "schedule", "batch-size", "epochs",
+    "seed", "shuffle", "drop-last", "metrics"];
+
+
+pub struct Arguments {
+    pub command: String,
+    pub positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Arguments {
+    // Options are given as --name value or --name=value
+    pub fn parse<I: IntoIterator<Item = String>>(arguments: I) -> io::Result<Self> {
+        let mut arguments = arguments.into_iter();
+        let command = arguments.next().ok_or_else(|| invalid_input("No command given".to_owned()))?;
+        let mut positional = Vec::new();
+        let mut options: Vec<(String, String)> = Vec::new();
+
+        while let Some(argument) = arguments.next() {
+            if !argument.starts_with("--") {
+                positional.push(argument);
+                continue;
+            }
+            let (name, value) = match argument.find('=') {
+                Some(i) => (argument[2..i].to_owned(), argument[i + 1..].to_owned()),
+                None => {
+                    let value = arguments.next().ok_or_else(|| invalid_input(format!("No value given to {}", argument)))?;
+                    (argument[2..].to_owned(), value)
+                },
+            };
+            if options.iter().any(|(given, _)| *given == name) {
+                return Err(invalid_input(format!("--{} is given twice", name)));
+            }
+            options.push((name, value));
+        }
+
+        Ok(Self { command, positional, options })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.options.iter().find(|(given, _)| given == name).map(|(_, value)| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> io::Result<&str> {
+        self.get(name).ok_or_else(|| invalid_input(format!("Missing --{}", name)))
+    }
+
+    fn value<T: FromStr>(&self, name: &str, default: T) -> io::Result<T> {
+        match self.get(name) {
+            Some(value) => value.parse().map_err(|_| invalid_input(format!("Invalid value {:?} for --{}", value, name))),
+            None => Ok(default),
+        }
+    }
+
+    fn check(&self, allowed: &[&str]) -> io::Result<()> {
+        for (name, _) in &self.options {
+            if !allowed.contains(&name.as_str()) {
+                return Err(invalid_input(format!("Unknown option --{} for {}", name, self.command)));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+pub fn run(arguments: &Arguments) -> io::Result<()> {
+    match arguments.command.as_str() {
+        "train" => train(arguments),
+        "evaluate" => evaluate(arguments),
+        "predict" => predict(arguments),
+        "inspect" => inspect(arguments),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        command => Err(invalid_input(format!("Unknown command {}", command))),
+    }
+}
+
+
+fn train(arguments: &Arguments) -> io::Result<()> {
+    let mut allowed = DATA_OPTIONS.to_vec();
+    allowed.extend_from_slice(&["model", "config", "save-config", "validation", "scale-inputs", "scale-targets", "history"]);
+    let model_config = match arguments.get("config") {
+        Some(path) => {
+            arguments.check(&allowed)?;
+            ModelConfig::load(path)?
+        },
+        None => {
+            allowed.extend_from_slice(&TRAINING_OPTIONS);
+            arguments.check(&allowed)?;
+            let training = training_config(arguments)?;
+            parse_architecture(arguments.required("architecture")?)?.training(training)
+        },
+    };
+    let config = model_config.training.clone().ok_or_else(|| invalid_input("The configuration has no training section".to_owned()))?;
+    if config.batch_size == 0 {
+        return Err(invalid_input("Batch size must be greater than zero".to_owned()));
+    }
+
+    let model = PathBuf::from(arguments.required("model")?);
+    let mut network = model_config.build()?;
+    let validation = arguments.value("validation", 0.0)?;
+    if !(0.0..1.0).contains(&validation) {
+        return Err(invalid_input(format!("Validation fraction {} is not between 0 and 1", validation)));
+    }
+
+    let (inputs, targets, encoder) = load_training_data(arguments)?;
+    check_columns(&network, &inputs, Some(&targets))?;
+    if let Some(ref encoder) = encoder {
+        // Classes of a single one hot target column name the outputs
+        if let [ref target] = encoder.targets[..] {
+            if let ColumnEncoder::OneHot { ref encoder, .. } = target.encoder {
+                network.labels = Some(encoder.categories.clone());
+            }
+        }
+    }
+    network.encoder = encoder;
+
+    let split = train_validation_test_split(&inputs, &targets, validation, 0.0, config.seed);
+    for warning in &split.warnings {
+        println!("Warning: {}", warning);
+    }
+    network.input_scaler = fit_scaler(arguments.get("scale-inputs"), &split.training.inputs)?;
+    network.target_scaler = fit_scaler(arguments.get("scale-targets"), &split.training.targets)?;
+    let training_inputs = network.preprocess(&split.training.inputs);
+    let training_targets = network.preprocess_targets(&split.training.targets);
+
+    let mut trainer = Trainer::new(config.clone());
+    trainer.fit(&mut network, &training_inputs, &training_targets)?;
+
+    let mut validation_evaluation = None;
+    if split.validation.inputs.rows() > 0 {
+        let evaluation = network.evaluate(&network.preprocess(&split.validation.inputs), &network.preprocess_targets(&split.validation.targets), &config.objective, &config.metrics)?;
+        println!("Validation set; {}", evaluation);
+        validation_evaluation = Some(evaluation);
+    }
+
+    save_model(&network, &model)?;
+    if let Some(path) = arguments.get("save-config") {
+        model_config.save(path)?;
+    }
+    if let Some(path) = arguments.get("history") {
+        let state = trainer.state();
+        let mut metrics = BTreeMap::new();
+        for (i, metric) in config.metrics.iter().enumerate() {
+            metrics.insert(metric.name(), state.metric_history.iter().map(|epoch| epoch[i]).collect());
+        }
+        let history = History {
+            loss: state.history.clone(),
+            metrics,
+            validation: validation_evaluation.as_ref().map(evaluation_values),
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &history)?;
+        writer.flush()?;
+    }
+
+    println!("Model saved to {}", model.display());
+    Ok(())
+}
+
+fn training_config(arguments: &Arguments) -> io::Result<TrainingConfig> {
+    let learning_rate = arguments.value("learning-rate", 0.01)?;
+    let mut config = TrainingConfig::new(parse_objective(arguments.required("objective")?)?, arguments.value("batch-size", 32)?, arguments.value("epochs", 10)?, learning_rate);
+    config.optimizer = parse_optimizer(arguments.get("optimizer").unwrap_or("sgd"))?;
+    config.schedule = parse_schedule(arguments.get("schedule").unwrap_or("constant"), learning_rate)?;
+    config.seed = arguments.value("seed", 0)?;
+    config.shuffle = arguments.value("shuffle", true)?;
+    config.drop_last = arguments.value("drop-last", false)?;
+    config.metrics = parse_metrics(arguments.get("metrics").unwrap_or(""))?;
+    Ok(config)
+}
+
+// Written by train --history
+#[derive(Serialize, Deserialize)]
+pub struct History {
+    // Mean loss of every epoch
+    pub loss: Vec<f64>,
+    // Value of every metric for every epoch, by metric name
+    pub metrics: BTreeMap<String, Vec<f64>>,
+    // Loss and metrics on the validation rows after training
+    pub validation: Option<BTreeMap<String, f64>>,
+}
+
+fn evaluation_values(evaluation: &Evaluation) -> BTreeMap<String, f64> {
+    let mut values: BTreeMap<String, f64> = evaluation.metrics.iter().map(|&(metric, value)| (metric.name(), value)).collect();
+    values.insert("loss".to_owned(), evaluation.loss);
+    values
+}
+
+
+fn evaluate(arguments: &Arguments) -> io::Result<()> {
+    let mut allowed = DATA_OPTIONS.to_vec();
+    allowed.extend_from_slice(&["model", "objective", "metrics"]);
+    arguments.check(&allowed)?;
+
+    let model = PathBuf::from(arguments.required("model")?);
+    let network = load_model(&model)?;
+    let objective = parse_objective(arguments.required("objective")?)?;
+    let metrics = parse_metrics(arguments.get("metrics").unwrap_or(""))?;
+
+    let inputs = load_inputs(arguments, &network)?;
+    let targets = load_targets(arguments, &network)?;
+    check_columns(&network, &inputs, Some(&targets))?;
+
+    println!("{}", network.evaluate(&network.preprocess(&inputs), &network.preprocess_targets(&targets), &objective, &metrics)?);
+    Ok(())
+}
+
+fn predict(arguments: &Arguments) -> io::Result<()> {
+    let mut allowed = DATA_OPTIONS.to_vec();
+    allowed.extend_from_slice(&["model", "output"]);
+    arguments.check(&allowed)?;
+
+    let model = PathBuf::from(arguments.required("model")?);
+    let network = load_model(&model)?;
+    let inputs = load_inputs(arguments, &network)?;
+    check_columns(&network, &inputs, None)?;
+
+    let outputs = network.postprocess(&network.predict(&network.preprocess(&inputs)));
+    match arguments.get("output") {
+        Some(path) => write_predictions(BufWriter::new(File::create(path)?), network.labels.as_ref(), &outputs),
+        None => write_predictions(io::stdout(), network.labels.as_ref(), &outputs),
+    }
+}
+
+// Header line then one line per row : the predicted label when the network has labels, then every output
+fn write_predictions<W: Write>(mut writer: W, labels: Option<&Vec<String>>, outputs: &Array2<f64>) -> io::Result<()> {
+    let names: Vec<String> = match labels {
+        Some(labels) => labels.clone(),
+        None => (0..outputs.cols()).map(|i| format!("output_{}", i)).collect(),
+    };
+    if labels.is_some() {
+        write!(writer, "label,")?;
+    }
+    writeln!(writer, "{}", names.join(","))?;
+
+    for row in outputs.genrows() {
+        if let Some(labels) = labels {
+            write!(writer, "{},", labels[argmax(row)])?;
+        }
+        let values: Vec<String> = row.iter().map(|value| value.to_string()).collect();
+        writeln!(writer, "{}", values.join(","))?;
+    }
+    writer.flush()
+}
+
+fn inspect(arguments: &Arguments) -> io::Result<()> {
+    arguments.check(&["model"])?;
+    let network = load_model(Path::new(arguments.required("model")?))?;
+
+    println!("{}", network.summary());
+    if let Some(ref labels) = network.labels {
+        println!("Labels: {}", labels.join(", "));
+    }
+    println!("Input scaler: {}", scaler_name(network.input_scaler.as_ref()));
+    println!("Target scaler: {}", scaler_name(network.target_scaler.as_ref()));
+    Ok(())
+}
+
+
+fn is_csv(arguments: &Arguments) -> io::Result<bool> {
+    match arguments.get("format") {
+        Some("csv") => Ok(true),
+        Some("idx") => Ok(false),
+        Some(format) => Err(invalid_input(format!("Unknown format {}", format))),
+        None => Ok(Path::new(arguments.required("data")?).extension().is_some_and(|extension| extension == "csv")),
+    }
+}
+
+// The fitted column encoding of a CSV file is returned to be saved with the model
+fn load_training_data(arguments: &Arguments) -> io::Result<(Array2<f64>, Array2<f64>, Option<TabularEncoder>)> {
+    let path = arguments.required("data")?;
+    if !is_csv(arguments)? {
+        return Ok((load_idx_inputs(arguments)?, load_idx_targets(arguments)?, None));
+    }
+
+    let mut loader = CsvLoader::new()
+        .delimiter(arguments.value("delimiter", ',')?)
+        .headers(arguments.value("headers", true)?);
+    for (column, column_type) in parse_columns(arguments.required("features")?)? {
+        loader = loader.feature(&column, column_type);
+    }
+    for (column, column_type) in parse_columns(arguments.required("target")?)? {
+        loader = loader.target(&column, column_type);
+    }
+    let data = loader.load(path)?;
+    Ok((data.inputs, data.targets, Some(data.encoder)))
+}
+
+fn load_inputs(arguments: &Arguments, network: &NeuralNetwork) -> io::Result<Array2<f64>> {
+    if is_csv(arguments)? {
+        column_encoder(network)?.transform(arguments.required("data")?)
+    } else {
+        load_idx_inputs(arguments)
+    }
+}
+
+fn load_targets(arguments: &Arguments, network: &NeuralNetwork) -> io::Result<Array2<f64>> {
+    if is_csv(arguments)? {
+        column_encoder(network)?.transform_targets(arguments.required("data")?)
+    } else {
+        load_idx_targets(arguments)
+    }
+}
+
+fn load_idx_inputs(arguments: &Arguments) -> io::Result<Array2<f64>> {
+    Ok(read_idx_matrix(arguments.required("data")?)? * arguments.value("scale", 1.0)?)
+}
+
+fn load_idx_targets(arguments: &Arguments) -> io::Result<Array2<f64>> {
+    let targets = read_idx_matrix(arguments.required("targets")?)?;
+    let classes = match arguments.get("classes") {
+        Some(_) => arguments.value("classes", 0)?,
+        None => return Ok(targets),
+    };
+
+    let mut one_hot = Array2::<f64>::zeros((targets.rows(), classes));
+    for (i, class) in targets.column(0).iter().enumerate() {
+        one_hot[[i, class_index(*class, classes)?]] = 1.0;
+    }
+    Ok(one_hot)
+}
+
+// Only whole numbers below classes, casting NaN or 1.5 would silently give another class
+fn class_index(class: f64, classes: usize) -> io::Result<usize> {
+    if !class.is_finite() || class.fract() != 0.0 || class < 0.0 || class >= classes as f64 {
+        return Err(invalid_input(format!("Class {} out of {} classes", class, classes)));
+    }
+    Ok(class as usize)
+}
+
+// Column encoding saved by train with the model
+fn column_encoder(network: &NeuralNetwork) -> io::Result<&TabularEncoder> {
+    network.encoder.as_ref().ok_or_else(|| invalid_input("The model was not trained on a CSV file, it has no column encoding".to_owned()))
+}
+
+fn check_columns(network: &NeuralNetwork, inputs: &Array2<f64>, targets: Option<&Array2<f64>>) -> io::Result<()> {
+    if inputs.cols() != network.input_size() {
+        return Err(invalid_input(format!("Data has {} input columns but the network expects {}", inputs.cols(), network.input_size())));
+    }
+    let outputs = network.layers().last().unwrap().outputs();
+    match targets {
+        Some(targets) if targets.cols() != outputs => {
+            Err(invalid_input(format!("Data has {} target columns but the network has {} outputs", targets.cols(), outputs)))
+        },
+        _ => Ok(()),
+    }
+}
+
+fn load_model(path: &Path) -> io::Result<NeuralNetwork> {
+    if is_json(path) { NeuralNetwork::load_json(path) } else { NeuralNetwork::load_binary(path) }
+}
+
+fn save_model(network: &NeuralNetwork, path: &Path) -> io::Result<()> {
+    if is_json(path) { network.save_json(path) } else { network.save_binary(path) }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "json")
+}
+
+fn fit_scaler(name: Option<&str>, data: &Array2<f64>) -> io::Result<Option<Scaler>> {
+    Ok(match name.unwrap_or("none") {
+        "none" => None,
+        "standard" => Some(Scaler::Standard(StandardScaler::fit(data))),
+        "min_max" => Some(Scaler::MinMax(MinMaxScaler::fit(data))),
+        "robust" => Some(Scaler::Robust(RobustScaler::fit(data))),
+        name => return Err(invalid_input(format!("Unknown scaler {}", name))),
+    })
+}
+
+fn scaler_name(scaler: Option<&Scaler>) -> &'static str {
+    match scaler {
+        None => "none",
+        Some(&Scaler::Standard(_)) => "standard",
+        Some(&Scaler::MinMax(_)) => "min_max",
+        Some(&Scaler::Robust(_)) => "robust",
+    }
+}
+
+
+// Inputs, then one item per layer : neurons:activation, attention(sequence_length, heads)
+// or transformer_encoder(sequence_length, heads, feed_forward_size)
+pub fn parse_architecture(text: &str) -> io::Result<ModelConfig> {
+    let items = split_top_level(text);
+    let inputs = items[0].trim().parse().map_err(|_| invalid_input(format!("Invalid amount of inputs {:?}", items[0])))?;
+
+    let mut config = ModelConfig::new(inputs);
+    for item in &items[1..] {
+        let item = item.trim();
+        let layer = match item.find(':') {
+            Some(i) => LayerConfig::Dense {
+                neurons: item[..i].parse().map_err(|_| invalid_input(format!("Invalid amount of neurons in {:?}", item)))?,
+                activation: parse_activation(&item[i + 1..])?,
+                initializer: None,
+                regularization: Regularization::default(),
+            },
+            None => {
+                let (name, values) = parse_call(item)?;
+                match (name.as_str(), &values[..]) {
+                    ("attention", &[sequence_length, heads]) => LayerConfig::Attention {
+                        sequence_length: sequence_length as usize,
+                        heads: heads as usize,
+                        mask: AttentionMask::default(),
+                    },
+                    ("transformer_encoder", &[sequence_length, heads, feed_forward_size]) => LayerConfig::TransformerEncoder {
+                        sequence_length: sequence_length as usize,
+                        heads: heads as usize,
+                        feed_forward_size: feed_forward_size as usize,
+                        mask: AttentionMask::default(),
+                    },
+                    _ => return Err(invalid_input(format!("Unknown layer {:?}", item))),
+                }
+            },
+        };
+        config = config.layer(layer);
+    }
+
+    // Sizes are checked when building
+    config.build()?;
+    Ok(config)
+}
+
+// Inverse of Activation::name()
+pub fn parse_activation(text: &str) -> io::Result<Activation> {
+    let (name, values) = parse_call(text)?;
+    Ok(match (name.as_str(), &values[..]) {
+        ("identity", &[]) => Activation::Identity,
+        ("binary", &[threshold]) => Activation::Binary(threshold),
+        ("sigmoid", &[]) => Activation::Sigmoid,
+        ("tanh", &[]) => Activation::TanH,
+        ("relu", &[]) => Activation::ReLU,
+        ("leaky_relu", &[]) => Activation::LeakyReLU(0.01),
+        ("leaky_relu", &[slope]) => Activation::LeakyReLU(slope),
+        ("softmax", &[]) => Activation::Softmax,
+        ("log_softmax", &[]) => Activation::LogSoftmax,
+        _ => return Err(invalid_input(format!("Unknown activation {:?}", text))),
+    })
+}
+
+pub fn parse_objective(text: &str) -> io::Result<Objective> {
+    let (name, values) = parse_call(text)?;
+    Ok(match (name.as_str(), &values[..]) {
+        ("log", &[]) => Objective::Log,
+        ("focal", &[]) => Objective::Focal(2.0),
+        ("focal", &[gamma]) => Objective::Focal(gamma),
+        ("exponential", &[]) => Objective::Exponential,
+        ("hinge", &[]) => Objective::Hinge,
+        ("cross_entropy", &[]) => Objective::CrossEntropy,
+        ("sum_squared_error", &[]) => Objective::SumSquaredError,
+        ("mean_squared_error", &[]) => Objective::MeanSquaredError,
+        ("mean_absolute_error", &[]) => Objective::MeanAbsoluteError,
+        ("huber", &[]) => Objective::Huber(1.0),
+        ("huber", &[threshold]) => Objective::Huber(threshold),
+        ("log_cosh", &[]) => Objective::LogCosh,
+        ("quantile", &[quantile]) => Objective::Quantile(quantile),
+        ("likelihood", &[]) => Objective::Likelihood,
+        _ => return Err(invalid_input(format!("Unknown objective {:?}", text))),
+    })
+}
+
+pub fn parse_optimizer(text: &str) -> io::Result<Optimizer> {
+    let (name, values) = parse_call(text)?;
+    Ok(match (name.as_str(), &values[..]) {
+        ("sgd", &[]) => Optimizer::StochasticGradientDescent,
+        ("momentum", &[]) => Optimizer::Momentum(0.9),
+        ("momentum", &[momentum]) => Optimizer::Momentum(momentum),
+        ("rmsprop", &[]) => Optimizer::RMSProp { decay: 0.9, epsilon: 1e-8 },
+        ("rmsprop", &[decay, epsilon]) => Optimizer::RMSProp { decay, epsilon },
+        ("adam", &[]) => Optimizer::adam(),
+        ("adam", &[beta1, beta2, epsilon]) => Optimizer::Adam { beta1, beta2, epsilon },
+        _ => return Err(invalid_input(format!("Unknown optimizer {:?}", text))),
+    })
+}
+
+pub fn parse_schedule(text: &str, initial: f64) -> io::Result<LearningRateSchedule> {
+    let (name, values) = parse_call(text)?;
+    Ok(match (name.as_str(), &values[..]) {
+        ("constant", &[]) => LearningRateSchedule::Constant(initial),
+        ("step_decay", &[decay, steps]) => LearningRateSchedule::StepDecay { initial, decay, steps: steps as u64 },
+        ("exponential_decay", &[decay]) => LearningRateSchedule::ExponentialDecay { initial, decay },
+        ("cosine_annealing", &[minimum, steps]) => LearningRateSchedule::CosineAnnealing { initial, minimum, steps: steps as u64 },
+        _ => return Err(invalid_input(format!("Unknown schedule {:?}", text))),
+    })
+}
+
+// Inverse of Metric::name()
+pub fn parse_metric(name: &str) -> io::Result<Metric> {
+    let name = name.trim();
+    let mut metrics = vec![
+        Metric::Accuracy, Metric::RocAuc, Metric::PrAuc, Metric::LogLoss,
+        Metric::MeanAbsoluteError, Metric::RootMeanSquaredError, Metric::R2, Metric::MeanAbsolutePercentageError,
+    ];
+    for average in &[Average::Micro, Average::Macro, Average::Weighted] {
+        metrics.extend_from_slice(&[Metric::Precision(*average), Metric::Recall(*average), Metric::F1(*average)]);
+    }
+    if let Some(metric) = metrics.into_iter().find(|metric| metric.name() == name) {
+        return Ok(metric);
+    }
+
+    if name.starts_with("top_") && name.ends_with("_accuracy") {
+        if let Ok(k) = name.trim_start_matches("top_").trim_end_matches("_accuracy").parse() {
+            return Ok(Metric::TopKAccuracy(k));
+        }
+    }
+    Err(invalid_input(format!("Unknown metric {:?}", name)))
+}
+
+fn parse_metrics(text: &str) -> io::Result<Vec<Metric>> {
+    text.split(',').filter(|name| !name.trim().is_empty()).map(parse_metric).collect()
+}
+
+// Column names, each with an optional :numeric, :one_hot or :index type
+fn parse_columns(text: &str) -> io::Result<Vec<(String, ColumnType)>> {
+    let mut columns = Vec::new();
+    for column in text.split(',').map(|column| column.trim()).filter(|column| !column.is_empty()) {
+        let (name, column_type) = match column.rfind(':') {
+            Some(i) => (&column[..i], match &column[i + 1..] {
+                "numeric" => ColumnType::Numeric,
+                "one_hot" => ColumnType::OneHot,
+                "index" => ColumnType::Index,
+                column_type => return Err(invalid_input(format!("Unknown column type {:?}", column_type))),
+            }),
+            None => (column, ColumnType::Numeric),
+        };
+        columns.push((name.to_owned(), column_type));
+    }
+    if columns.is_empty() {
+        return Err(invalid_input("No columns given".to_owned()));
+    }
+    Ok(columns)
+}
+
+// "name" or "name(1, 2.5)"
+fn parse_call(text: &str) -> io::Result<(String, Vec<f64>)> {
+    let text = text.trim();
+    let start = match text.find('(') {
+        Some(start) if text.ends_with(')') => start,
+        Some(_) => return Err(invalid_input(format!("Unclosed parenthesis in {:?}", text))),
+        None => return Ok((text.to_owned(), Vec::new())),
+    };
+
+    let mut values = Vec::new();
+    for value in text[start + 1..text.len() - 1].split(',').map(|value| value.trim()).filter(|value| !value.is_empty()) {
+        values.push(value.parse().map_err(|_| invalid_input(format!("Invalid number {:?} in {:?}", value, text)))?);
+    }
+    Ok((text[..start].trim().to_owned(), values))
+}
+
+// Commas between parentheses separate arguments, not items
+fn split_top_level(text: &str) -> Vec<&str> {
+    let (mut items, mut depth, mut start) = (Vec::new(), 0, 0);
+    for (i, character) in text.char_indices() {
+        match character {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(&text[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    items.push(&text[start..]);
+    items
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use super::*;
+
+    fn arguments(text: &str) -> Arguments {
+        Arguments::parse(text.split_whitespace().map(|argument| argument.to_owned())).unwrap()
+    }
+
+    #[test]
+    fn parses_specifications() {
+        let network = parse_architecture("8, 4:tanh, attention(2, 2), transformer_encoder(2, 1, 6), 3:leaky_relu(0.1)").unwrap().build().unwrap();
+        let sizes: Vec<(usize, usize)> = network.layers().iter().map(|layer| (layer.inputs(), layer.outputs())).collect();
+        assert_eq!(sizes, vec![(8, 4), (4, 4), (4, 4), (4, 3)]);
+        for architecture in &["8", "0,2:relu", "8,2:unknown", "8,0:relu", "8,attention(3,1)", "8,attention(2,3)", "8,2:relu(1"] {
+            assert!(parse_architecture(architecture).is_err(), "{}", architecture);
+        }
+
+        let activations = [Activation::Identity, Activation::Binary(0.5), Activation::Sigmoid, Activation::TanH, Activation::ReLU,
+            Activation::LeakyReLU(0.3), Activation::Softmax, Activation::LogSoftmax];
+        for activation in &activations {
+            assert_eq!(parse_activation(&activation.name()).unwrap().name(), activation.name());
+        }
+        for metric in &[Metric::Accuracy, Metric::F1(Average::Weighted), Metric::TopKAccuracy(3), Metric::R2] {
+            assert_eq!(parse_metric(&metric.name()).unwrap().name(), metric.name());
+        }
+        assert!(parse_metric("top_x_accuracy").is_err());
+        assert!(parse_optimizer("adam(0.9)").is_err());
+        match parse_schedule("step_decay(0.5, 100)", 0.1).unwrap() {
+            LearningRateSchedule::StepDecay { initial, decay, steps } => assert_eq!((initial, decay, steps), (0.1, 0.5, 100)),
+            _ => panic!("Wrong schedule"),
+        }
+
+        let parsed = arguments("train --epochs 3 --model=a.json extra");
+        assert_eq!((parsed.command.as_str(), parsed.get("model"), parsed.positional.len()), ("train", Some("a.json"), 1));
+        assert!(parsed.value::<usize>("model", 0).is_err());
+        assert!(Arguments::parse(vec!["train".to_owned(), "--epochs".to_owned()]).is_err());
+
+        assert_eq!(class_index(2.0, 3).unwrap(), 2);
+        for class in &[3.0, -1.0, 1.5, f64::NAN, f64::INFINITY] {
+            assert!(class_index(*class, 3).is_err());
+        }
+    }
+
+    #[test]
+    fn train_evaluate_predict_inspect() {
+        let directory = env::temp_dir().join("neural_network_cli");
+        fs::create_dir_all(&directory).unwrap();
+        let data = directory.join("data.csv");
+        let model = directory.join("model.json");
+        let predictions = directory.join("predictions.csv");
+        let history = directory.join("history.json");
+        fs::write(&data, "x,y,class\n0.1,0.9,b\n0.8,0.2,a\n0.2,0.7,b\n0.9,0.1,a\n0.3,0.8,b\n0.7,0.3,a\n").unwrap();
+
+        let config = directory.join("config.toml");
+
+        let common = format!("--model {} --data {}", model.display(), data.display());
+        let columns = "--features x,y --target class:one_hot";
+        run(&arguments(&format!("train {} {} --architecture 2,4:tanh,2:softmax --objective cross_entropy --optimizer adam \
+            --epochs 3 --batch-size 2 --scale-inputs standard --metrics accuracy --history {} --save-config {}",
+            common, columns, history.display(), config.display()))).unwrap();
+        // Same run from the saved configuration
+        run(&arguments(&format!("train {} {} --config {} --scale-inputs standard", common, columns, config.display()))).unwrap();
+        assert!(run(&arguments(&format!("train {} {} --config {} --epochs 2", common, columns, config.display()))).is_err());
+        run(&arguments(&format!("evaluate {} --objective cross_entropy --metrics accuracy,log_loss", common))).unwrap();
+        run(&arguments(&format!("predict {} --output {}", common, predictions.display()))).unwrap();
+        run(&arguments(&format!("inspect --model {}", model.display()))).unwrap();
+
+        let saved: History = serde_json::from_reader(File::open(&history).unwrap()).unwrap();
+        assert_eq!((saved.loss.len(), saved.metrics["accuracy"].len()), (3, 3));
+        let lines: Vec<String> = fs::read_to_string(&predictions).unwrap().lines().map(|line| line.to_owned()).collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "label,a,b");
+
+        assert!(run(&arguments(&format!("evaluate {} --objective cross_entropy --epochs 3", common))).is_err());
+        assert!(run(&arguments(&format!("train {} --architecture 3,2:softmax --objective cross_entropy --features x,y --target class:one_hot", common))).is_err());
+        assert!(run(&arguments("unknown")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
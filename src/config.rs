@@ -0,0 +1,287 @@
+// Declarative model and training configuration, saved as TOML or JSON depending on the file extension
+//
+// Experiments are versioned as files instead of code. Enums use the snake case names of the command
+// line, e.g. in TOML : activation = "relu", activation = { leaky_relu = 0.01 }, initializer = "he".
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde_json;
+use toml;
+
+use network::NeuralNetwork;
+use builder::NeuralNetworkBuilder;
+use layer::{Layer, Initializer, Regularization};
+use activation::Activation;
+use attention::AttentionMask;
+use training::TrainingConfig;
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ModelConfig {
+    pub inputs: usize,
+    pub layers: Vec<LayerConfig>,
+    // How to train the network, absent from files only describing an architecture
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub training: Option<TrainingConfig>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LayerConfig {
+    Dense {
+        neurons: usize,
+        activation: Activation,
+        // Absent means the default initializer, it is unknown for layers of an existing network
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        initializer: Option<Initializer>,
+        #[serde(default, skip_serializing_if = "Regularization::is_zero")]
+        regularization: Regularization,
+    },
+    Attention {
+        sequence_length: usize,
+        heads: usize,
+        #[serde(default)]
+        mask: AttentionMask,
+    },
+    TransformerEncoder {
+        sequence_length: usize,
+        heads: usize,
+        feed_forward_size: usize,
+        #[serde(default)]
+        mask: AttentionMask,
+    },
+}
+
+impl ModelConfig {
+    pub fn new(inputs: usize) -> Self {
+        Self {
+            inputs,
+            layers: Vec::new(),
+            training: None,
+        }
+    }
+
+    pub fn layer(mut self, layer: LayerConfig) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    pub fn training(mut self, training: TrainingConfig) -> Self {
+        self.training = Some(training);
+        self
+    }
+
+    // Architecture of an existing network, initializers are unknown and left out
+    pub fn from_network(network: &NeuralNetwork) -> Self {
+        let layers = network.layers().iter().map(|layer| match *layer {
+            Layer::Dense(ref layer) => LayerConfig::Dense {
+                neurons: layer.weights.cols(),
+                activation: layer.activation_function,
+                initializer: None,
+                regularization: layer.regularization,
+            },
+            Layer::MultiHeadAttention(ref layer) => LayerConfig::Attention {
+                sequence_length: layer.sequence_length(),
+                heads: layer.heads(),
+                mask: layer.mask,
+            },
+            Layer::TransformerEncoder(ref layer) => LayerConfig::TransformerEncoder {
+                sequence_length: layer.attention.sequence_length(),
+                heads: layer.attention.heads(),
+                feed_forward_size: layer.feed_forward_hidden.weights.cols(),
+                mask: layer.attention.mask,
+            },
+        }).collect();
+
+        Self {
+            inputs: network.input_size(),
+            layers,
+            training: None,
+        }
+    }
+
+    // Layers that do not fit the outputs of the previous one are reported by try_build()
+    pub fn builder(&self) -> NeuralNetworkBuilder {
+        let mut builder = NeuralNetworkBuilder::new(self.inputs);
+        for layer in &self.layers {
+            builder = match *layer {
+                LayerConfig::Dense { neurons, activation, initializer, regularization } => {
+                    builder.initializer(initializer.unwrap_or_default()).regularization(regularization).layer(neurons, activation)
+                },
+                LayerConfig::Attention { sequence_length, heads, mask } => builder.attention(sequence_length, heads, mask),
+                LayerConfig::TransformerEncoder { sequence_length, heads, feed_forward_size, mask } => {
+                    builder.transformer_encoder(sequence_length, heads, feed_forward_size, mask)
+                },
+            };
+        }
+        builder
+    }
+
+    pub fn build(&self) -> io::Result<NeuralNetwork> {
+        Ok(self.builder().try_build()?)
+    }
+
+    pub fn from_toml(text: &str) -> io::Result<Self> {
+        toml::from_str(text).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> io::Result<String> {
+        toml::to_string_pretty(self).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    pub fn from_json(text: &str) -> io::Result<Self> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    // .toml or .json file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(&path)?;
+        if is_toml(path.as_ref())? { Self::from_toml(&text) } else { Self::from_json(&text) }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let text = if is_toml(path.as_ref())? { self.to_toml()? } else { self.to_json()? };
+        fs::write(path, text)
+    }
+}
+
+
+fn is_toml(path: &Path) -> io::Result<bool> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => Ok(true),
+        Some("json") => Ok(false),
+        _ => Err(invalid_input(format!("{} is neither a .toml nor a .json file", path.display()))),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use serde::Serialize;
+    use objective::Objective;
+    use optimizer::Optimizer;
+    use metrics::Metric;
+    use super::*;
+
+    const MNIST: &str = r#"
+inputs = 784
+
+[[layers]]
+type = "dense"
+neurons = 512
+activation = "relu"
+initializer = "he"
+regularization = { l1 = 0.0, l2 = 0.0001 }
+
+[[layers]]
+type = "dense"
+neurons = 10
+activation = "softmax"
+initializer = "xavier"
+
+[training]
+objective = "cross_entropy"
+optimizer = { adam = { beta1 = 0.9, beta2 = 0.999, epsilon = 1e-8 } }
+schedule = { exponential_decay = { initial = 0.001, decay = 0.9999 } }
+batch_size = 32
+epochs = 10
+metrics = ["accuracy", { f1 = "macro" }]
+"#;
+
+    #[test]
+    fn toml_and_json_round_trip() {
+        let config = ModelConfig::from_toml(MNIST).unwrap();
+        let network = config.build().unwrap();
+        let sizes: Vec<(usize, usize)> = network.layers().iter().map(|layer| (layer.inputs(), layer.outputs())).collect();
+        assert_eq!(sizes, vec![(784, 512), (512, 10)]);
+
+        let training = config.training.clone().unwrap();
+        assert_eq!((training.batch_size, training.epochs, training.shuffle, training.seed), (32, 10, true, 0));
+        assert_eq!(training.metrics.len(), 2);
+
+        // Written back, then read again, gives the same file
+        let written = config.to_toml().unwrap();
+        assert_eq!(ModelConfig::from_toml(&written).unwrap().to_toml().unwrap(), written);
+        let json = config.to_json().unwrap();
+        assert_eq!(ModelConfig::from_json(&json).unwrap().to_toml().unwrap(), written);
+
+        let path = env::temp_dir().join("neural_network_config.toml");
+        config.save(&path).unwrap();
+        assert_eq!(ModelConfig::load(&path).unwrap().to_toml().unwrap(), written);
+        fs::remove_file(&path).unwrap();
+        assert!(config.save(env::temp_dir().join("neural_network_config.yaml")).is_err());
+
+        // Regularization is kept by the network, initializers only matter when building it
+        let from_network = ModelConfig::from_network(&network);
+        match from_network.layers[0] {
+            LayerConfig::Dense { regularization, initializer, .. } => {
+                assert_eq!(regularization.l2, 0.0001);
+                assert!(initializer.is_none());
+            },
+            _ => panic!("Wrong layer"),
+        }
+        assert!(!from_network.to_toml().unwrap().contains("initializer"));
+    }
+
+    #[test]
+    fn invalid_architectures() {
+        let dense = |neurons| LayerConfig::Dense {
+            neurons,
+            activation: Activation::TanH,
+            initializer: None,
+            regularization: Regularization::default(),
+        };
+        let attention = |sequence_length, heads| LayerConfig::Attention { sequence_length, heads, mask: AttentionMask::default() };
+
+        assert!(ModelConfig::new(4).build().is_err());
+        assert!(ModelConfig::new(4).layer(dense(0)).build().is_err());
+        assert!(ModelConfig::new(4).layer(dense(6)).layer(attention(4, 1)).build().is_err());
+        assert!(ModelConfig::new(4).layer(dense(6)).layer(attention(3, 3)).build().is_err());
+        assert!(ModelConfig::new(4).layer(dense(6)).layer(attention(3, 2)).layer(dense(2)).build().is_ok());
+        assert!(ModelConfig::new(0).layer(dense(2)).build().is_err());
+        let transformer = LayerConfig::TransformerEncoder { sequence_length: 2, heads: 1, feed_forward_size: 0, mask: AttentionMask::default() };
+        let error = ModelConfig::new(4).layer(transformer).build().err().unwrap();
+        assert_eq!(error.to_string(), "Layer 0 has no neurons");
+        assert!(ModelConfig::from_toml("inputs = 4\n[[layers]]\ntype = \"convolution\"\n").is_err());
+    }
+    // Name of a unit variant, or key of a variant with values
+    fn serde_name<T: Serialize>(value: &T) -> String {
+        match serde_json::to_value(value).unwrap() {
+            serde_json::Value::String(name) => name,
+            serde_json::Value::Object(map) => map.keys().next().unwrap().clone(),
+            _ => panic!("Not an enum"),
+        }
+    }
+
+    // A file uses the same names as the command line, e.g. leaky_relu for --activation leaky_relu(0.1)
+    #[test]
+    fn names_match_the_command_line() {
+        let command_line = |name: String| name.split('(').next().unwrap().to_owned();
+        for activation in &[Activation::Identity, Activation::Binary(0.5), Activation::TanH, Activation::ReLU, Activation::LeakyReLU(0.1), Activation::LogSoftmax] {
+            assert_eq!(serde_name(activation), command_line(activation.name()));
+        }
+        for objective in &[Objective::Focal(2.0), Objective::CrossEntropy, Objective::MeanSquaredError, Objective::LogCosh] {
+            assert_eq!(serde_name(objective), command_line(objective.name()));
+        }
+        for metric in &[Metric::Accuracy, Metric::RocAuc, Metric::MeanAbsoluteError, Metric::RootMeanSquaredError, Metric::R2] {
+            assert_eq!(serde_name(metric), metric.name());
+        }
+        assert_eq!(serde_name(&Optimizer::StochasticGradientDescent), "sgd");
+        assert_eq!(serde_name(&Optimizer::RMSProp { decay: 0.9, epsilon: 1e-8 }), "rmsprop");
+    }
+}
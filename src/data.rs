@@ -0,0 +1,312 @@
+// Datasets read batch by batch, so the whole training set does not have to fit in memory
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use ndarray::{Array2, Axis};
+use rand::{Rng, SeedableRng};
+use rand::prng::XorShiftRng;
+
+use idx::{IdxHeader, read_idx_rows};
+use error::Error;
+use augmentation::Augmenter;
+
+
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    // Inputs and targets of the given rows, in the order of the indices
+    fn get(&self, indices: &[usize]) -> io::Result<(Array2<f64>, Array2<f64>)>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+// Matrices already in memory, borrowed
+pub struct ArrayDataset<'a> {
+    pub inputs: &'a Array2<f64>,
+    pub targets: &'a Array2<f64>,
+}
+
+impl<'a> ArrayDataset<'a> {
+    pub fn new(inputs: &'a Array2<f64>, targets: &'a Array2<f64>) -> Self {
+        assert_eq!(inputs.rows(), targets.rows(), "Inputs should have same amount of rows as targets");
+        Self { inputs, targets }
+    }
+}
+
+impl<'a> Dataset for ArrayDataset<'a> {
+    fn len(&self) -> usize {
+        self.inputs.rows()
+    }
+
+    fn get(&self, indices: &[usize]) -> io::Result<(Array2<f64>, Array2<f64>)> {
+        Ok((self.inputs.select(Axis(0), indices), self.targets.select(Axis(0), indices)))
+    }
+}
+
+
+// Pair of IDX files read from disk on every batch, only the headers are kept in memory
+pub struct IdxDataset {
+    inputs_path: PathBuf,
+    targets_path: PathBuf,
+    inputs_header: IdxHeader,
+    targets_header: IdxHeader,
+    input_scale: f64,
+    classes: Option<usize>,
+}
+
+impl IdxDataset {
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(inputs_path: P, targets_path: Q) -> io::Result<Self> {
+        let inputs_header = IdxHeader::read(BufReader::new(File::open(&inputs_path)?))?;
+        let targets_header = IdxHeader::read(BufReader::new(File::open(&targets_path)?))?;
+        if inputs_header.rows() != targets_header.rows() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} inputs but {} targets", inputs_header.rows(), targets_header.rows())));
+        }
+
+        Ok(Self {
+            inputs_path: inputs_path.as_ref().to_path_buf(),
+            targets_path: targets_path.as_ref().to_path_buf(),
+            inputs_header,
+            targets_header,
+            input_scale: 1.0,
+            classes: None,
+        })
+    }
+
+    // Every input value is multiplied by the scale, e.g. 1/255 for images
+    pub fn scale_inputs(mut self, scale: f64) -> Self {
+        self.input_scale = scale;
+        self
+    }
+
+    // Targets are class indices, turned into one hot rows
+    pub fn one_hot_targets(mut self, classes: usize) -> Self {
+        self.classes = Some(classes);
+        self
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.inputs_header.row_length()
+    }
+}
+
+impl Dataset for IdxDataset {
+    fn len(&self) -> usize {
+        self.inputs_header.rows()
+    }
+
+    fn get(&self, indices: &[usize]) -> io::Result<(Array2<f64>, Array2<f64>)> {
+        let mut inputs = read_idx_rows(BufReader::new(File::open(&self.inputs_path)?), &self.inputs_header, indices)?;
+        let mut targets = read_idx_rows(BufReader::new(File::open(&self.targets_path)?), &self.targets_header, indices)?;
+
+        if self.input_scale != 1.0 {
+            inputs *= self.input_scale;
+        }
+        if let Some(classes) = self.classes {
+            let mut one_hot = Array2::<f64>::zeros((targets.rows(), classes));
+            for (i, class) in targets.column(0).iter().enumerate() {
+                if *class < 0.0 || *class as usize >= classes {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Class {} out of {} classes", class, classes)));
+                }
+                one_hot[[i, *class as usize]] = 1.0;
+            }
+            targets = one_hot;
+        }
+        Ok((inputs, targets))
+    }
+}
+
+
+pub struct DataLoader<D: Dataset> {
+    dataset: D,
+    pub batch_size: usize,
+    pub shuffle: bool,
+    // Skip the last batch of an epoch when it is smaller than batch_size
+    pub drop_last: bool,
+    // Applied to the inputs of every batch
+    pub augmenter: Option<Augmenter>,
+    rng: XorShiftRng,
+}
+
+impl<D: Dataset> DataLoader<D> {
+    pub fn new(dataset: D, batch_size: usize) -> Self {
+        Self {
+            dataset,
+            batch_size,
+            shuffle: true,
+            drop_last: false,
+            augmenter: None,
+            rng: seeded_rng(0),
+        }
+    }
+
+    pub fn shuffle(mut self, shuffle: bool) -> Self {
+        self.shuffle = shuffle;
+        self
+    }
+
+    pub fn drop_last(mut self, drop_last: bool) -> Self {
+        self.drop_last = drop_last;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng = seeded_rng(seed);
+        self
+    }
+
+    pub fn augment(mut self, augmenter: Augmenter) -> Self {
+        self.augmenter = Some(augmenter);
+        self
+    }
+
+    pub fn dataset(&self) -> &D {
+        &self.dataset
+    }
+
+    // Batches of one epoch
+    pub fn batches(&self) -> usize {
+        batch_count(self.dataset.len(), self.batch_size, self.drop_last)
+    }
+
+    // Rows of every batch are only read when the iterator reaches the batch, a zero batch size gives a single error
+    pub fn epoch(&mut self) -> Batches<'_, D> {
+        let order = epoch_order(self.dataset.len(), self.batch_size, self.shuffle, self.drop_last, &mut self.rng);
+
+        Batches {
+            dataset: &self.dataset,
+            augmenter: self.augmenter.as_mut(),
+            order,
+            batch_size: self.batch_size,
+            next: 0,
+            error: if self.batch_size == 0 { Some(Error::ZeroBatchSize) } else { None },
+        }
+    }
+}
+
+pub struct Batches<'a, D: Dataset + 'a> {
+    dataset: &'a D,
+    augmenter: Option<&'a mut Augmenter>,
+    order: Vec<usize>,
+    batch_size: usize,
+    next: usize,
+    error: Option<Error>,
+}
+
+impl<'a, D: Dataset> Iterator for Batches<'a, D> {
+    type Item = io::Result<(Array2<f64>, Array2<f64>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error.into()));
+        }
+        if self.next * self.batch_size >= self.order.len() {
+            return None;
+        }
+        let batch = read_batch(self.dataset, &self.order, self.next, self.batch_size, self.augmenter.as_deref_mut());
+        self.next += 1;
+        Some(batch)
+    }
+}
+
+
+// Batching shared by DataLoader and training::Trainer
+
+// No batches for a zero batch size, their users report it as Error::ZeroBatchSize
+pub fn batch_count(rows: usize, batch_size: usize, drop_last: bool) -> usize {
+    if batch_size == 0 {
+        return 0;
+    }
+    if drop_last { rows / batch_size } else { rows.div_ceil(batch_size) }
+}
+
+// Rows in the order they are read during one epoch, without the rows of a dropped last batch
+pub fn epoch_order<R: Rng>(rows: usize, batch_size: usize, shuffle: bool, drop_last: bool, rng: &mut R) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..rows).collect();
+    if shuffle {
+        rng.shuffle(&mut order);
+    }
+    order.truncate(batch_count(rows, batch_size, drop_last) * batch_size);
+    order
+}
+
+// Rows of the given batch of an epoch order, the inputs are augmented when there is an augmenter
+pub fn read_batch<D: Dataset>(dataset: &D, order: &[usize], batch: usize, batch_size: usize, augmenter: Option<&mut Augmenter>) -> io::Result<(Array2<f64>, Array2<f64>)> {
+    let start = batch * batch_size;
+    let (inputs, targets) = dataset.get(&order[start..order.len().min(start + batch_size)])?;
+    Ok(match augmenter {
+        Some(augmenter) => (augmenter.augment(&inputs), targets),
+        None => (inputs, targets),
+    })
+}
+
+// Same seed, same random numbers, on every platform
+pub fn seeded_rng(seed: u64) -> XorShiftRng {
+    let mut bytes = [0; 16];
+    for i in 0..8 {
+        bytes[i] = (seed >> (8 * i)) as u8;
+        bytes[i + 8] = !bytes[i];
+    }
+    XorShiftRng::from_seed(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use ndarray::arr2;
+    use idx::{write_idx, IdxType};
+    use super::*;
+
+    #[test]
+    fn batches_shuffle_and_drop_last() {
+        let inputs = arr2(&[[0.0], [1.0], [2.0], [3.0], [4.0]]);
+        let targets = arr2(&[[0.0], [10.0], [20.0], [30.0], [40.0]]);
+
+        let mut loader = DataLoader::new(ArrayDataset::new(&inputs, &targets), 2).shuffle(false);
+        let sizes: Vec<usize> = loader.epoch().map(|batch| batch.unwrap().0.rows()).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+
+        let mut loader = DataLoader::new(ArrayDataset::new(&inputs, &targets), 2).drop_last(true).seed(3);
+        assert_eq!(loader.batches(), 2);
+        let mut seen = Vec::new();
+        for batch in loader.epoch() {
+            let (inputs, targets) = batch.unwrap();
+            assert_eq!(inputs.rows(), 2);
+            assert_eq!(targets, &inputs * 10.0);
+            seen.extend(inputs.column(0).iter().cloned());
+        }
+        seen.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        seen.dedup();
+        assert_eq!(seen.len(), 4);
+
+        let mut loader = DataLoader::new(ArrayDataset::new(&inputs, &targets), 0);
+        assert_eq!(loader.batches(), 0);
+        let batches: Vec<_> = loader.epoch().collect();
+        assert_eq!(batches.len(), 1);
+        assert!(batches[0].as_ref().unwrap_err().to_string().contains("Batch size"));
+    }
+
+    #[test]
+    fn idx_dataset_reads_requested_rows() {
+        let directory = env::temp_dir().join("neural_network_idx_dataset");
+        fs::create_dir_all(&directory).unwrap();
+        let images = directory.join("images.idx");
+        let labels = directory.join("labels.idx");
+        write_idx(&images, &arr2(&[[0.0, 51.0], [102.0, 153.0], [204.0, 255.0]]).into_dyn(), IdxType::UnsignedByte).unwrap();
+        write_idx(&labels, &arr2(&[[2.0], [0.0], [1.0]]).into_dyn(), IdxType::UnsignedByte).unwrap();
+
+        let dataset = IdxDataset::open(&images, &labels).unwrap().scale_inputs(1.0 / 255.0).one_hot_targets(3);
+        let (inputs, targets) = dataset.get(&[2, 0]).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(dataset.len(), 3);
+        assert!(inputs.all_close(&arr2(&[[0.8, 1.0], [0.0, 0.2]]), 1e-12));
+        assert_eq!(targets, arr2(&[[0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]));
+    }
+}
@@ -0,0 +1,97 @@
+// Errors of the fallible variants (try_build(), try_feed_forward(), try_train(), ...), for callers that must not abort
+//
+// The panicking variants stay for experiments, where a wrong shape is a bug to fix right away.
+// Errors convert into io::Error, so they can be returned from functions giving io::Result.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    NoLayers,
+    NoInputs,
+    // Dense layer without neurons, or transformer encoder without a feed forward network
+    ZeroSize { layer: usize },
+    SequenceLength { layer: usize, inputs: usize, sequence_length: usize },
+    Heads { layer: usize, model_size: usize, heads: usize },
+    InputWidth { expected: usize, found: usize },
+    OutputWidth { expected: usize, found: usize },
+    RowCount { inputs: usize, targets: usize },
+    EmptyData,
+    ZeroBatchSize,
+    // Activation without a usable derivative in a layer that would be trained
+    NotTrainable { layer: usize, activation: String },
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::NoLayers => write!(f, "No layers defined"),
+            Error::NoInputs => write!(f, "A network needs at least one input"),
+            Error::ZeroSize { layer } => write!(f, "Layer {} has no neurons", layer),
+            Error::SequenceLength { layer, inputs, sequence_length } => {
+                write!(f, "Layer {} can not split {} inputs into {} tokens", layer, inputs, sequence_length)
+            },
+            Error::Heads { layer, model_size, heads } => write!(f, "Layer {} can not split model size {} into {} heads", layer, model_size, heads),
+            Error::InputWidth { expected, found } => write!(f, "Input has {} columns but the network expects {}", found, expected),
+            Error::OutputWidth { expected, found } => write!(f, "Expected output has {} columns but the network has {} outputs", found, expected),
+            Error::RowCount { inputs, targets } => write!(f, "{} input rows but {} expected output rows", inputs, targets),
+            Error::EmptyData => write!(f, "No rows given"),
+            Error::ZeroBatchSize => write!(f, "Batch size must be greater than zero"),
+            Error::NotTrainable { layer, ref activation } => {
+                write!(f, "Layer {} uses the {} activation, its derivative is zero almost everywhere so it can not be trained", layer, activation)
+            },
+            Error::Io(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(io::ErrorKind::InvalidInput, error.to_string()),
+        }
+    }
+}
+
+
+// Configuration that trains, but most likely not the way it was meant to
+#[derive(Debug)]
+pub enum Warning {
+    IncompatibleObjective { activation: String, objective: String, reason: &'static str },
+    // Too few rows of a class for the requested split fractions
+    SmallClass { class: usize, rows: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::IncompatibleObjective { ref activation, ref objective, reason } => {
+                write!(f, "{} output with the {} objective: {}", activation, objective, reason)
+            },
+            Warning::SmallClass { class, rows } => write!(f, "class {} only has {} rows, one of them is kept for training", class, rows),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// Gradients of the error with regard to every parameter of a network
+// Stored per layer, in the same order as Layer::parameters()
+
+use ndarray::Array2;
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum GradientClipping {
+    // Every gradient value is clamped between -limit and limit
+    Value(f64),
+    // Gradients are rescaled when the norm over all of them is greater than the limit
+    Norm(f64),
+}
+
+
+#[derive(Clone)]
+pub struct Gradients {
+    pub layers: Vec<Vec<Array2<f64>>>,
+}
+
+impl Gradients {
+    pub fn new(layers: Vec<Vec<Array2<f64>>>) -> Self {
+        Self {
+            layers,
+        }
+    }
+
+    pub fn layer(&self, index: usize) -> &[Array2<f64>] {
+        &self.layers[index]
+    }
+
+    // Sum other gradients into these ones, used to accumulate gradients over several micro batches
+    pub fn add(&mut self, other: &Gradients) {
+        assert_eq!(self.layers.len(), other.layers.len(), "Gradients do not have the same amount of layers");
+        for (layer, other_layer) in self.layers.iter_mut().zip(&other.layers) {
+            for (gradient, other_gradient) in layer.iter_mut().zip(other_layer) {
+                *gradient += other_gradient;
+            }
+        }
+    }
+
+    pub fn scale(&mut self, factor: f64) {
+        for gradient in self.layers.iter_mut().flat_map(|layer| layer.iter_mut()) {
+            *gradient *= factor;
+        }
+    }
+
+    // L2 norm over every gradient of every layer
+    pub fn norm(&self) -> f64 {
+        self.layers.iter()
+            .flat_map(|layer| layer.iter())
+            .map(|gradient| gradient.iter().map(|v| v * v).sum::<f64>())
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    pub fn clip(&mut self, clipping: GradientClipping) {
+        match clipping {
+            GradientClipping::Value(limit) => self.clip_by_value(limit),
+            GradientClipping::Norm(limit) => self.clip_by_norm(limit),
+        }
+    }
+
+    pub fn clip_by_value(&mut self, limit: f64) {
+        for gradient in self.layers.iter_mut().flat_map(|layer| layer.iter_mut()) {
+            gradient.mapv_inplace(|v| v.max(-limit).min(limit));
+        }
+    }
+
+    pub fn clip_by_norm(&mut self, limit: f64) {
+        let norm = self.norm();
+        if norm > limit {
+            self.scale(limit / norm);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use super::*;
+
+    fn gradients() -> Gradients {
+        Gradients::new(vec![
+            vec![arr2(&[[3.0, -4.0]]), arr2(&[[0.0]])],
+            vec![arr2(&[[0.0], [12.0]])],
+        ])
+    }
+
+    #[test]
+    fn clipping() {
+        let mut by_value = gradients();
+        by_value.clip(GradientClipping::Value(3.5));
+        assert_eq!(by_value.layer(0)[0], arr2(&[[3.0, -3.5]]));
+        assert_eq!(by_value.layer(1)[0], arr2(&[[0.0], [3.5]]));
+
+        let mut by_norm = gradients();
+        assert_eq!(by_norm.norm(), 13.0);
+        by_norm.clip(GradientClipping::Norm(6.5));
+        assert_eq!(by_norm.layer(0)[0], arr2(&[[1.5, -2.0]]));
+        assert_eq!(by_norm.layer(1)[0], arr2(&[[0.0], [6.0]]));
+    }
+
+    #[test]
+    fn accumulation() {
+        let mut total = gradients();
+        total.add(&gradients());
+        total.scale(0.5);
+        assert_eq!(total.layers, gradients().layers);
+    }
+}
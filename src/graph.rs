@@ -0,0 +1,621 @@
+// Graph network : every node can read the activities of several previous nodes
+// Nodes are stored in the order they were created, which is always a valid topological order
+// because a node can only reference nodes created before it.
+
+use std::collections::HashMap;
+
+use ndarray::{Array2, Axis, stack};
+
+use layer::{Layer, Dense};
+use activation::Activation;
+use objective::Objective;
+use attention::{AttentionMask, MultiHeadAttention, TransformerEncoder};
+use gradients::{Gradients, GradientClipping};
+use network::GradientCheck;
+
+
+pub type NodeId = usize;
+
+#[derive(Copy, Clone)]
+pub enum Merge {
+    Add,
+    Concatenate,
+    Multiply,
+}
+
+// Objective of one named output, its error is multiplied by weight in the total loss
+pub struct HeadObjective {
+    pub output: String,
+    pub objective: Objective,
+    pub weight: f64,
+}
+
+impl HeadObjective {
+    pub fn new(output: &str, objective: Objective, weight: f64) -> Self {
+        Self {
+            output: output.to_owned(),
+            objective,
+            weight,
+        }
+    }
+}
+
+enum Node {
+    Input,
+    // Boxed, a layer is much larger than the other nodes
+    Layer(Box<Layer>, NodeId),
+    Merge(Merge, Vec<NodeId>),
+}
+
+
+pub struct GraphBuilder {
+    nodes: Vec<Node>,
+    sizes: Vec<usize>,
+    inputs: Vec<NodeId>,
+    outputs: Vec<(String, NodeId)>,
+}
+
+impl Default for GraphBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            sizes: Vec::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, node: Node, size: usize) -> NodeId {
+        self.nodes.push(node);
+        self.sizes.push(size);
+        self.nodes.len() - 1
+    }
+
+    pub fn input(&mut self, size: usize) -> NodeId {
+        let id = self.push(Node::Input, size);
+        self.inputs.push(id);
+        id
+    }
+
+    pub fn layer(&mut self, from: NodeId, neurons: usize, activation_function: Activation) -> NodeId {
+        let layer = Layer::Dense(Dense::new(neurons, self.sizes[from], activation_function));
+        self.push(Node::Layer(Box::new(layer), from), neurons)
+    }
+
+    pub fn attention(&mut self, from: NodeId, sequence_length: usize, heads: usize, mask: AttentionMask) -> NodeId {
+        let size = self.sequence_size(from, sequence_length);
+        let layer = Layer::MultiHeadAttention(MultiHeadAttention::new(sequence_length, size / sequence_length, heads, mask));
+        self.push(Node::Layer(Box::new(layer), from), size)
+    }
+
+    pub fn transformer_encoder(&mut self, from: NodeId, sequence_length: usize, heads: usize, feed_forward_size: usize, mask: AttentionMask) -> NodeId {
+        let size = self.sequence_size(from, sequence_length);
+        let layer = Layer::TransformerEncoder(TransformerEncoder::new(sequence_length, size / sequence_length, heads, feed_forward_size, mask));
+        self.push(Node::Layer(Box::new(layer), from), size)
+    }
+
+    // Size of a node read as sequence_length tokens
+    fn sequence_size(&self, node: NodeId, sequence_length: usize) -> usize {
+        let size = self.sizes[node];
+        assert!(sequence_length > 0 && size.is_multiple_of(sequence_length), "Node {} of size {} can not be split into {} tokens", node, size, sequence_length);
+        size
+    }
+
+    pub fn add(&mut self, inputs: &[NodeId]) -> NodeId {
+        self.merge(Merge::Add, inputs)
+    }
+
+    pub fn concatenate(&mut self, inputs: &[NodeId]) -> NodeId {
+        self.merge(Merge::Concatenate, inputs)
+    }
+
+    pub fn multiply(&mut self, inputs: &[NodeId]) -> NodeId {
+        self.merge(Merge::Multiply, inputs)
+    }
+
+    pub fn merge(&mut self, merge: Merge, inputs: &[NodeId]) -> NodeId {
+        assert!(!inputs.is_empty(), "Merge needs at least one input");
+        let size = match merge {
+            Merge::Concatenate => inputs.iter().map(|id| self.sizes[*id]).sum(),
+            Merge::Add | Merge::Multiply => {
+                let size = self.sizes[inputs[0]];
+                assert!(inputs.iter().all(|id| self.sizes[*id] == size), "Merged nodes must have the same size");
+                size
+            },
+        };
+        self.push(Node::Merge(merge, inputs.to_vec()), size)
+    }
+
+    pub fn size(&self, node: NodeId) -> usize {
+        self.sizes[node]
+    }
+
+    pub fn output(&mut self, name: &str, node: NodeId) {
+        assert!(self.outputs.iter().all(|(output, _)| output != name), "Output {} is already defined", name);
+        self.outputs.push((name.to_owned(), node));
+    }
+
+    pub fn build(self) -> GraphNetwork {
+        assert!(!self.inputs.is_empty(), "No inputs defined");
+        assert!(!self.outputs.is_empty(), "No outputs defined");
+
+        let number_of_nodes = self.nodes.len();
+        GraphNetwork {
+            nodes: self.nodes,
+            sizes: self.sizes,
+            inputs: self.inputs,
+            outputs: self.outputs,
+            activities: vec![Array2::<f64>::zeros((1, 1)); number_of_nodes],
+            gradient_clipping: None,
+        }
+    }
+}
+
+
+pub struct GraphNetwork {
+    nodes: Vec<Node>,
+    sizes: Vec<usize>,
+    inputs: Vec<NodeId>,
+    outputs: Vec<(String, NodeId)>,
+    activities: Vec<Array2<f64>>,
+    pub gradient_clipping: Option<GradientClipping>,
+}
+
+impl GraphNetwork {
+    // Inputs are given in the order the input nodes were created
+    pub fn feed_forward(&mut self, inputs: &[Array2<f64>]) -> HashMap<String, Array2<f64>> {
+        assert_eq!(inputs.len(), self.inputs.len(), "Wrong amount of inputs");
+
+        for (id, input) in self.inputs.iter().zip(inputs) {
+            assert_eq!(input.cols(), self.sizes[*id], "Input does not have the expected amount of columns");
+            self.activities[*id] = input.clone();
+        }
+
+        // Padding found by the attention layers, followed along the edges of the graph
+        let mut paddings: Vec<Option<Array2<bool>>> = vec![None; self.nodes.len()];
+
+        for i in 0..self.nodes.len() {
+            let activities = match self.nodes[i] {
+                Node::Input => continue,
+                Node::Layer(ref mut layer, from) => {
+                    paddings[i] = layer.padding(&self.activities[from], paddings[from].clone());
+                    layer.calculate_activities(&self.activities[from], paddings[i].as_ref());
+                    layer.activities().clone()
+                },
+                Node::Merge(merge, ref from) => {
+                    paddings[i] = from.iter().filter_map(|id| paddings[*id].clone()).next();
+                    let activities = &self.activities;
+                    let inputs: Vec<&Array2<f64>> = from.iter().map(|id| &activities[*id]).collect();
+                    merge_forward(merge, &inputs)
+                },
+            };
+            self.activities[i] = activities;
+        }
+
+        let activities = &self.activities;
+        self.outputs.iter().map(|&(ref name, id)| (name.clone(), activities[id].clone())).collect()
+    }
+
+    // Outputs without objective are not trained, they only receive deltas from the nodes they feed
+    pub fn train(&mut self, training_set: &[Array2<f64>], expected_result: &HashMap<String, Array2<f64>>, objectives: &[HeadObjective], batch_size: usize, learning_rate: f64) {
+        assert!(batch_size > 0, "Batch size must be greater than zero");
+        assert_eq!(training_set.len(), self.inputs.len(), "Wrong amount of inputs");
+        self.check_heads(expected_result, objectives);
+
+        let rows = training_set[0].rows();
+        assert!(training_set.iter().chain(expected_result.values()).all(|set| set.rows() == rows), "Training set should have same amount of rows as expected results");
+
+        let mut i = 0;
+
+        while i < rows {
+
+            let current_max_row = rows.min(batch_size + i);
+
+            let data: Vec<Array2<f64>> = training_set.iter().map(|set| set.slice(s![i..current_max_row, ..]).to_owned()).collect();
+            let expected_result_slice: HashMap<String, Array2<f64>> = objectives.iter()
+                .map(|head| (head.output.clone(), expected_result[&head.output].slice(s![i..current_max_row, ..]).to_owned()))
+                .collect();
+
+            let (errors, mut gradients) = self.compute_error_and_gradients(&data, &expected_result_slice, objectives);
+            if let Some(clipping) = self.gradient_clipping {
+                gradients.clip(clipping);
+            }
+            self.apply_gradients(&gradients, learning_rate);
+
+            let mut total_error = 0.0;
+            for (head, error) in objectives.iter().zip(errors) {
+                println!("Iteration {}; {} error: {}", i / batch_size, head.output, error);
+                total_error += head.weight * error;
+            }
+
+            println!("Iteration {}; error: {}", i / batch_size, total_error);
+            i += batch_size;
+        }
+    }
+
+    // Gradients of the weighted error of every head, stored per node in the order of the nodes.
+    // Inputs and merges have no parameters, so they have no gradients.
+    pub fn compute_gradients(&mut self, inputs: &[Array2<f64>], expected_result: &HashMap<String, Array2<f64>>, objectives: &[HeadObjective]) -> Gradients {
+        self.compute_error_and_gradients(inputs, expected_result, objectives).1
+    }
+
+    // Same as compute_gradients(), also returning the unweighted error of every head, in the order of objectives
+    pub fn compute_error_and_gradients(&mut self, inputs: &[Array2<f64>], expected_result: &HashMap<String, Array2<f64>>, objectives: &[HeadObjective]) -> (Vec<f64>, Gradients) {
+        self.check_heads(expected_result, objectives);
+        let network_result = self.feed_forward(inputs);
+
+        let mut errors = Vec::with_capacity(objectives.len());
+        let mut output_deltas = Vec::with_capacity(objectives.len());
+        for head in objectives {
+            let actual = &network_result[&head.output];
+            let ideal = &expected_result[&head.output];
+            assert_eq!(ideal.dim(), actual.dim(), "Expected result and actual result do not have the same shape");
+
+            errors.push(head.objective.calculate_error(actual, ideal));
+            let delta = head.objective.compute_derivative(actual, ideal) * head.weight;
+            output_deltas.push((self.output(&head.output).unwrap(), delta));
+        }
+
+        (errors, self.backpropagation(output_deltas))
+    }
+
+    // Plain gradient descent step, other optimizers can update parameters_mut() themselves
+    pub fn apply_gradients(&mut self, gradients: &Gradients, learning_rate: f64) {
+        assert_eq!(gradients.layers.len(), self.nodes.len(), "Gradients do not match the network nodes");
+        for (node, node_gradients) in self.nodes.iter_mut().zip(&gradients.layers) {
+            if let Node::Layer(ref mut layer, _) = *node {
+                layer.apply_gradients(node_gradients, learning_rate);
+            }
+        }
+    }
+
+    // Trainable matrices of every node, empty for inputs and merges
+    pub fn parameters(&self) -> Vec<Vec<&Array2<f64>>> {
+        self.nodes.iter().map(|node| match *node {
+            Node::Layer(ref layer, _) => layer.parameters(),
+            _ => Vec::new(),
+        }).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<Vec<&mut Array2<f64>>> {
+        self.nodes.iter_mut().map(|node| match *node {
+            Node::Layer(ref mut layer, _) => layer.parameters_mut(),
+            _ => Vec::new(),
+        }).collect()
+    }
+
+    // Compare the gradients of compute_gradients() with central finite differences of the weighted error,
+    // see NeuralNetwork::gradient_check(). Only nodes with parameters are checked, layer being the node id.
+    pub fn gradient_check(&mut self, inputs: &[Array2<f64>], expected_result: &HashMap<String, Array2<f64>>, objectives: &[HeadObjective], epsilon: f64) -> Vec<GradientCheck> {
+        let analytical_gradients = self.compute_gradients(inputs, expected_result, objectives);
+
+        let mut result = Vec::new();
+        for (i, node_gradients) in analytical_gradients.layers.iter().enumerate() {
+            if node_gradients.is_empty() {
+                continue;
+            }
+
+            let mut difference = 0.0;
+            let mut analytical_norm = 0.0;
+            let mut numerical_norm = 0.0;
+            let mut parameters = 0;
+
+            for (p, gradient) in node_gradients.iter().enumerate() {
+                for ((row, col), analytical) in gradient.indexed_iter() {
+                    let original = self.parameters()[i][p][[row, col]];
+
+                    self.parameters_mut()[i][p][[row, col]] = original + epsilon;
+                    let error_plus = self.weighted_error(inputs, expected_result, objectives);
+                    self.parameters_mut()[i][p][[row, col]] = original - epsilon;
+                    let error_minus = self.weighted_error(inputs, expected_result, objectives);
+                    self.parameters_mut()[i][p][[row, col]] = original;
+
+                    let numerical = (error_plus - error_minus) / (2.0 * epsilon);
+
+                    difference += (analytical - numerical).powi(2);
+                    analytical_norm += analytical.powi(2);
+                    numerical_norm += numerical.powi(2);
+                    parameters += 1;
+                }
+            }
+
+            let norms = analytical_norm.sqrt() + numerical_norm.sqrt();
+            result.push(GradientCheck {
+                layer: i,
+                parameters,
+                relative_error: if norms > 0.0 { difference.sqrt() / norms } else { 0.0 },
+            });
+        }
+
+        result
+    }
+
+    fn weighted_error(&mut self, inputs: &[Array2<f64>], expected_result: &HashMap<String, Array2<f64>>, objectives: &[HeadObjective]) -> f64 {
+        let outputs = self.feed_forward(inputs);
+        objectives.iter()
+            .map(|head| head.weight * head.objective.calculate_error(&outputs[&head.output], &expected_result[&head.output]))
+            .sum()
+    }
+
+    fn check_heads(&self, expected_result: &HashMap<String, Array2<f64>>, objectives: &[HeadObjective]) {
+        for head in objectives {
+            assert!(self.output(&head.output).is_some(), "Unknown output {}", head.output);
+            assert!(expected_result.contains_key(&head.output), "No expected result for output {}", head.output);
+        }
+    }
+
+    // Uses the activities of the last feed_forward() call
+    // Deltas of nodes read by several other nodes are summed before being propagated
+    fn backpropagation(&self, output_deltas: Vec<(NodeId, Array2<f64>)>) -> Gradients {
+        let mut deltas: Vec<Option<Array2<f64>>> = vec![None; self.nodes.len()];
+        for (id, delta) in output_deltas {
+            accumulate(&mut deltas, id, delta);
+        }
+
+        // Layers that do not lead to a trained output keep zero gradients
+        let mut gradients: Vec<Vec<Array2<f64>>> = self.parameters().iter()
+            .map(|parameters| parameters.iter().map(|parameter| Array2::<f64>::zeros(parameter.dim())).collect())
+            .collect();
+
+        for i in (0..self.nodes.len()).rev() {
+            let delta = match deltas[i].take() {
+                Some(delta) => delta,
+                None => continue,
+            };
+
+            match self.nodes[i] {
+                Node::Input => {},
+                Node::Layer(ref layer, from) => {
+                    let (previous_delta, layer_gradients) = layer.compute_gradients(&self.activities[from], &delta);
+                    gradients[i] = layer_gradients;
+                    accumulate(&mut deltas, from, previous_delta);
+                },
+                Node::Merge(merge, ref from) => {
+                    let activities = &self.activities;
+                    let inputs: Vec<&Array2<f64>> = from.iter().map(|id| &activities[*id]).collect();
+                    for (id, previous_delta) in from.iter().zip(merge_backward(merge, &inputs, &delta)) {
+                        accumulate(&mut deltas, *id, previous_delta);
+                    }
+                },
+            }
+        }
+
+        Gradients::new(gradients)
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn output_names(&self) -> Vec<&str> {
+        self.outputs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    fn output(&self, name: &str) -> Option<NodeId> {
+        self.outputs.iter().find(|&(output, _)| output == name).map(|&(_, id)| id)
+    }
+}
+
+
+fn accumulate(deltas: &mut [Option<Array2<f64>>], id: NodeId, delta: Array2<f64>) {
+    deltas[id] = Some(match deltas[id].take() {
+        Some(current) => current + delta,
+        None => delta,
+    });
+}
+
+fn merge_forward(merge: Merge, inputs: &[&Array2<f64>]) -> Array2<f64> {
+    match merge {
+        Merge::Add => {
+            let mut result = inputs[0].clone();
+            for input in &inputs[1..] {
+                result += *input;
+            }
+            result
+        },
+        Merge::Multiply => {
+            let mut result = inputs[0].clone();
+            for input in &inputs[1..] {
+                result *= *input;
+            }
+            result
+        },
+        Merge::Concatenate => {
+            let views: Vec<_> = inputs.iter().map(|input| input.view()).collect();
+            stack(Axis(1), &views).unwrap()
+        },
+    }
+}
+
+// Returns one delta per merged input
+fn merge_backward(merge: Merge, inputs: &[&Array2<f64>], delta: &Array2<f64>) -> Vec<Array2<f64>> {
+    match merge {
+        Merge::Add => {
+            inputs.iter().map(|_| delta.clone()).collect()
+        },
+        Merge::Multiply => {
+            (0..inputs.len()).map(|i| {
+                let mut result = delta.clone();
+                for (j, input) in inputs.iter().enumerate() {
+                    if i != j {
+                        result *= *input;
+                    }
+                }
+                result
+            }).collect()
+        },
+        Merge::Concatenate => {
+            let mut offset = 0;
+            inputs.iter().map(|input| {
+                let result = delta.slice(s![.., offset..offset + input.cols()]).to_owned();
+                offset += input.cols();
+                result
+            }).collect()
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use super::*;
+
+    #[test]
+    fn merges() {
+        let a = arr2(&[[1., 2.], [3., 4.]]);
+        let b = arr2(&[[5., 6.], [7., 8.]]);
+
+        assert_eq!(merge_forward(Merge::Add, &[&a, &b]), arr2(&[[6., 8.], [10., 12.]]));
+        assert_eq!(merge_forward(Merge::Multiply, &[&a, &b]), arr2(&[[5., 12.], [21., 32.]]));
+        assert_eq!(merge_forward(Merge::Concatenate, &[&a, &b]), arr2(&[[1., 2., 5., 6.], [3., 4., 7., 8.]]));
+
+        let delta = arr2(&[[1., 1., 2., 2.], [3., 3., 4., 4.]]);
+        let deltas = merge_backward(Merge::Concatenate, &[&a, &b], &delta);
+        assert_eq!(deltas[0], arr2(&[[1., 1.], [3., 3.]]));
+        assert_eq!(deltas[1], arr2(&[[2., 2.], [4., 4.]]));
+
+        let deltas = merge_backward(Merge::Multiply, &[&a, &b], &arr2(&[[1., 1.], [1., 1.]]));
+        assert_eq!(deltas[0], b);
+        assert_eq!(deltas[1], a);
+    }
+
+    #[test]
+    fn residual_block_with_two_heads() {
+        let mut builder = GraphBuilder::new();
+        let image = builder.input(4);
+        let features = builder.input(2);
+        let hidden = builder.layer(image, 4, Activation::ReLU);
+        let residual = builder.add(&[image, hidden]);
+        let merged = builder.concatenate(&[residual, features]);
+        let class = builder.layer(merged, 3, Activation::Softmax);
+        let value = builder.layer(merged, 1, Activation::Identity);
+        assert_eq!(builder.size(merged), 6);
+
+        builder.output("class", class);
+        builder.output("value", value);
+
+        let mut network = builder.build();
+        let results = network.feed_forward(&[arr2(&[[0.1, 0.2, 0.3, 0.4]]), arr2(&[[1.0, -1.0]])]);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results["class"].dim(), (1, 3));
+        assert_eq!(results["value"].dim(), (1, 1));
+    }
+
+    #[test]
+    fn gradient_check_residual_block_with_two_heads() {
+        let mut builder = GraphBuilder::new();
+        let image = builder.input(4);
+        let features = builder.input(2);
+        let hidden = builder.layer(image, 4, Activation::TanH);
+        let residual = builder.add(&[image, hidden]);
+        let merged = builder.concatenate(&[residual, features]);
+        let gate = builder.layer(merged, 6, Activation::Sigmoid);
+        let gated = builder.multiply(&[merged, gate]);
+        let class = builder.layer(gated, 3, Activation::Softmax);
+        let value = builder.layer(gated, 1, Activation::Identity);
+        builder.output("class", class);
+        builder.output("value", value);
+        let mut network = builder.build();
+
+        // Small weights so no unit saturates
+        for node in network.parameters_mut() {
+            for parameter in node {
+                parameter.mapv_inplace(|v| v - 0.5);
+            }
+        }
+
+        let inputs = [arr2(&[[0.1, 0.2, 0.3, 0.4], [-0.3, 0.5, 0.0, 0.2]]), arr2(&[[1.0, -1.0], [0.5, 0.2]])];
+        let mut expected_result = HashMap::new();
+        expected_result.insert("class".to_owned(), arr2(&[[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]));
+        expected_result.insert("value".to_owned(), arr2(&[[0.5], [-1.0]]));
+        let objectives = [
+            HeadObjective::new("class", Objective::CrossEntropy, 1.0),
+            HeadObjective::new("value", Objective::SumSquaredError, 0.5),
+        ];
+
+        let checks = network.gradient_check(&inputs, &expected_result, &objectives, 1e-5);
+        let nodes: Vec<NodeId> = checks.iter().map(|check| check.layer).collect();
+        assert_eq!(nodes, vec![hidden, gate, class, value]);
+        for check in checks {
+            assert!(check.relative_error < 1e-6, "Node {} has a relative error of {}", check.layer, check.relative_error);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "can not be split into 3 tokens")]
+    fn attention_size_is_checked() {
+        let mut builder = GraphBuilder::new();
+        let input = builder.input(8);
+        builder.attention(input, 3, 1, AttentionMask::default());
+    }
+
+    #[test]
+    fn weighted_heads() {
+        let mut builder = GraphBuilder::new();
+        let input = builder.input(2);
+        let first = builder.layer(input, 1, Activation::Identity);
+        let second = builder.layer(input, 1, Activation::Identity);
+        builder.output("first", first);
+        builder.output("second", second);
+        let mut network = builder.build();
+
+        let training_set = [arr2(&[[0.1, 0.2], [0.3, 0.4]])];
+        let before = network.feed_forward(&training_set);
+
+        let mut expected_result = HashMap::new();
+        expected_result.insert("first".to_owned(), arr2(&[[1.0], [2.0]]));
+        expected_result.insert("second".to_owned(), arr2(&[[1.0], [2.0]]));
+
+        // A head with a zero weight is never updated
+        let objectives = [
+            HeadObjective::new("first", Objective::SumSquaredError, 1.0),
+            HeadObjective::new("second", Objective::SumSquaredError, 0.0),
+        ];
+        network.train(&training_set, &expected_result, &objectives, 2, 0.1);
+        let after = network.feed_forward(&training_set);
+
+        assert!(after["first"] != before["first"]);
+        assert_eq!(after["second"], before["second"]);
+    }
+
+    #[test]
+    fn gradients_are_computed_then_applied() {
+        let mut builder = GraphBuilder::new();
+        let input = builder.input(2);
+        let hidden = builder.layer(input, 3, Activation::TanH);
+        let output = builder.layer(hidden, 1, Activation::Identity);
+        let unused = builder.layer(input, 1, Activation::Identity);
+        builder.output("output", output);
+        builder.output("unused", unused);
+        let mut network = builder.build();
+
+        let inputs = [arr2(&[[0.1, 0.2], [0.3, 0.4]])];
+        let mut expected_result = HashMap::new();
+        expected_result.insert("output".to_owned(), arr2(&[[1.0], [2.0]]));
+        let objectives = [HeadObjective::new("output", Objective::SumSquaredError, 1.0)];
+
+        let before: Vec<Array2<f64>> = network.parameters().iter().flat_map(|node| node.iter().map(|parameter| (*parameter).clone())).collect();
+        let mut gradients = network.compute_gradients(&inputs, &expected_result, &objectives);
+        let unchanged: Vec<Array2<f64>> = network.parameters().iter().flat_map(|node| node.iter().map(|parameter| (*parameter).clone())).collect();
+        assert_eq!(before, unchanged);
+
+        // One entry per node, zero for the layer feeding no trained output
+        assert_eq!(gradients.layers.len(), 4);
+        assert!(gradients.layer(0).is_empty());
+        assert!(gradients.layer(3).iter().all(|gradient| gradient.iter().all(|v| *v == 0.0)));
+
+        gradients.clip(GradientClipping::Norm(0.01));
+        assert!(gradients.norm() <= 0.01 + 1e-12);
+        network.apply_gradients(&gradients, 1.0);
+        let after: Vec<Array2<f64>> = network.parameters().iter().flat_map(|node| node.iter().map(|parameter| (*parameter).clone())).collect();
+        assert!(before != after);
+    }
+}
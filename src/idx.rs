@@ -0,0 +1,285 @@
+// IDX (ubyte) tensor files, the format of MNIST, Fashion-MNIST and EMNIST
+//
+// Layout, every number is big endian :
+// - two zero bytes, one byte for the data type, one byte for the amount of dimensions
+// - one u32 per dimension
+// - the values, last dimension varying the fastest
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use ndarray::{Array2, ArrayD, IxDyn};
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IdxType {
+    UnsignedByte,
+    SignedByte,
+    Short,
+    Int,
+    Float,
+    Double,
+}
+
+impl IdxType {
+    fn from_code(code: u8) -> io::Result<Self> {
+        match code {
+            0x08 => Ok(IdxType::UnsignedByte),
+            0x09 => Ok(IdxType::SignedByte),
+            0x0B => Ok(IdxType::Short),
+            0x0C => Ok(IdxType::Int),
+            0x0D => Ok(IdxType::Float),
+            0x0E => Ok(IdxType::Double),
+            _ => Err(invalid_data(format!("Unknown IDX data type 0x{:02X}", code))),
+        }
+    }
+
+    fn code(&self) -> u8 {
+        match *self {
+            IdxType::UnsignedByte => 0x08,
+            IdxType::SignedByte => 0x09,
+            IdxType::Short => 0x0B,
+            IdxType::Int => 0x0C,
+            IdxType::Float => 0x0D,
+            IdxType::Double => 0x0E,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match *self {
+            IdxType::UnsignedByte | IdxType::SignedByte => 1,
+            IdxType::Short => 2,
+            IdxType::Int | IdxType::Float => 4,
+            IdxType::Double => 8,
+        }
+    }
+
+    // Range of the integer types, values written must fit in it
+    fn bounds(&self) -> Option<(f64, f64)> {
+        match *self {
+            IdxType::UnsignedByte => Some((0.0, 255.0)),
+            IdxType::SignedByte => Some((-128.0, 127.0)),
+            IdxType::Short => Some((-32768.0, 32767.0)),
+            IdxType::Int => Some((-2147483648.0, 2147483647.0)),
+            IdxType::Float | IdxType::Double => None,
+        }
+    }
+}
+
+
+pub struct IdxHeader {
+    pub data_type: IdxType,
+    pub shape: Vec<usize>,
+}
+
+impl IdxHeader {
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header)?;
+        if header[0] != 0 || header[1] != 0 {
+            return Err(invalid_data("Not an IDX file".to_owned()));
+        }
+        let data_type = IdxType::from_code(header[2])?;
+
+        let mut shape = Vec::with_capacity(header[3] as usize);
+        for _ in 0..header[3] {
+            let mut dimension = [0; 4];
+            reader.read_exact(&mut dimension)?;
+            shape.push(u32::from_be_bytes(dimension) as usize);
+        }
+
+        Ok(Self { data_type, shape })
+    }
+
+    // Size of the header in bytes, values start right after it
+    pub fn size(&self) -> usize {
+        4 + 4 * self.shape.len()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.shape.first().cloned().unwrap_or(1)
+    }
+
+    // Values of one entry of the first dimension
+    pub fn row_length(&self) -> usize {
+        self.shape.iter().skip(1).product()
+    }
+}
+
+
+pub fn read_idx<P: AsRef<Path>>(path: P) -> io::Result<ArrayD<f64>> {
+    read_idx_from(BufReader::new(File::open(path)?))
+}
+
+// First dimension becomes the rows, the other dimensions are flattened into the columns
+// e.g. 60000 images of 28×28 give a 60000×784 matrix, 60000 labels give a 60000×1 matrix
+pub fn read_idx_matrix<P: AsRef<Path>>(path: P) -> io::Result<Array2<f64>> {
+    to_matrix(read_idx(path)?)
+}
+
+// The values are read as they come, a header claiming more values than the file holds is an error
+pub fn read_idx_from<R: Read>(mut reader: R) -> io::Result<ArrayD<f64>> {
+    let header = IdxHeader::read(&mut reader)?;
+    let data_type = header.data_type;
+
+    let length = header.shape.iter()
+        .try_fold(data_type.size(), |length, dimension| length.checked_mul(*dimension))
+        .ok_or_else(|| invalid_data(format!("IDX shape {:?} is too large", header.shape)))?;
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length {
+        return Err(invalid_data(format!("Expected {} bytes of values, found {}", length, bytes.len())));
+    }
+
+    let values = bytes.chunks(data_type.size()).map(|value| decode(data_type, value)).collect();
+    ArrayD::from_shape_vec(IxDyn(&header.shape), values).map_err(|e| invalid_data(e.to_string()))
+}
+
+// Only reads the given rows (entries of the first dimension), flattened like read_idx_matrix()
+pub fn read_idx_rows<R: Read + Seek>(mut reader: R, header: &IdxHeader, indices: &[usize]) -> io::Result<Array2<f64>> {
+    let data_type = header.data_type;
+    let row_length = header.row_length();
+    let mut rows = Array2::<f64>::zeros((indices.len(), row_length));
+    let mut bytes = vec![0; row_length * data_type.size()];
+
+    for (i, index) in indices.iter().enumerate() {
+        if *index >= header.rows() {
+            return Err(invalid_input(format!("Row {} out of {} rows", index, header.rows())));
+        }
+        reader.seek(SeekFrom::Start((header.size() + index * bytes.len()) as u64))?;
+        reader.read_exact(&mut bytes)?;
+        for (j, value) in bytes.chunks(data_type.size()).enumerate() {
+            rows[[i, j]] = decode(data_type, value);
+        }
+    }
+    Ok(rows)
+}
+
+pub fn write_idx<P: AsRef<Path>>(path: P, array: &ArrayD<f64>, data_type: IdxType) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_idx_to(&mut writer, array, data_type)?;
+    writer.flush()
+}
+
+pub fn write_idx_to<W: Write>(mut writer: W, array: &ArrayD<f64>, data_type: IdxType) -> io::Result<()> {
+    if array.ndim() > 255 {
+        return Err(invalid_input(format!("IDX files can not store {} dimensions", array.ndim())));
+    }
+    if let Some((minimum, maximum)) = data_type.bounds() {
+        if let Some(value) = array.iter().find(|v| v.fract() != 0.0 || **v < minimum || **v > maximum) {
+            return Err(invalid_input(format!("{} can not be stored as {:?}", value, data_type)));
+        }
+    }
+
+    writer.write_all(&[0, 0, data_type.code(), array.ndim() as u8])?;
+    for dimension in array.shape() {
+        if *dimension > u32::MAX as usize {
+            return Err(invalid_input(format!("Dimension {} is too large for an IDX file", dimension)));
+        }
+        writer.write_all(&(*dimension as u32).to_be_bytes())?;
+    }
+    for value in array.iter() {
+        encode(&mut writer, data_type, *value)?;
+    }
+    Ok(())
+}
+
+pub fn to_matrix(array: ArrayD<f64>) -> io::Result<Array2<f64>> {
+    let rows = if array.ndim() == 0 { 1 } else { array.shape()[0] };
+    let cols = array.shape().iter().skip(1).product();
+    array.into_shape((rows, cols)).map_err(|e| invalid_data(e.to_string()))
+}
+
+
+fn decode(data_type: IdxType, bytes: &[u8]) -> f64 {
+    match data_type {
+        IdxType::UnsignedByte => bytes[0] as f64,
+        IdxType::SignedByte => bytes[0] as i8 as f64,
+        IdxType::Short => i16::from_be_bytes([bytes[0], bytes[1]]) as f64,
+        IdxType::Int => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        IdxType::Float => f32::from_bits(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])) as f64,
+        IdxType::Double => {
+            let mut value = [0; 8];
+            value.copy_from_slice(bytes);
+            f64::from_bits(u64::from_be_bytes(value))
+        },
+    }
+}
+
+fn encode<W: Write>(writer: &mut W, data_type: IdxType, value: f64) -> io::Result<()> {
+    match data_type {
+        IdxType::UnsignedByte => writer.write_all(&[value as u8]),
+        IdxType::SignedByte => writer.write_all(&[value as i8 as u8]),
+        IdxType::Short => writer.write_all(&(value as i16).to_be_bytes()),
+        IdxType::Int => writer.write_all(&(value as i32).to_be_bytes()),
+        IdxType::Float => writer.write_all(&(value as f32).to_bits().to_be_bytes()),
+        IdxType::Double => writer.write_all(&value.to_bits().to_be_bytes()),
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use ndarray::arr2;
+    use super::*;
+
+    #[test]
+    fn reads_images_and_labels() {
+        // Two 2×3 images and their labels, as written by the MNIST tools
+        let mut images = vec![0, 0, 0x08, 3, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 3];
+        images.extend_from_slice(&[0, 1, 2, 3, 4, 5, 250, 251, 252, 253, 254, 255]);
+        let labels = vec![0, 0, 0x08, 1, 0, 0, 0, 2, 7, 3];
+
+        let images = read_idx_from(&images[..]).unwrap();
+        assert_eq!(images.shape(), &[2, 2, 3]);
+        assert_eq!(images[[1, 0, 2]], 252.0);
+        assert_eq!(to_matrix(images).unwrap(), arr2(&[[0.0, 1.0, 2.0, 3.0, 4.0, 5.0], [250.0, 251.0, 252.0, 253.0, 254.0, 255.0]]));
+
+        assert_eq!(to_matrix(read_idx_from(&labels[..]).unwrap()).unwrap(), arr2(&[[7.0], [3.0]]));
+    }
+
+    #[test]
+    fn round_trip_every_type() {
+        let array = arr2(&[[-3.0, 0.0, 7.0], [12.0, -100.0, 127.0]]).into_dyn();
+        let path = env::temp_dir().join("neural_network_idx_round_trip.idx");
+
+        for data_type in &[IdxType::SignedByte, IdxType::Short, IdxType::Int, IdxType::Float, IdxType::Double] {
+            write_idx(&path, &array, *data_type).unwrap();
+            assert_eq!(read_idx(&path).unwrap(), array);
+        }
+        fs::remove_file(&path).unwrap();
+
+        let fractional = arr2(&[[0.1, -2.5e-3]]).into_dyn();
+        let mut buffer = Vec::new();
+        write_idx_to(&mut buffer, &fractional, IdxType::Double).unwrap();
+        assert_eq!(read_idx_from(&buffer[..]).unwrap(), fractional);
+    }
+
+    #[test]
+    fn rejects_invalid_files_and_values() {
+        assert!(read_idx_from(&[1, 0, 0x08, 1, 0, 0, 0, 1, 0][..]).is_err());
+        assert!(read_idx_from(&[0, 0, 0x0A, 1, 0, 0, 0, 1, 0][..]).is_err());
+        // Header announces three values, only two follow
+        assert!(read_idx_from(&[0, 0, 0x08, 1, 0, 0, 0, 3, 1, 2][..]).is_err());
+        // Huge dimensions, overflowing or not, fail without allocating them
+        let overflowing = [0, 0, 0x0E, 3, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 1];
+        assert_eq!(read_idx_from(&overflowing[..]).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        let huge = [0, 0, 0x0E, 2, 255, 255, 255, 255, 0, 1, 0, 0, 1, 2, 3];
+        assert_eq!(read_idx_from(&huge[..]).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        let mut buffer = Vec::new();
+        assert!(write_idx_to(&mut buffer, &arr2(&[[256.0]]).into_dyn(), IdxType::UnsignedByte).is_err());
+        assert!(write_idx_to(&mut buffer, &arr2(&[[0.5]]).into_dyn(), IdxType::Int).is_err());
+    }
+}
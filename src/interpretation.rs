@@ -0,0 +1,247 @@
+// What the network has learned, seen from its inputs
+//
+// Activation maximization searches, by gradient ascent on the input, the input that maximizes one output neuron :
+// objective = output - l2 × ‖input‖² - total_variation × Σ (neighbour difference)², input clamped to range after every step.
+//
+// Attributions explain the prediction of every input row for one output neuron, they have the shape of the inputs
+// so a row can be saved with visualization::save_vector_heatmap(). Signed, a diverging colormap shows them best.
+
+use ndarray::Array2;
+use rand::Rng;
+use rand::distributions::Normal;
+
+use network::NeuralNetwork;
+use data::seeded_rng;
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct ActivationMaximization {
+    pub iterations: usize,
+    pub learning_rate: f64,
+    // Keeps the input small, only the values that matter stand out
+    pub l2: f64,
+    // Keeps neighbouring values close, gives smooth images instead of noise
+    pub total_variation: f64,
+    // Height and width of the input image, neighbours are consecutive values when None
+    pub image: Option<(usize, usize)>,
+    // Valid input values, e.g. (0, 1) for normalized pixels
+    pub range: (f64, f64),
+}
+
+impl ActivationMaximization {
+    pub fn new(iterations: usize, learning_rate: f64) -> Self {
+        Self {
+            iterations,
+            learning_rate,
+            l2: 0.0,
+            total_variation: 0.0,
+            image: None,
+            range: (0.0, 1.0),
+        }
+    }
+
+    pub fn l2(mut self, l2: f64) -> Self {
+        self.l2 = l2;
+        self
+    }
+
+    pub fn total_variation(mut self, total_variation: f64) -> Self {
+        self.total_variation = total_variation;
+        self
+    }
+
+    pub fn image(mut self, height: usize, width: usize) -> Self {
+        self.image = Some((height, width));
+        self
+    }
+
+    pub fn range(mut self, minimum: f64, maximum: f64) -> Self {
+        assert!(minimum < maximum, "Range minimum must be lower than its maximum");
+        self.range = (minimum, maximum);
+        self
+    }
+}
+
+
+impl NeuralNetwork {
+    // 1 × inputs matrix, starting from the middle of the range
+    pub fn maximize_activation(&self, neuron: usize, settings: &ActivationMaximization) -> Array2<f64> {
+        let inputs = self.input_size();
+        if let Some((height, width)) = settings.image {
+            assert_eq!(height * width, inputs, "Image of {}×{} does not match the {} inputs", height, width, inputs);
+        }
+        let output_gradient = self.neuron_gradient(1, neuron);
+
+        let (minimum, maximum) = settings.range;
+        let mut input = Array2::<f64>::from_elem((1, inputs), (minimum + maximum) / 2.0);
+
+        for _ in 0..settings.iterations {
+            let mut gradient = self.input_gradient(&input, &output_gradient);
+            gradient.scaled_add(-2.0 * settings.l2, &input);
+            if settings.total_variation > 0.0 {
+                gradient.scaled_add(-settings.total_variation, &total_variation_gradient(&input, settings.image));
+            }
+
+            input.scaled_add(settings.learning_rate, &gradient);
+            input.mapv_inplace(|v| v.max(minimum).min(maximum));
+        }
+
+        input
+    }
+
+    // Vanilla saliency, gradient of the output neuron with respect to every input
+    pub fn saliency(&self, input: &Array2<f64>, neuron: usize) -> Array2<f64> {
+        let output_gradient = self.neuron_gradient(input.rows(), neuron);
+        self.input_gradient(input, &output_gradient)
+    }
+
+    pub fn gradient_times_input(&self, input: &Array2<f64>, neuron: usize) -> Array2<f64> {
+        self.saliency(input, neuron) * input
+    }
+
+    // Gradients averaged along the straight path from the baseline to the input (midpoint rule), times input - baseline.
+    // The attributions of a row add up to output(input) - output(baseline) as steps grows.
+    // baseline has one row, used for every input row, e.g. a black image
+    pub fn integrated_gradients(&self, input: &Array2<f64>, neuron: usize, baseline: &Array2<f64>, steps: usize) -> Array2<f64> {
+        assert_eq!(baseline.dim(), (1, input.cols()), "Baseline should be one row of {} inputs", input.cols());
+        assert!(steps > 0, "At least one step is needed");
+
+        let difference = input - baseline;
+        let mut gradients = Array2::<f64>::zeros(input.dim());
+        for step in 0..steps {
+            let point = &difference * ((step as f64 + 0.5) / steps as f64) + baseline;
+            gradients += &self.saliency(&point, neuron);
+        }
+        gradients / steps as f64 * difference
+    }
+
+    // Saliency averaged over copies of the input with gaussian noise, less noisy than a single gradient
+    pub fn smooth_grad(&self, input: &Array2<f64>, neuron: usize, samples: usize, deviation: f64, seed: u64) -> Array2<f64> {
+        assert!(samples > 0, "At least one sample is needed");
+
+        let mut rng = seeded_rng(seed);
+        let normal = Normal::new(0.0, deviation);
+        let mut gradients = Array2::<f64>::zeros(input.dim());
+        for _ in 0..samples {
+            let noisy = input.mapv(|v| v + rng.sample(normal));
+            gradients += &self.saliency(&noisy, neuron);
+        }
+        gradients / samples as f64
+    }
+
+    fn neuron_gradient(&self, rows: usize, neuron: usize) -> Array2<f64> {
+        let outputs = self.layers().last().unwrap().outputs();
+        assert!(neuron < outputs, "Neuron {} out of {} outputs", neuron, outputs);
+        let mut output_gradient = Array2::<f64>::zeros((rows, outputs));
+        output_gradient.column_mut(neuron).fill(1.0);
+        output_gradient
+    }
+}
+
+
+// Gradient of Σ (b - a)² over every pair of neighbours (a, b)
+fn total_variation_gradient(input: &Array2<f64>, image: Option<(usize, usize)>) -> Array2<f64> {
+    let (height, width) = image.unwrap_or((1, input.cols()));
+    let mut gradient = Array2::<f64>::zeros(input.dim());
+
+    let mut add_pair = |a: usize, b: usize| {
+        let difference = input[[0, b]] - input[[0, a]];
+        gradient[[0, b]] += 2.0 * difference;
+        gradient[[0, a]] -= 2.0 * difference;
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if x + 1 < width {
+                add_pair(i, i + 1);
+            }
+            if y + 1 < height {
+                add_pair(i, i + width);
+            }
+        }
+    }
+    gradient
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use activation::Activation;
+    use attention::AttentionMask;
+    use super::*;
+
+    fn check_input_gradient(network: &NeuralNetwork, input: &Array2<f64>, neuron: usize) {
+        let gradient = network.saliency(input, neuron);
+        let epsilon = 1e-6;
+        for j in 0..input.cols() {
+            let (mut plus, mut minus) = (input.clone(), input.clone());
+            plus[[0, j]] += epsilon;
+            minus[[0, j]] -= epsilon;
+            let numerical = (network.predict(&plus)[[0, neuron]] - network.predict(&minus)[[0, neuron]]) / (2.0 * epsilon);
+            assert!((numerical - gradient[[0, j]]).abs() < 1e-7, "{} {}", numerical, gradient[[0, j]]);
+        }
+    }
+
+    #[test]
+    fn input_gradient_matches_finite_differences() {
+        let network = NeuralNetworkBuilder::new(3)
+            .layer(4, Activation::TanH)
+            .layer(2, Activation::Softmax)
+            .build();
+        check_input_gradient(&network, &arr2(&[[0.3, -0.2, 0.5]]), 1);
+
+        // Attention layers keep their intermediate values in the cache, not in the shared network
+        let network = NeuralNetworkBuilder::new(6)
+            .transformer_encoder(2, 3, 4, AttentionMask { causal: true, padding: false })
+            .attention(2, 1, AttentionMask::default())
+            .layer(2, Activation::Sigmoid)
+            .build();
+        check_input_gradient(&network, &arr2(&[[0.3, -0.2, 0.5, 0.1, 0.9, -0.4]]), 0);
+    }
+
+    #[test]
+    fn maximizes_the_chosen_neuron() {
+        let mut network = NeuralNetworkBuilder::new(4)
+            .layer(2, Activation::Identity)
+            .build();
+        *network.parameters_mut()[0][0] = arr2(&[[1.0, -1.0], [-1.0, 1.0], [0.5, 0.0], [0.0, 0.0]]);
+
+        let settings = ActivationMaximization::new(50, 0.1).image(2, 2);
+        assert_eq!(network.maximize_activation(0, &settings), arr2(&[[1.0, 0.0, 1.0, 0.5]]));
+        assert_eq!(network.maximize_activation(1, &settings), arr2(&[[0.0, 1.0, 0.5, 0.5]]));
+
+        // Regularization pulls the inputs that do not matter towards 0 and their neighbours
+        let regularized = network.maximize_activation(0, &settings.l2(0.1).total_variation(0.01));
+        assert!(regularized[[0, 3]] < 0.5);
+        assert_eq!(regularized[[0, 0]], 1.0);
+    }
+
+    #[test]
+    fn attributions() {
+        let mut network = NeuralNetworkBuilder::new(3)
+            .layer(1, Activation::Identity)
+            .build();
+        *network.parameters_mut()[0][0] = arr2(&[[2.0], [-1.0], [0.5]]);
+        let input = arr2(&[[1.0, 2.0, 4.0], [0.0, -1.0, 1.0]]);
+
+        // The gradient of a linear network is its weights, wherever the input is
+        let weights = arr2(&[[2.0, -1.0, 0.5], [2.0, -1.0, 0.5]]);
+        assert_eq!(network.saliency(&input, 0), weights);
+        assert_eq!(network.gradient_times_input(&input, 0), arr2(&[[2.0, -2.0, 2.0], [0.0, 1.0, 0.5]]));
+        assert!(network.smooth_grad(&input, 0, 5, 0.1, 3).all_close(&weights, 1e-12));
+
+        // Completeness, attributions add up to the change of the output from the baseline
+        let network = NeuralNetworkBuilder::new(3)
+            .layer(4, Activation::TanH)
+            .layer(2, Activation::Softmax)
+            .build();
+        let baseline = Array2::zeros((1, 3));
+        let attributions = network.integrated_gradients(&input, 1, &baseline, 200);
+        let outputs = network.predict(&input).column(1).to_owned() - network.predict(&baseline)[[0, 1]];
+        for (row, output) in attributions.genrows().into_iter().zip(outputs.iter()) {
+            assert!((row.scalar_sum() - output).abs() < 1e-4);
+        }
+    }
+}
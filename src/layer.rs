@@ -1,23 +1,237 @@
-use rand::distributions::Range;
-use ndarray::{Array, Array2};
+use rand::distributions::{Normal, Range};
+use ndarray::Array2;
 use ndarray_rand::RandomExt;
 use activation::Activation;
+use attention::{MultiHeadAttention, TransformerEncoder, AttentionCache, EncoderCache};
 
 
-pub struct Layer {
-    neurons: usize,
+// Layers are only stored in vectors, moving a large attention layer is rare
+#[allow(clippy::large_enum_variant)]
+pub enum Layer {
+    Dense(Dense),
+    MultiHeadAttention(MultiHeadAttention),
+    TransformerEncoder(TransformerEncoder),
+}
+
+// Intermediate values of a forward pass needed to compute the gradients, see NeuralNetwork::forward_with_cache()
+pub enum LayerCache {
+    // Output of the layer before its activation
+    Dense(Array2<f64>),
+    MultiHeadAttention(Box<AttentionCache>),
+    TransformerEncoder(Box<EncoderCache>),
+}
+
+impl Layer {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Layer::Dense(_) => "dense",
+            Layer::MultiHeadAttention(_) => "multi_head_attention",
+            Layer::TransformerEncoder(_) => "transformer_encoder",
+        }
+    }
+
+    // Padding tokens to give to this layer and the following ones, see AttentionMask
+    pub fn padding(&self, input: &Array2<f64>, previous: Option<Array2<bool>>) -> Option<Array2<bool>> {
+        match *self {
+            Layer::Dense(_) => previous,
+            Layer::MultiHeadAttention(ref layer) => layer.padding(input, previous),
+            Layer::TransformerEncoder(ref layer) => layer.padding(input, previous),
+        }
+    }
+
+    pub fn calculate_activities(&mut self, input: &Array2<f64>, padding: Option<&Array2<bool>>) {
+        match *self {
+            Layer::Dense(ref mut layer) => layer.calculate_activities(input),
+            Layer::MultiHeadAttention(ref mut layer) => layer.calculate_activities(input, padding),
+            Layer::TransformerEncoder(ref mut layer) => layer.calculate_activities(input, padding),
+        }
+    }
+
+    // Same as calculate_activities(), returning the intermediate values instead of keeping them in the layer
+    pub fn forward(&self, input: &Array2<f64>, padding: Option<&Array2<bool>>) -> (LayerCache, Array2<f64>) {
+        match *self {
+            Layer::Dense(ref layer) => {
+                let (output, activities) = layer.forward(input);
+                (LayerCache::Dense(output), activities)
+            },
+            Layer::MultiHeadAttention(ref layer) => {
+                let (cache, activities) = layer.forward(input, padding);
+                (LayerCache::MultiHeadAttention(Box::new(cache)), activities)
+            },
+            Layer::TransformerEncoder(ref layer) => {
+                let (cache, activities) = layer.forward(input, padding);
+                (LayerCache::TransformerEncoder(Box::new(cache)), activities)
+            },
+        }
+    }
+
+    // Output of the layer without caching anything, so it can be used on a shared network
+    pub fn predict(&self, input: &Array2<f64>, padding: Option<&Array2<bool>>) -> Array2<f64> {
+        match *self {
+            Layer::Dense(ref layer) => layer.predict(input),
+            Layer::MultiHeadAttention(ref layer) => layer.predict(input, padding),
+            Layer::TransformerEncoder(ref layer) => layer.predict(input, padding),
+        }
+    }
+
+    // Columns of the input and of the output matrices
+    pub fn inputs(&self) -> usize {
+        match *self {
+            Layer::Dense(ref layer) => layer.weights.rows(),
+            Layer::MultiHeadAttention(ref layer) => layer.sequence_length() * layer.model_size(),
+            Layer::TransformerEncoder(ref layer) => layer.attention.sequence_length() * layer.attention.model_size(),
+        }
+    }
+
+    pub fn outputs(&self) -> usize {
+        match *self {
+            Layer::Dense(ref layer) => layer.weights.cols(),
+            _ => self.inputs(),
+        }
+    }
+
+    pub fn activities(&self) -> &Array2<f64> {
+        match *self {
+            Layer::Dense(ref layer) => &layer.activities,
+            Layer::MultiHeadAttention(ref layer) => &layer.activities,
+            Layer::TransformerEncoder(ref layer) => &layer.activities,
+        }
+    }
+
+    // Delta is the gradient of the error with regard to the activities of the layer
+    // Returns the delta to propagate to the previous layer and the gradients of parameters(), in the same order
+    pub fn compute_gradients(&self, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        match *self {
+            Layer::Dense(ref layer) => layer.compute_gradients(input, delta),
+            Layer::MultiHeadAttention(ref layer) => layer.compute_gradients(input, delta),
+            Layer::TransformerEncoder(ref layer) => layer.compute_gradients(input, delta),
+        }
+    }
+
+    // Same as compute_gradients(), from the intermediate values of forward()
+    pub fn backward(&self, cache: &LayerCache, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        match (self, cache) {
+            (Layer::Dense(layer), LayerCache::Dense(output)) => layer.backward(output, input, delta),
+            (Layer::MultiHeadAttention(layer), LayerCache::MultiHeadAttention(cache)) => layer.backward(cache, input, delta),
+            (Layer::TransformerEncoder(layer), LayerCache::TransformerEncoder(cache)) => layer.backward(cache, input, delta),
+            _ => panic!("Cache of another kind of layer given to a {} layer", self.name()),
+        }
+    }
+
+    pub fn apply_gradients(&mut self, gradients: &[Array2<f64>], learning_rate: f64) {
+        for (parameter, gradient) in self.parameters_mut().into_iter().zip(gradients) {
+            parameter.scaled_add(-learning_rate, gradient);
+        }
+    }
+
+    // Trainable matrices, always in the same order
+    pub fn parameters(&self) -> Vec<&Array2<f64>> {
+        match *self {
+            Layer::Dense(ref layer) => vec![&layer.weights, &layer.bias],
+            Layer::MultiHeadAttention(ref layer) => layer.parameters(),
+            Layer::TransformerEncoder(ref layer) => layer.parameters(),
+        }
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<&mut Array2<f64>> {
+        match *self {
+            Layer::Dense(ref mut layer) => vec![&mut layer.weights, &mut layer.bias],
+            Layer::MultiHeadAttention(ref mut layer) => layer.parameters_mut(),
+            Layer::TransformerEncoder(ref mut layer) => layer.parameters_mut(),
+        }
+    }
+
+    // Added to the error of the network, see Regularization
+    pub fn penalty(&self) -> f64 {
+        match *self {
+            Layer::Dense(ref layer) => layer.regularization.penalty(&layer.weights),
+            _ => 0.0,
+        }
+    }
+}
+
+
+// Initial weights of a Dense layer, the bias always starts at 0.1
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Initializer {
+    // Between the minimum and the maximum
+    Uniform(f64, f64),
+    // Zero mean, with the given standard deviation
+    Normal(f64),
+    // Uniform within ±sqrt(6 / (inputs + neurons)), suited to TanH and Sigmoid layers
+    Xavier,
+    // Normal with a deviation of sqrt(2 / inputs), suited to ReLU layers
+    He,
+}
+
+impl Default for Initializer {
+    fn default() -> Self {
+        Initializer::Uniform(0.0, 1.0)
+    }
+}
+
+impl Initializer {
+    pub fn weights(&self, inputs: usize, neurons: usize) -> Array2<f64> {
+        match *self {
+            Initializer::Uniform(minimum, maximum) => Array2::random((inputs, neurons), Range::new(minimum, maximum)),
+            Initializer::Normal(deviation) => Array2::random((inputs, neurons), Normal::new(0.0, deviation)),
+            Initializer::Xavier => {
+                let limit = (6.0 / (inputs + neurons) as f64).sqrt();
+                Array2::random((inputs, neurons), Range::new(-limit, limit))
+            },
+            Initializer::He => Array2::random((inputs, neurons), Normal::new(0.0, (2.0 / inputs as f64).sqrt())),
+        }
+    }
+}
+
+// Penalty on the weights of a Dense layer : l1 × Σ|w| + l2 × Σw², the bias is not regularized
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+pub struct Regularization {
+    pub l1: f64,
+    pub l2: f64,
+}
+
+impl Regularization {
+    pub fn is_zero(&self) -> bool {
+        self.l1 == 0.0 && self.l2 == 0.0
+    }
+
+    pub fn penalty(&self, weights: &Array2<f64>) -> f64 {
+        if self.is_zero() {
+            return 0.0;
+        }
+        weights.iter().map(|w| self.l1 * w.abs() + self.l2 * w * w).sum()
+    }
+
+    pub fn gradient(&self, weights: &Array2<f64>) -> Array2<f64> {
+        // Subgradient of |w| is 0 at 0
+        weights.mapv(|w| {
+            let sign = if w > 0.0 { 1.0 } else if w < 0.0 { -1.0 } else { 0.0 };
+            self.l1 * sign + 2.0 * self.l2 * w
+        })
+    }
+}
+
+
+pub struct Dense {
     pub weights: Array2<f64>,
     pub bias: Array2<f64>,
     pub output: Array2<f64>,
     pub activities: Array2<f64>,
     pub activation_function: Activation,
+    pub regularization: Regularization,
 }
 
-impl Layer {
+impl Dense {
     pub fn new(neurons: usize, inputs: usize, activation_function: Activation) -> Self {
+        Self::initialized(neurons, inputs, activation_function, Initializer::default())
+    }
+
+    pub fn initialized(neurons: usize, inputs: usize, activation_function: Activation, initializer: Initializer) -> Self {
 
         // Create weights matrix with random values
-        let weights = Array2::<f64>::random((inputs, neurons), Range::new(0.0, 1.0));
+        let weights = initializer.weights(inputs, neurons);
 
         // Create bias matrix with constant value
         let mut bias = Array2::<f64>::zeros((1, neurons));
@@ -26,21 +240,57 @@ impl Layer {
 
 
         Self {
-            neurons,
             weights,
             bias,
             output: Array2::<f64>::zeros((1, 1)),
             activities: Array2::<f64>::zeros((1, 1)),
             activation_function,
+            regularization: Regularization::default(),
         }
     }
 
     pub fn calculate_activities(&mut self, input: &Array2<f64>) {
+        let (output, activities) = self.forward(input);
+        self.output = output;
+        self.activities = activities;
+    }
+
+    pub fn predict(&self, input: &Array2<f64>) -> Array2<f64> {
+        self.activation_function.compute(&(input.dot(&self.weights) + &self.bias))
+    }
+
+    // Output before the activation, then activities
+    pub fn forward(&self, input: &Array2<f64>) -> (Array2<f64>, Array2<f64>) {
 
         // Compute matrix calculation between input and weights
-        self.output = input.dot(&self.weights) + &self.bias;
+        let output = input.dot(&self.weights) + &self.bias;
 
         // Apply activation function
-        self.activities = self.activation_function.compute(&self.output);
+        let activities = self.activation_function.compute(&output);
+        (output, activities)
     }
-}
\ No newline at end of file
+
+    pub fn compute_gradients(&self, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        self.backward(&self.output, input, delta)
+    }
+
+    // Same as compute_gradients(), output being the one of forward()
+    pub fn backward(&self, output: &Array2<f64>, input: &Array2<f64>, delta: &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>) {
+        let result = self.activation_function.compute_loss(delta, output);
+
+        let mut diff_weight = input.t().dot(&result);
+        if !self.regularization.is_zero() {
+            diff_weight += &self.regularization.gradient(&self.weights);
+        }
+
+        // Objective derivative is already averaged over the rows
+        let mut diff_bias = Array2::<f64>::zeros((1, result.cols()));
+        for j in 0..result.cols() {
+            diff_bias[[0, j]] = result.slice(s![.., j]).scalar_sum();
+        }
+
+        let previous_delta = result.dot(&self.weights.t());
+
+        (previous_delta, vec![diff_weight, diff_bias])
+    }
+}
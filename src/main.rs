@@ -3,9 +3,15 @@ extern crate rand;
 extern crate ndarray;
 extern crate ndarray_rand;
 
-extern crate mnist;
 extern crate image;
 
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate bincode;
+extern crate toml;
+
 
 
 
@@ -14,30 +20,75 @@ pub mod layer;
 pub mod activation;
 pub mod objective;
 pub mod network;
-
-
-use rand::distributions::Range;
-use ndarray::{Array2, arr2, arr1};
-use ndarray_rand::RandomExt;
+pub mod attention;
+pub mod graph;
+pub mod gradients;
+pub mod serialization;
+pub mod optimizer;
+pub mod schedule;
+pub mod training;
+pub mod classification;
+pub mod metrics;
+pub mod idx;
+pub mod preprocessing;
+pub mod tabular;
+pub mod data;
+pub mod split;
+pub mod augmentation;
+pub mod visualization;
+pub mod interpretation;
+pub mod summary;
+pub mod config;
+pub mod error;
+pub mod cli;
+
+
+use std::env;
+use std::io;
+use std::process;
+
+use ndarray::Array2;
 use builder::NeuralNetworkBuilder;
 use activation::Activation;
 use objective::Objective;
+use attention::AttentionMask;
+use metrics::{Metric, Average};
+use preprocessing::{Scaler, StandardScaler, MinMaxScaler};
 
 
 
 
 fn main() {
+    let arguments = match cli::Arguments::parse(env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, cli::USAGE);
+            process::exit(2);
+        },
+    };
+
+    let result = match arguments.command.as_str() {
+        "experiment" => run_experiment(&arguments),
+        _ => cli::run(&arguments),
+    };
+    if let Err(error) = result {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    }
+}
 
-    test_mnist();
-
-    //test_simple_softmax();
-
-//    test_addition();
-
-//    test_equal();
-
-    // https://news.ycombinator.com/item?id=18145622
-    //test_basic();
+fn run_experiment(arguments: &cli::Arguments) -> io::Result<()> {
+    match arguments.positional.first().map(|name| name.as_str()) {
+        Some("mnist") => test_mnist(),
+        Some("softmax") => test_simple_softmax(),
+        Some("addition") => test_addition(),
+        Some("equal") => test_equal(),
+        Some("sequence") => test_sequence(),
+        // https://news.ycombinator.com/item?id=18145622
+        Some("basic") => test_basic(),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Experiment should be mnist, softmax, addition, equal, sequence or basic")),
+    }
+    Ok(())
 }
 
 fn test_basic() {
@@ -85,13 +136,69 @@ fn test_basic() {
     println!("{}", network.feed_forward(&training_input_data));
 }
 
+fn test_sequence() {
+    // Classify sequences of one hot tokens : does the first token appear again later in the sequence ?
+    use rand::Rng;
+
+    let sequence_length = 6;
+    let vocabulary_size = 4;
+    let training_size = 2000;
+    let test_size = 200;
+    let epoch = 20;
+    let batch_size = 16;
+    let learning_rate = 0.01;
+
+    println!("Creating neural network");
+    let mut network = NeuralNetworkBuilder::new(sequence_length * vocabulary_size)
+        .transformer_encoder(sequence_length, 2, 16, AttentionMask::default())
+        .layer(2, Activation::Softmax)
+        .build();
+
+    let mut rng = rand::thread_rng();
+    let mut create_set = |size: usize| {
+        let mut input_data = Array2::<f64>::zeros((size, sequence_length * vocabulary_size));
+        let mut expected_result = Array2::<f64>::zeros((size, 2));
+
+        for i in 0..size {
+            let tokens: Vec<usize> = (0..sequence_length).map(|_| rng.gen_range(0, vocabulary_size)).collect();
+            for (position, token) in tokens.iter().enumerate() {
+                input_data[[i, position * vocabulary_size + token]] = 1.0;
+            }
+            let repeated = tokens[1..].contains(&tokens[0]);
+            expected_result[[i, repeated as usize]] = 1.0;
+        }
+
+        (input_data, expected_result)
+    };
+
+    println!("Preparing training set");
+    let (mut training_input_data, training_expected_result) = create_set(training_size);
+
+    println!("Preparing test set");
+    let (test_input_data, test_expected_result) = create_set(test_size);
+
+    println!("Starting training");
+    for i in 0..epoch {
+        println!("Epoch {}", i);
+        network.train(
+            &mut training_input_data,
+            training_expected_result.clone(),
+            Objective::CrossEntropy,
+            batch_size,
+            learning_rate
+        );
+    }
+
+    let evaluation = network.evaluate(&test_input_data, &test_expected_result, &Objective::CrossEntropy, &[Metric::Accuracy]).unwrap();
+    println!("Test set; {}", evaluation);
+}
+
 fn test_equal() {
     let training_size = 100000;
     let test_size = 100;
     let batch_size = 1000;
     let epoch = 8;
     let learning_rate = 0.01;
-    let denominator = 1000000.0;
 
     println!("Creating neural network");
     let mut network = NeuralNetworkBuilder::new(1)
@@ -106,11 +213,17 @@ fn test_equal() {
     let mut training_expected_result = Array2::<f64>::zeros((training_size, 1));
 
     for i in 0..training_size {
-        training_input_data[[i, 0]] = i as f64 / denominator;
+        training_input_data[[i, 0]] = i as f64;
 
-        training_expected_result[[i, 0]] = i as f64 / denominator;
+        training_expected_result[[i, 0]] = i as f64;
     }
 
+    println!("Scaling training set");
+    network.input_scaler = Some(Scaler::Standard(StandardScaler::fit(&training_input_data)));
+    network.target_scaler = Some(Scaler::Standard(StandardScaler::fit(&training_expected_result)));
+    let mut training_input_data = network.preprocess(&training_input_data);
+    let training_expected_result = network.preprocess_targets(&training_expected_result);
+
     println!("Preparing test set");
     let mut test_input_data = Array2::<f64>::zeros((test_size, 1));
     let mut test_expected_result = Array2::<f64>::zeros((test_size, 1));
@@ -120,6 +233,8 @@ fn test_equal() {
 
         test_expected_result[[i, 0]] = i as f64 + 1000.0;
     }
+    let test_input_data = network.preprocess(&test_input_data);
+    let test_expected_result = network.preprocess_targets(&test_expected_result);
 
 
     println!("Starting training");
@@ -135,13 +250,8 @@ fn test_equal() {
     }
 
 
-    let result_test = network.feed_forward(&test_input_data);
-
-    let mut good_guesses = 0;
-
-    for row in 0..result_test.rows() {
-        println!("(Result, Expected) => ({}, {})", result_test[[row, 0]], test_expected_result[[row, 0]]);
-    }
+    let evaluation = network.evaluate(&test_input_data, &test_expected_result, &Objective::SumSquaredError, &[Metric::MeanAbsoluteError, Metric::RootMeanSquaredError, Metric::R2]).unwrap();
+    println!("Test set; {}", evaluation);
 }
 
 fn test_addition() {
@@ -152,11 +262,9 @@ fn test_addition() {
     let mut training_input_data = Array2::<f64>::zeros((training_size, 2));
     let mut training_expected_result = Array2::<f64>::zeros((training_size, 1));
 
-    let denominator = 1000000.0;
-
     for i in 0..training_size {
-        training_input_data[[i, 0]] = i as f64 / denominator;
-        training_input_data[[i, 1]] = (i + i) as f64 / denominator;
+        training_input_data[[i, 0]] = i as f64;
+        training_input_data[[i, 1]] = (i + i) as f64;
 
         training_expected_result[[i, 0]] = training_input_data[[i, 0]] + training_input_data[[i, 1]];
     }
@@ -177,6 +285,14 @@ fn test_addition() {
         .layer(1, Activation::Identity)
         .build();
 
+    println!("Scaling data");
+    network.input_scaler = Some(Scaler::Standard(StandardScaler::fit(&training_input_data)));
+    network.target_scaler = Some(Scaler::Standard(StandardScaler::fit(&training_expected_result)));
+    let mut training_input_data = network.preprocess(&training_input_data);
+    let training_expected_result = network.preprocess_targets(&training_expected_result);
+    let test_input_data = network.preprocess(&test_input_data);
+    let test_expected_result = network.preprocess_targets(&test_expected_result);
+
     println!("Starting training");
 
     for _ in 0..1 {
@@ -191,23 +307,25 @@ fn test_addition() {
     }
 
 
-    let result_test = network.feed_forward(&test_input_data);
-
-    let mut good_guesses = 0;
-
-    for row in 0..result_test.rows() {
-        println!("(Result, Expected) => ({}, {})", result_test[[row, 0]], test_expected_result[[row, 0]]);
-
-    }
-    println!("Number of good guesses : {:?}", good_guesses);
-    println!("Number of result rows : {:?}", result_test.rows());
-    println!("{}% of good guesses", (good_guesses as f64 / result_test.rows() as f64) * 100.0);
+    let evaluation = network.evaluate(&test_input_data, &test_expected_result, &Objective::SumSquaredError, &[Metric::MeanAbsoluteError, Metric::RootMeanSquaredError, Metric::R2]).unwrap();
+    println!("Test set; {}", evaluation);
 }
 
 
 fn test_mnist() {
-    use mnist::{Mnist, MnistBuilder};
-
+    use std::env;
+    use std::path::PathBuf;
+    use idx::read_idx_matrix;
+    use split::{Split, stratified_split};
+    use data::{DataLoader, ArrayDataset};
+    use augmentation::{Augmenter, Augmentation};
+    use visualization::{Colormap, save_vector_heatmap, save_weight_grid};
+    use layer::Layer;
+    use interpretation::ActivationMaximization;
+    use metrics::to_classes;
+
+    // Any MNIST-format dataset works : Fashion-MNIST, EMNIST, ...
+    let data_directory = PathBuf::from(env::var("MNIST_DIRECTORY").unwrap_or_else(|_| "data".to_owned()));
     let training_set_size = 1; // 50000
     let test_set_size: usize = 1; // 10000
     let epoch = 10;
@@ -221,45 +339,35 @@ fn test_mnist() {
         .layer(512, Activation::ReLU)
         .layer(10, Activation::Softmax)
         .build();
+    println!("{}", network.summary());
 
-    println!("Building MNIST data");
-    let Mnist { trn_img, trn_lbl, val_img, val_lbl, tst_img, tst_lbl } = MnistBuilder::new()
-        .label_format_digit()
-        .training_set_length(training_set_size as u32)
-        .validation_set_length(10_000)
-        .test_set_length(test_set_size as u32)
-        .finalize();
+    println!("Loading MNIST data from {}", data_directory.display());
+    let trn_img = read_idx_matrix(data_directory.join("train-images-idx3-ubyte")).unwrap();
+    let trn_lbl = read_idx_matrix(data_directory.join("train-labels-idx1-ubyte")).unwrap();
+    let tst_img = read_idx_matrix(data_directory.join("t10k-images-idx3-ubyte")).unwrap();
+    let tst_lbl = read_idx_matrix(data_directory.join("t10k-labels-idx1-ubyte")).unwrap();
 
 
+    // Pixels from 0..255 to 0..1, saved with the network so predictions get the same scaling
+    network.input_scaler = Some(Scaler::MinMax(MinMaxScaler {
+        minimum: Array2::zeros((1, 28 * 28)),
+        maximum: Array2::from_elem((1, 28 * 28), 255.0),
+        range: (0.0, 1.0),
+    }));
 
     println!("Preparing training set");
-    let mut training_input_data = Array2::<f64>::zeros((training_set_size, 28 * 28));
-    let mut training_expected_result = Array2::<f64>::zeros((training_set_size, 10));
+    let training_input_data = network.preprocess(&trn_img.slice(s![..training_set_size, ..]).to_owned());
+    let training_expected_result = to_one_hot(&trn_lbl.slice(s![..training_set_size, ..]).to_owned(), 10);
 
-    for i in 0..training_set_size {
-        for j in 0..(28*28) {
-            training_input_data[[i, j]] = trn_img[i * 784 + j] as f64;
-        }
-        training_expected_result[[i, trn_lbl[i] as usize]] = 1.0;
-    }
-
-    println!("Normalizing training set");
-    training_input_data /= 255.0;
+    println!("Keeping 10% of the training set for validation");
+    let Split { training, validation, .. } = stratified_split(&training_input_data, &training_expected_result, 0.1, 0.0, 0);
+    let training_input_data = training.inputs;
+    let training_expected_result = training.targets;
 
 
     println!("Preparing test set");
-    let mut test_input_data = Array2::<f64>::zeros((test_set_size, 28 * 28));
-    let mut test_expected_result = Array2::<f64>::zeros((test_set_size, 10));
-
-    for i in 0..test_set_size {
-        for j in 0..(28*28) {
-            test_input_data[[i, j]] = tst_img[i * 784 + j] as f64;
-        }
-        test_expected_result[[i, tst_lbl[i] as usize]] = 1.0;
-    }
-
-    println!("Normalizing test set");
-    test_input_data /= 255.0;
+    let test_input_data = network.preprocess(&tst_img.slice(s![..test_set_size, ..]).to_owned());
+    let test_expected_result = to_one_hot(&tst_lbl.slice(s![..test_set_size, ..]).to_owned(), 10);
 
 
 
@@ -267,90 +375,58 @@ fn test_mnist() {
 
     println!("Starting training");
 
+    // Every batch gets slightly moved, rotated and zoomed digits
+    let augmenter = Augmenter::new(28, 28, 0)
+        .then(Augmentation::Shift(2))
+        .then(Augmentation::Rotation(10.0))
+        .then(Augmentation::Scale(0.9, 1.1));
+    let mut loader = DataLoader::new(ArrayDataset::new(&training_input_data, &training_expected_result), batch_size)
+        .augment(augmenter);
+
     for i in 0..epoch {
-        println!("Epoch {}", i);
-        network.train(
-            &mut training_input_data,
-            training_expected_result.clone(),
-            objective_function,
-            batch_size,
-            learning_rate
-        );
+        let error = network.train_epoch(&mut loader, objective_function, learning_rate).unwrap();
+        println!("Epoch {}; error: {}", i, error);
     }
 
 
 
-    let result_test = network.feed_forward(&test_input_data);
-
-    let mut good_guesses = 0;
-
-    for row in 0..result_test.rows() {
-        let mut my_index = 0;
-        for i in 0..result_test.cols() {
-            if result_test[[row as usize, i]] > result_test[[row as usize, my_index]] {
-                my_index = i;
-            }
-        }
-        if my_index == tst_lbl[row as usize] as usize {
-            good_guesses += 1;
-        }
+    network.set_labels(&["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"]).unwrap();
+    if validation.inputs.rows() > 0 {
+        println!("Validation set; {}", network.evaluate(&validation.inputs, &validation.targets, &objective_function, &[Metric::Accuracy]).unwrap());
     }
-    println!("Number of good guesses : {:?}", good_guesses);
-    println!("Number of result rows : {:?}", result_test.rows());
-    println!("{}% of good guesses", (good_guesses as f64 / result_test.rows() as f64) * 100.0);
-
-
+    let evaluation = network.evaluate(&test_input_data, &test_expected_result, &objective_function, &[Metric::Accuracy, Metric::TopKAccuracy(3), Metric::F1(Average::Macro)]).unwrap();
+    println!("Test set; {}", evaluation);
 
+    println!("Saving trained network");
+    network.save_binary("mnist.model").unwrap();
 
 
 
 
 
-/*
-    println!("Expected input for zero : ");
-    let expected_input = network.get_expected_input(&arr2(&[[0., 0., 0., 3., 0., 0., 0., 0., 0., 0.]]));
-    println!("{:?}", 255.0 * &expected_input);
 
 
-    let mut my_vec: Vec<f64> = expected_input.row(0).to_vec();
 
-    let min_value = my_vec.iter().cloned().fold(0./0., f64::min);
-    let max_value = my_vec.iter().cloned().fold(0./0., f64::max);
-    for i in 0..my_vec.len() {
-        my_vec[i] = (my_vec[i] + min_value) / (min_value + max_value) * 255.0;
+    println!("Saving heatmaps");
+    // Inputs are compared after preprocessing, pixels between 0 and 1
+    let settings = ActivationMaximization::new(200, 0.1)
+        .image(28, 28)
+        .range(0.0, 1.0)
+        .l2(0.01)
+        .total_variation(0.05);
+    for (digit, name) in ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"].iter().enumerate() {
+        let expected_input = network.maximize_activation(digit, &settings);
+        save_vector_heatmap(format!("{}.png", name), expected_input.row(0), 28, 28, Colormap::Viridis).unwrap();
     }
-
-    let mut pixels: [u8; 784] = [0; 28 * 28];
-
-    for i in 0..my_vec.len() {
-        pixels[i] = my_vec[i] as u8;
+    if let Layer::Dense(ref layer) = network.layers()[0] {
+        save_weight_grid("first_layer_weights.png", &layer.weights, 28, 28, Colormap::Coolwarm).unwrap();
     }
 
-
-
-
-
-    use image::ColorType;
-    use image::png::PNGEncoder;
-    use std::fs::File;
-
-    let output = File::create("zero.png").unwrap();
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(&pixels, 28, 28, ColorType::Gray(8));
-
-*/
-
-
-    create_image_heat_map("zero", &network.get_expected_input(&arr2(&[[1., 0., 0., 0., 0., 0., 0., 0., 0., 0.]])));
-    create_image_heat_map("one", &network.get_expected_input(&arr2(&[[0., 1., 0., 0., 0., 0., 0., 0., 0., 0.]])));
-    create_image_heat_map("two", &network.get_expected_input(&arr2(&[[0., 0., 1., 0., 0., 0., 0., 0., 0., 0.]])));
-    create_image_heat_map("three", &network.get_expected_input(&arr2(&[[0., 0., 0., 1., 0., 0., 0., 0., 0., 0.]])));
-    create_image_heat_map("four", &network.get_expected_input(&arr2(&[[0., 0., 0., 0., 1., 0., 0., 0., 0., 0.]])));
-    create_image_heat_map("five", &network.get_expected_input(&arr2(&[[0., 0., 0., 0., 0., 1., 0., 0., 0., 0.]])));
-    create_image_heat_map("six", &network.get_expected_input(&arr2(&[[0., 0., 0., 0., 0., 0., 1., 0., 0., 0.]])));
-    create_image_heat_map("seven", &network.get_expected_input(&arr2(&[[0., 0., 0., 0., 0., 0., 0., 1., 0., 0.]])));
-    create_image_heat_map("eight", &network.get_expected_input(&arr2(&[[0., 0., 0., 0., 0., 0., 0., 0., 1., 0.]])));
-    create_image_heat_map("nine", &network.get_expected_input(&arr2(&[[0., 0., 0., 0., 0., 0., 0., 0., 0., 1.]])));
+    // Pixels that made the network choose its class for the first test digit, compared with a black image
+    let digit = test_input_data.slice(s![..1, ..]).to_owned();
+    let class = to_classes(&network.predict(&digit))[0];
+    let attributions = network.integrated_gradients(&digit, class, &Array2::zeros((1, 28 * 28)), 50);
+    save_vector_heatmap("test_digit_attributions.png", attributions.row(0), 28, 28, Colormap::Coolwarm).unwrap();
 
 }
 
@@ -428,37 +504,11 @@ fn test_simple_softmax() {
 }
 
 
-fn create_image_heat_map(name: &str, expected_input: &Array2<f64>) {
-
-
-    let mut my_vec: Vec<f64> = expected_input.row(0).to_vec();
-
-    let min_value = my_vec.iter().cloned().fold(0./0., f64::min);
-    let max_value = my_vec.iter().cloned().fold(0./0., f64::max);
-    for i in 0..my_vec.len() {
-        my_vec[i] = (my_vec[i] + min_value) / (min_value + max_value) * 255.0;
+// One row per label, labels being the class indices stored in the first column
+fn to_one_hot(labels: &Array2<f64>, classes: usize) -> Array2<f64> {
+    let mut one_hot = Array2::<f64>::zeros((labels.rows(), classes));
+    for (i, label) in labels.column(0).iter().enumerate() {
+        one_hot[[i, *label as usize]] = 1.0;
     }
-
-    let mut pixels: [u8; 784] = [0; 28 * 28];
-
-    for i in 0..my_vec.len() {
-        pixels[i] = my_vec[i] as u8;
-    }
-
-
-
-
-
-    use image::ColorType;
-    use image::png::PNGEncoder;
-    use std::fs::File;
-
-    let mut file_name = name.to_owned();
-    file_name.push_str(".png");
-
-    let output = File::create(file_name).unwrap();
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(&pixels, 28, 28, ColorType::Gray(8));
-
-    // TODO : how to have colors on heatmap ?
-}
\ No newline at end of file
+    one_hot
+}
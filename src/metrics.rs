@@ -0,0 +1,648 @@
+// Evaluation metrics
+//
+// Classification metrics accept either one column of probabilities (binary classification, threshold at 0.5)
+// or one column per class (one hot expected outputs, predicted class is the greatest output).
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+
+use ndarray::{Array2, ArrayView1, Axis};
+
+use network::NeuralNetwork;
+use objective::Objective;
+use classification::{argmax, top_k};
+use data::{Dataset, ArrayDataset};
+use error::Error;
+
+
+// Rows given to predict() at once by evaluate()
+pub const EVALUATION_BATCH_SIZE: usize = 256;
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Average {
+    // Computed over every prediction at once
+    Micro,
+    // Unweighted mean of the score of every class
+    Macro,
+    // Mean of the score of every class, weighted by the amount of expected rows of the class
+    Weighted,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    // Classification
+    Accuracy,
+    Precision(Average),
+    Recall(Average),
+    F1(Average),
+    RocAuc,
+    PrAuc,
+    LogLoss,
+    TopKAccuracy(usize),
+
+    // Regression
+    #[serde(rename = "mae")]
+    MeanAbsoluteError,
+    #[serde(rename = "rmse")]
+    RootMeanSquaredError,
+    R2,
+    #[serde(rename = "mape")]
+    MeanAbsolutePercentageError,
+}
+
+impl Metric {
+    pub fn name(&self) -> String {
+        match *self {
+            Metric::Accuracy => "accuracy".to_owned(),
+            Metric::Precision(average) => format!("precision_{}", average_name(average)),
+            Metric::Recall(average) => format!("recall_{}", average_name(average)),
+            Metric::F1(average) => format!("f1_{}", average_name(average)),
+            Metric::RocAuc => "roc_auc".to_owned(),
+            Metric::PrAuc => "pr_auc".to_owned(),
+            Metric::LogLoss => "log_loss".to_owned(),
+            Metric::TopKAccuracy(k) => format!("top_{}_accuracy", k),
+            Metric::MeanAbsoluteError => "mae".to_owned(),
+            Metric::RootMeanSquaredError => "rmse".to_owned(),
+            Metric::R2 => "r2".to_owned(),
+            Metric::MeanAbsolutePercentageError => "mape".to_owned(),
+        }
+    }
+
+    pub fn compute(&self, output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
+        assert_eq!(output.dim(), expected_output.dim(), "Output and expected output do not have the same shape");
+
+        let classes = output.cols().max(2);
+        match *self {
+            Metric::Accuracy => accuracy(&to_classes(output), &to_classes(expected_output)),
+            Metric::Precision(average) => precision(&confusion_matrix(&to_classes(output), &to_classes(expected_output), classes), average),
+            Metric::Recall(average) => recall(&confusion_matrix(&to_classes(output), &to_classes(expected_output), classes), average),
+            Metric::F1(average) => f1(&confusion_matrix(&to_classes(output), &to_classes(expected_output), classes), average),
+            Metric::RocAuc => one_vs_rest(output, expected_output, roc_auc),
+            Metric::PrAuc => one_vs_rest(output, expected_output, pr_auc),
+            Metric::LogLoss => log_loss(output, expected_output),
+            Metric::TopKAccuracy(k) => top_k_accuracy(output, &to_classes(expected_output), k),
+            Metric::MeanAbsoluteError => mean_absolute_error(output, expected_output),
+            Metric::RootMeanSquaredError => root_mean_squared_error(output, expected_output),
+            Metric::R2 => r2(output, expected_output),
+            Metric::MeanAbsolutePercentageError => mean_absolute_percentage_error(output, expected_output),
+        }
+    }
+}
+
+fn average_name(average: Average) -> &'static str {
+    match average {
+        Average::Micro => "micro",
+        Average::Macro => "macro",
+        Average::Weighted => "weighted",
+    }
+}
+
+
+pub struct Evaluation {
+    pub loss: f64,
+    pub metrics: Vec<(Metric, f64)>,
+}
+
+impl Evaluation {
+    pub fn metric(&self, name: &str) -> Option<f64> {
+        self.metrics.iter().find(|&&(metric, _)| metric.name() == name).map(|&(_, value)| value)
+    }
+}
+
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "loss: {}", self.loss)?;
+        for &(metric, value) in &self.metrics {
+            write!(f, "; {}: {}", metric.name(), value)?;
+        }
+        Ok(())
+    }
+}
+
+impl NeuralNetwork {
+    pub fn evaluate(&self, inputs: &Array2<f64>, targets: &Array2<f64>, objective_function: &Objective, metrics: &[Metric]) -> io::Result<Evaluation> {
+        self.evaluate_in_batches(&ArrayDataset::new(inputs, targets), objective_function, metrics, EVALUATION_BATCH_SIZE)
+    }
+
+    // Rows are read from the dataset batch_size at a time and the metrics updated batch by batch, so memory does not
+    // grow with the dataset. Areas under curves are computed on binned scores, see RunningMetrics
+    pub fn evaluate_in_batches<D: Dataset>(&self, dataset: &D, objective_function: &Objective, metrics: &[Metric], batch_size: usize) -> io::Result<Evaluation> {
+        if batch_size == 0 {
+            return Err(Error::ZeroBatchSize.into());
+        }
+        if dataset.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Nothing to evaluate"));
+        }
+
+        let rows = dataset.len();
+        let mut loss = 0.0;
+        let mut running_metrics = RunningMetrics::new(metrics);
+        let mut start = 0;
+
+        while start < rows {
+            let end = rows.min(start + batch_size);
+            let indices: Vec<usize> = (start..end).collect();
+            let (input, expected_output) = dataset.get(&indices)?;
+            let output = self.predict(&input);
+
+            // Objectives are averaged over the rows of the batch
+            loss += objective_function.calculate_error(&output, &expected_output) * indices.len() as f64;
+            if !metrics.is_empty() {
+                running_metrics.update(&output, &expected_output);
+            }
+            start = end;
+        }
+
+        Ok(Evaluation {
+            loss: loss / rows as f64,
+            metrics: metrics.iter().cloned().zip(running_metrics.values()).collect(),
+        })
+    }
+}
+
+
+// Score bins of the running ROC and PR areas, thresholds are exact to 1 / AUC_BINS
+pub const AUC_BINS: usize = 200;
+
+// Metrics of an epoch updated batch by batch, so only statistics of the outputs are kept and not the outputs themselves.
+// Values are the ones of Metric::compute() on every row at once, except the areas under curves that are computed
+// on scores rounded to AUC_BINS bins.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunningMetrics {
+    metrics: Vec<Metric>,
+    statistics: Vec<Statistic>,
+    rows: usize,
+    values: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum Statistic {
+    // Created by the first batch, the amount of classes is not known before
+    Empty,
+    // Rows are expected classes, columns are predicted classes
+    Confusion(Array2<usize>),
+    Count(usize),
+    Sum(f64),
+    // Positive and negative counts of every score bin, for every column
+    Histograms(Vec<(Vec<usize>, Vec<usize>)>),
+    // Residuals, then count, mean and sum of squared deviations of the expected values, for every column
+    Variance(Vec<(f64, ColumnVariance)>),
+}
+
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+struct ColumnVariance {
+    count: f64,
+    mean: f64,
+    deviations: f64,
+}
+
+impl ColumnVariance {
+    fn of(values: ArrayView1<f64>) -> Self {
+        let count = values.len() as f64;
+        let mean = values.scalar_sum() / count;
+        let deviations = values.iter().map(|v| (v - mean).powi(2)).sum();
+        Self { count, mean, deviations }
+    }
+
+    // Parallel algorithm of Chan et al.
+    fn merge(&self, other: &Self) -> Self {
+        if self.count == 0.0 {
+            return *other;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        Self {
+            count,
+            mean: self.mean + delta * other.count / count,
+            deviations: self.deviations + other.deviations + delta * delta * self.count * other.count / count,
+        }
+    }
+}
+
+impl RunningMetrics {
+    pub fn new(metrics: &[Metric]) -> Self {
+        Self {
+            metrics: metrics.to_vec(),
+            statistics: metrics.iter().map(|_| Statistic::Empty).collect(),
+            rows: 0,
+            values: 0,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn update(&mut self, output: &Array2<f64>, expected_output: &Array2<f64>) {
+        assert_eq!(output.dim(), expected_output.dim(), "Output and expected output do not have the same shape");
+        if output.rows() == 0 {
+            return;
+        }
+
+        let classes = output.cols().max(2);
+        for (metric, statistic) in self.metrics.iter().zip(self.statistics.iter_mut()) {
+            if let Statistic::Empty = *statistic {
+                *statistic = empty_statistic(*metric, output.cols(), classes);
+            }
+
+            match (*metric, statistic) {
+                (Metric::Accuracy, &mut Statistic::Confusion(ref mut matrix)) |
+                (Metric::Precision(_), &mut Statistic::Confusion(ref mut matrix)) |
+                (Metric::Recall(_), &mut Statistic::Confusion(ref mut matrix)) |
+                (Metric::F1(_), &mut Statistic::Confusion(ref mut matrix)) => {
+                    *matrix += &confusion_matrix(&to_classes(output), &to_classes(expected_output), classes);
+                },
+                (Metric::TopKAccuracy(k), &mut Statistic::Count(ref mut count)) => {
+                    let expected = to_classes(expected_output);
+                    *count += output.genrows().into_iter().zip(&expected).filter(|&(row, class)| top_k(row, k).contains(class)).count();
+                },
+                (Metric::RocAuc, &mut Statistic::Histograms(ref mut histograms)) |
+                (Metric::PrAuc, &mut Statistic::Histograms(ref mut histograms)) => {
+                    for (j, &mut (ref mut positives, ref mut negatives)) in histograms.iter_mut().enumerate() {
+                        for (score, expected) in output.column(j).iter().zip(expected_output.column(j)) {
+                            let bin = ((score.clamp(0.0, 1.0) * AUC_BINS as f64) as usize).min(AUC_BINS - 1);
+                            if *expected >= 0.5 { positives[bin] += 1 } else { negatives[bin] += 1 }
+                        }
+                    }
+                },
+                (Metric::LogLoss, &mut Statistic::Sum(ref mut sum)) => *sum += log_loss(output, expected_output) * output.rows() as f64,
+                (Metric::MeanAbsoluteError, &mut Statistic::Sum(ref mut sum)) => *sum += (output - expected_output).map(|v| v.abs()).scalar_sum(),
+                (Metric::RootMeanSquaredError, &mut Statistic::Sum(ref mut sum)) => *sum += (output - expected_output).map(|v| v * v).scalar_sum(),
+                (Metric::MeanAbsolutePercentageError, &mut Statistic::Sum(ref mut sum)) => {
+                    *sum += mean_absolute_percentage_error(output, expected_output) * output.len() as f64;
+                },
+                (Metric::R2, &mut Statistic::Variance(ref mut columns)) => {
+                    for (j, &mut (ref mut residual, ref mut variance)) in columns.iter_mut().enumerate() {
+                        *residual += output.column(j).iter().zip(expected_output.column(j)).map(|(o, e)| (e - o).powi(2)).sum::<f64>();
+                        *variance = variance.merge(&ColumnVariance::of(expected_output.column(j)));
+                    }
+                },
+                _ => panic!("Every batch should have {} columns", output.cols()),
+            }
+        }
+        self.rows += output.rows();
+        self.values += output.len();
+    }
+
+    // NAN before the first batch
+    pub fn values(&self) -> Vec<f64> {
+        if self.rows == 0 {
+            return vec![f64::NAN; self.metrics.len()];
+        }
+
+        self.metrics.iter().zip(&self.statistics).map(|(metric, statistic)| match (*metric, statistic) {
+            (Metric::Accuracy, Statistic::Confusion(matrix)) => matrix.diag().scalar_sum() as f64 / self.rows as f64,
+            (Metric::Precision(average), Statistic::Confusion(matrix)) => precision(matrix, average),
+            (Metric::Recall(average), Statistic::Confusion(matrix)) => recall(matrix, average),
+            (Metric::F1(average), Statistic::Confusion(matrix)) => f1(matrix, average),
+            (Metric::TopKAccuracy(_), Statistic::Count(count)) => *count as f64 / self.rows as f64,
+            (Metric::RocAuc, Statistic::Histograms(histograms)) => mean(histograms.iter().map(|(p, n)| binned_roc_auc(p, n))),
+            (Metric::PrAuc, Statistic::Histograms(histograms)) => mean(histograms.iter().map(|(p, n)| binned_pr_auc(p, n))),
+            (Metric::LogLoss, Statistic::Sum(sum)) => *sum / self.rows as f64,
+            (Metric::RootMeanSquaredError, Statistic::Sum(sum)) => (*sum / self.values as f64).sqrt(),
+            (Metric::MeanAbsoluteError, Statistic::Sum(sum)) |
+            (Metric::MeanAbsolutePercentageError, Statistic::Sum(sum)) => *sum / self.values as f64,
+            (Metric::R2, Statistic::Variance(columns)) => mean(columns.iter().map(|(residual, variance)| {
+                if variance.deviations == 0.0 {
+                    if *residual == 0.0 { 1.0 } else { 0.0 }
+                } else {
+                    1.0 - residual / variance.deviations
+                }
+            })),
+            _ => unreachable!(),
+        }).collect()
+    }
+}
+
+fn empty_statistic(metric: Metric, columns: usize, classes: usize) -> Statistic {
+    match metric {
+        Metric::Accuracy | Metric::Precision(_) | Metric::Recall(_) | Metric::F1(_) => Statistic::Confusion(Array2::zeros((classes, classes))),
+        Metric::TopKAccuracy(_) => Statistic::Count(0),
+        Metric::RocAuc | Metric::PrAuc => Statistic::Histograms(vec![(vec![0; AUC_BINS], vec![0; AUC_BINS]); columns]),
+        Metric::LogLoss | Metric::MeanAbsoluteError | Metric::RootMeanSquaredError | Metric::MeanAbsolutePercentageError => Statistic::Sum(0.0),
+        Metric::R2 => Statistic::Variance(vec![(0.0, ColumnVariance::default()); columns]),
+    }
+}
+
+fn mean<I: ExactSizeIterator<Item = f64>>(values: I) -> f64 {
+    let count = values.len() as f64;
+    values.sum::<f64>() / count
+}
+
+// Same as roc_auc(), scores of a bin are equal
+fn binned_roc_auc(positives: &[usize], negatives: &[usize]) -> f64 {
+    let total_positives: usize = positives.iter().sum();
+    let total_negatives: usize = negatives.iter().sum();
+    if total_positives == 0 || total_negatives == 0 {
+        return 0.5;
+    }
+
+    // Pairs where the positive is scored above the negative, ties count for half
+    let mut pairs = 0.0;
+    let mut negatives_below = 0;
+    for (p, n) in positives.iter().zip(negatives) {
+        pairs += *p as f64 * (negatives_below as f64 + *n as f64 / 2.0);
+        negatives_below += n;
+    }
+    pairs / (total_positives as f64 * total_negatives as f64)
+}
+
+// Average precision, every row of a bin is predicted positive at once
+fn binned_pr_auc(positives: &[usize], negatives: &[usize]) -> f64 {
+    let total_positives: usize = positives.iter().sum();
+    if total_positives == 0 {
+        return 0.0;
+    }
+
+    let mut true_positives = 0;
+    let mut predicted = 0;
+    let mut average_precision = 0.0;
+    for (p, n) in positives.iter().zip(negatives).rev() {
+        true_positives += p;
+        predicted += p + n;
+        if *p > 0 {
+            average_precision += *p as f64 * true_positives as f64 / predicted as f64;
+        }
+    }
+    average_precision / total_positives as f64
+}
+
+
+// Class of every row
+pub fn to_classes(output: &Array2<f64>) -> Vec<usize> {
+    if output.cols() == 1 {
+        output.column(0).iter().map(|v| if *v >= 0.5 { 1 } else { 0 }).collect()
+    } else {
+        output.genrows().into_iter().map(argmax).collect()
+    }
+}
+
+pub fn accuracy(predicted: &[usize], expected: &[usize]) -> f64 {
+    assert_eq!(predicted.len(), expected.len());
+    let good_guesses = predicted.iter().zip(expected).filter(|&(p, e)| p == e).count();
+    good_guesses as f64 / predicted.len() as f64
+}
+
+pub fn top_k_accuracy(output: &Array2<f64>, expected: &[usize], k: usize) -> f64 {
+    assert_eq!(output.rows(), expected.len());
+    let good_guesses = output.genrows().into_iter()
+        .zip(expected)
+        .filter(|&(row, class)| top_k(row, k).contains(class))
+        .count();
+    good_guesses as f64 / expected.len() as f64
+}
+
+// Rows are expected classes, columns are predicted classes
+pub fn confusion_matrix(predicted: &[usize], expected: &[usize], classes: usize) -> Array2<usize> {
+    assert_eq!(predicted.len(), expected.len());
+    let mut matrix = Array2::<usize>::zeros((classes, classes));
+    for (p, e) in predicted.iter().zip(expected) {
+        matrix[[*e, *p]] += 1;
+    }
+    matrix
+}
+
+pub fn precision(confusion_matrix: &Array2<usize>, average: Average) -> f64 {
+    // Precision of a class : true positives / predicted as the class
+    averaged_score(confusion_matrix, average, |true_positives, predicted, _| ratio(true_positives, predicted))
+}
+
+pub fn recall(confusion_matrix: &Array2<usize>, average: Average) -> f64 {
+    // Recall of a class : true positives / expected as the class
+    averaged_score(confusion_matrix, average, |true_positives, _, expected| ratio(true_positives, expected))
+}
+
+pub fn f1(confusion_matrix: &Array2<usize>, average: Average) -> f64 {
+    averaged_score(confusion_matrix, average, |true_positives, predicted, expected| ratio(2.0 * true_positives, predicted + expected))
+}
+
+// Score is given (true positives, predicted, expected) counts
+fn averaged_score<F: Fn(f64, f64, f64) -> f64>(confusion_matrix: &Array2<usize>, average: Average, score: F) -> f64 {
+    let classes = confusion_matrix.rows();
+    let counts: Vec<(f64, f64, f64)> = (0..classes).map(|class| (
+        confusion_matrix[[class, class]] as f64,
+        confusion_matrix.column(class).scalar_sum() as f64,
+        confusion_matrix.row(class).scalar_sum() as f64,
+    )).collect();
+
+    match average {
+        Average::Micro => {
+            let total = counts.iter().fold((0.0, 0.0, 0.0), |total, count| (total.0 + count.0, total.1 + count.1, total.2 + count.2));
+            score(total.0, total.1, total.2)
+        },
+        Average::Macro => {
+            counts.iter().map(|&(t, p, e)| score(t, p, e)).sum::<f64>() / classes as f64
+        },
+        Average::Weighted => {
+            let total: f64 = counts.iter().map(|&(_, _, e)| e).sum();
+            counts.iter().map(|&(t, p, e)| score(t, p, e) * e).sum::<f64>() / total
+        },
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator == 0.0 { 0.0 } else { numerator / denominator }
+}
+
+// Binary metric for one column, macro averaged over every column when there are several classes
+fn one_vs_rest<F: Fn(ArrayView1<f64>, &[bool]) -> f64>(output: &Array2<f64>, expected_output: &Array2<f64>, metric: F) -> f64 {
+    let mut total = 0.0;
+    for (scores, expected) in output.gencolumns().into_iter().zip(expected_output.gencolumns()) {
+        let labels: Vec<bool> = expected.iter().map(|v| *v >= 0.5).collect();
+        total += metric(scores, &labels);
+    }
+    total / output.cols() as f64
+}
+
+// Area under the ROC curve, probability that a random positive is scored above a random negative
+pub fn roc_auc(scores: ArrayView1<f64>, labels: &[bool]) -> f64 {
+    let order = sorted_indexes(scores, false);
+
+    // Average rank of equal scores
+    let mut ranks = vec![0.0; order.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && scores[order[j + 1]] == scores[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for k in i..j + 1 {
+            ranks[order[k]] = rank;
+        }
+        i = j + 1;
+    }
+
+    let positives = labels.iter().filter(|l| **l).count() as f64;
+    let negatives = labels.len() as f64 - positives;
+    if positives == 0.0 || negatives == 0.0 {
+        return 0.5;
+    }
+
+    let positive_ranks: f64 = ranks.iter().zip(labels).filter(|&(_, l)| *l).map(|(r, _)| *r).sum();
+    (positive_ranks - positives * (positives + 1.0) / 2.0) / (positives * negatives)
+}
+
+// Area under the precision recall curve, computed as the average precision
+pub fn pr_auc(scores: ArrayView1<f64>, labels: &[bool]) -> f64 {
+    let positives = labels.iter().filter(|l| **l).count() as f64;
+    if positives == 0.0 {
+        return 0.0;
+    }
+
+    let mut true_positives = 0.0;
+    let mut average_precision = 0.0;
+    for (i, index) in sorted_indexes(scores, true).into_iter().enumerate() {
+        if labels[index] {
+            true_positives += 1.0;
+            average_precision += true_positives / (i + 1) as f64;
+        }
+    }
+    average_precision / positives
+}
+
+fn sorted_indexes(scores: ArrayView1<f64>, descending: bool) -> Vec<usize> {
+    let mut indexes: Vec<usize> = (0..scores.len()).collect();
+    indexes.sort_by(|a, b| {
+        let ordering = scores[*a].partial_cmp(&scores[*b]).unwrap_or(Ordering::Equal);
+        if descending { ordering.reverse() } else { ordering }
+    });
+    indexes
+}
+
+// Binary log loss for one column, categorical cross entropy otherwise, averaged over the rows
+pub fn log_loss(output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
+    let epsilon = 1e-15;
+    let mut total = 0.0;
+    for (approx, expected) in output.iter().zip(expected_output.iter()) {
+        let approx = approx.max(epsilon).min(1.0 - epsilon);
+        total -= expected * approx.ln();
+        if output.cols() == 1 {
+            total -= (1.0 - expected) * (1.0 - approx).ln();
+        }
+    }
+    total / output.rows() as f64
+}
+
+pub fn mean_absolute_error(output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
+    (output - expected_output).map(|v| v.abs()).scalar_sum() / output.len() as f64
+}
+
+pub fn root_mean_squared_error(output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
+    ((output - expected_output).map(|v| v * v).scalar_sum() / output.len() as f64).sqrt()
+}
+
+// Coefficient of determination of every column, averaged over the columns
+pub fn r2(output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
+    let means = expected_output.sum_axis(Axis(0)) / expected_output.rows() as f64;
+    let mut total = 0.0;
+    for j in 0..output.cols() {
+        let residual: f64 = output.column(j).iter().zip(expected_output.column(j)).map(|(o, e)| (e - o).powi(2)).sum();
+        let variance: f64 = expected_output.column(j).iter().map(|e| (e - means[j]).powi(2)).sum();
+        total += if variance == 0.0 {
+            if residual == 0.0 { 1.0 } else { 0.0 }
+        } else {
+            1.0 - residual / variance
+        };
+    }
+    total / output.cols() as f64
+}
+
+// Expected values close to zero are clamped to avoid infinite errors
+pub fn mean_absolute_percentage_error(output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
+    let mut total = 0.0;
+    for (approx, expected) in output.iter().zip(expected_output.iter()) {
+        total += (expected - approx).abs() / expected.abs().max(f64::EPSILON);
+    }
+    total / output.len() as f64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{arr1, arr2};
+    use super::*;
+
+    #[test]
+    fn classification_scores() {
+        let expected = vec![0, 0, 1, 1, 2, 2];
+        let predicted = vec![0, 1, 1, 1, 2, 0];
+        let matrix = confusion_matrix(&predicted, &expected, 3);
+
+        assert_eq!(matrix, arr2(&[[1, 1, 0], [0, 2, 0], [1, 0, 1]]));
+        assert!((accuracy(&predicted, &expected) - 4.0 / 6.0).abs() < 1e-12);
+        assert!((precision(&matrix, Average::Macro) - (0.5 + 2.0 / 3.0 + 1.0) / 3.0).abs() < 1e-12);
+        assert!((recall(&matrix, Average::Macro) - (0.5 + 1.0 + 0.5) / 3.0).abs() < 1e-12);
+        assert!((f1(&matrix, Average::Micro) - 4.0 / 6.0).abs() < 1e-12);
+        assert!((recall(&matrix, Average::Weighted) - 4.0 / 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn areas_under_curves() {
+        let scores = arr1(&[0.1, 0.4, 0.35, 0.8]);
+        let labels = [false, false, true, true];
+
+        assert!((roc_auc(scores.view(), &labels) - 0.75).abs() < 1e-12);
+        assert!((pr_auc(scores.view(), &labels) - (1.0 + 2.0 / 3.0) / 2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn evaluation_does_not_depend_on_batch_size() {
+        use builder::NeuralNetworkBuilder;
+        use activation::Activation;
+
+        let network = NeuralNetworkBuilder::new(2)
+            .layer(3, Activation::Softmax)
+            .build();
+        let inputs = arr2(&[[0.2, -0.4], [0.9, 0.1], [0.5, 0.5], [-0.3, 0.8], [0.0, -0.6]]);
+        let targets = arr2(&[[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+        let metrics = [Metric::Accuracy, Metric::F1(Average::Macro)];
+
+        let full = network.evaluate(&inputs, &targets, &Objective::CrossEntropy, &metrics).unwrap();
+        let batched = network.evaluate_in_batches(&ArrayDataset::new(&inputs, &targets), &Objective::CrossEntropy, &metrics, 2).unwrap();
+
+        assert!((full.loss - batched.loss).abs() < 1e-12);
+        assert_eq!(full.metric("accuracy"), batched.metric("accuracy"));
+        assert_eq!(full.metric("f1_macro"), batched.metric("f1_macro"));
+        assert!((full.loss - Objective::CrossEntropy.calculate_error(&network.predict(&inputs), &targets)).abs() < 1e-12);
+
+        assert!(network.evaluate_in_batches(&ArrayDataset::new(&inputs, &targets), &Objective::CrossEntropy, &metrics, 0).is_err());
+
+        let empty = Array2::zeros((0, 2));
+        assert!(network.evaluate(&empty, &Array2::zeros((0, 3)), &Objective::CrossEntropy, &metrics).is_err());
+    }
+
+    #[test]
+    fn running_metrics_match_computed_ones() {
+        let output = arr2(&[[0.7, 0.2, 0.1], [0.1, 0.8, 0.1], [0.3, 0.3, 0.4], [0.5, 0.4, 0.1], [0.2, 0.2, 0.6]]);
+        let expected = arr2(&[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        let metrics = [Metric::Accuracy, Metric::F1(Average::Weighted), Metric::TopKAccuracy(2), Metric::LogLoss,
+                       Metric::RootMeanSquaredError, Metric::R2, Metric::RocAuc, Metric::PrAuc];
+
+        let mut running = RunningMetrics::new(&metrics);
+        assert!(running.values().iter().all(|value| value.is_nan()));
+        for &(start, end) in &[(0, 2), (2, 3), (3, 5)] {
+            running.update(&output.slice(s![start..end, ..]).to_owned(), &expected.slice(s![start..end, ..]).to_owned());
+        }
+
+        // Scores are multiples of 1 / AUC_BINS, so areas under curves are exact too
+        assert_eq!(running.rows(), 5);
+        for (metric, value) in metrics.iter().zip(running.values()) {
+            assert!((metric.compute(&output, &expected) - value).abs() < 1e-12, "{}", metric.name());
+        }
+    }
+
+    #[test]
+    fn regression_scores() {
+        let output = arr2(&[[2.5], [0.0], [2.0], [8.0]]);
+        let expected = arr2(&[[3.0], [-0.5], [2.0], [7.0]]);
+
+        assert!((Metric::MeanAbsoluteError.compute(&output, &expected) - 0.5).abs() < 1e-12);
+        assert!((Metric::RootMeanSquaredError.compute(&output, &expected) - 0.375f64.sqrt()).abs() < 1e-12);
+        assert!((Metric::R2.compute(&output, &expected) - 0.9486081370449679).abs() < 1e-12);
+    }
+}
@@ -1,100 +1,724 @@
+use std::io;
+
 use ndarray::{Array2};
 
-use layer::Layer;
+use layer::{Layer, LayerCache};
 use activation::Activation;
 use objective::Objective;
+use error::{Error, Warning, Result};
+use gradients::{Gradients, GradientClipping};
+use data::{Dataset, DataLoader};
+use preprocessing::Scaler;
+use tabular::TabularEncoder;
 
 pub struct NeuralNetwork {
     layers: Vec<Layer>,
+    pub gradient_clipping: Option<GradientClipping>,
+    // Names of the output classes
+    pub labels: Option<Vec<String>>,
+    // Fitted on the training data, see preprocess() and postprocess()
+    pub input_scaler: Option<Scaler>,
+    pub target_scaler: Option<Scaler>,
+    // Categorical and numeric columns of the CSV file the network was trained on, encodes new files the same way
+    pub encoder: Option<TabularEncoder>,
+}
+
+// Activities of every layer computed by predict_with_cache(), kept outside of the network
+// so a trained network can be shared between threads
+pub struct ForwardCache {
+    pub activities: Vec<Array2<f64>>,
+    // Intermediate values of every layer, only kept by forward_with_cache() for a backward pass
+    pub layers: Vec<LayerCache>,
+}
+
+impl Default for ForwardCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ForwardCache {
+    pub fn new() -> Self {
+        Self {
+            activities: Vec::new(),
+            layers: Vec::new(),
+        }
+    }
+
+    pub fn output(&self) -> Option<&Array2<f64>> {
+        self.activities.last()
+    }
+}
+
+// Result of gradient_check() for one layer
+pub struct GradientCheck {
+    pub layer: usize,
+    pub parameters: usize,
+    // |analytical - numerical| / (|analytical| + |numerical|), computed over every parameter of the layer
+    pub relative_error: f64,
 }
 
 impl NeuralNetwork {
     pub fn new(layers: Vec<Layer>) -> Self {
         Self {
             layers,
+            gradient_clipping: None,
+            labels: None,
+            input_scaler: None,
+            target_scaler: None,
+            encoder: None,
         }
     }
 
     pub fn feed_forward(&mut self, input: &Array2<f64>) -> Array2<f64> {
         let mut layer_result = input.clone();
-        for mut layer in &mut self.layers {
-            layer.calculate_activities(&layer_result);
-            layer_result = layer.activities.clone();    // TODO : optimize so no need to clone every layer_result
+        let mut padding = None;
+        for layer in &mut self.layers {
+            padding = layer.padding(&layer_result, padding);
+            layer.calculate_activities(&layer_result, padding.as_ref());
+            layer_result = layer.activities().clone();    // TODO : optimize so no need to clone every layer_result
         }
 
         layer_result
     }
 
+    // Same result as feed_forward() without modifying the network
+    pub fn predict(&self, input: &Array2<f64>) -> Array2<f64> {
+        let mut cache = ForwardCache::new();
+        self.predict_with_cache(input, &mut cache);
+        cache.activities.pop().unwrap()
+    }
+
+    pub fn predict_with_cache<'a>(&self, input: &Array2<f64>, cache: &'a mut ForwardCache) -> &'a Array2<f64> {
+        cache.activities.clear();
+        cache.layers.clear();
+        let mut padding = None;
+        for layer in &self.layers {
+            let layer_input = cache.activities.last().unwrap_or(input);
+            padding = layer.padding(layer_input, padding);
+            let activities = layer.predict(layer_input, padding.as_ref());
+            cache.activities.push(activities);
+        }
+
+        cache.activities.last().unwrap()
+    }
+
+    // Same as predict_with_cache(), also keeping what a backward pass needs, see input_gradient()
+    pub fn forward_with_cache<'a>(&self, input: &Array2<f64>, cache: &'a mut ForwardCache) -> &'a Array2<f64> {
+        cache.activities.clear();
+        cache.layers.clear();
+        let mut padding = None;
+        for layer in &self.layers {
+            let layer_input = cache.activities.last().unwrap_or(input);
+            padding = layer.padding(layer_input, padding);
+            let (layer_cache, activities) = layer.forward(layer_input, padding.as_ref());
+            cache.layers.push(layer_cache);
+            cache.activities.push(activities);
+        }
+
+        cache.activities.last().unwrap()
+    }
+
+    // Same as feed_forward(), with an error instead of a panic when the input does not fit the network
+    pub fn try_feed_forward(&mut self, input: &Array2<f64>) -> Result<Array2<f64>> {
+        self.check_input(input)?;
+        Ok(self.feed_forward(input))
+    }
+
+    pub fn try_predict(&self, input: &Array2<f64>) -> Result<Array2<f64>> {
+        self.check_input(input)?;
+        Ok(self.predict(input))
+    }
+
+    pub fn check_input(&self, input: &Array2<f64>) -> Result<()> {
+        if input.cols() != self.input_size() {
+            return Err(Error::InputWidth { expected: self.input_size(), found: input.cols() });
+        }
+        Ok(())
+    }
+
+    // Errors for data or layers that can not be trained, warnings for an output activation that does not fit the objective
+    pub fn check_training(&self, inputs: &Array2<f64>, expected_outputs: &Array2<f64>, objective_function: &Objective) -> Result<Vec<Warning>> {
+        self.check_input(inputs)?;
+        if inputs.rows() != expected_outputs.rows() {
+            return Err(Error::RowCount { inputs: inputs.rows(), targets: expected_outputs.rows() });
+        }
+        if inputs.rows() == 0 {
+            return Err(Error::EmptyData);
+        }
+        let outputs = self.layers.last().unwrap().outputs();
+        if expected_outputs.cols() != outputs {
+            return Err(Error::OutputWidth { expected: outputs, found: expected_outputs.cols() });
+        }
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            if let Layer::Dense(ref layer) = *layer {
+                if let Activation::Binary(_) = layer.activation_function {
+                    return Err(Error::NotTrainable { layer: i, activation: layer.activation_function.name() });
+                }
+            }
+        }
+
+        Ok(self.objective_warnings(objective_function))
+    }
+
+    pub fn objective_warnings(&self, objective_function: &Objective) -> Vec<Warning> {
+        let activation = match self.layers.last() {
+            Some(Layer::Dense(layer)) => layer.activation_function,
+            _ => return Vec::new(),
+        };
+        let bounded = matches!(activation, Activation::Sigmoid | Activation::Softmax | Activation::Binary(_));
+        let logarithmic = matches!(*objective_function, Objective::Log | Objective::CrossEntropy | Objective::Focal(_));
+
+        let reason = match (activation, *objective_function) {
+            (Activation::LogSoftmax, Objective::Likelihood) => None,
+            (Activation::LogSoftmax, _) => Some("log probabilities are only meant for the Likelihood objective"),
+            (_, Objective::Likelihood) => Some("Likelihood expects log probabilities, use a LogSoftmax output"),
+            (Activation::Softmax, objective) if objective.is_regression() => Some("probabilities are trained as quantities, CrossEntropy fits them better"),
+            _ if logarithmic && !bounded => Some("outputs can leave 0..1, where the logarithm of the objective is undefined"),
+            _ => None,
+        };
+
+        reason.map(|reason| Warning::IncompatibleObjective {
+            activation: activation.name(),
+            objective: objective_function.name(),
+            reason,
+        }).into_iter().collect()
+    }
+
     pub fn train(&mut self, training_set: &mut Array2<f64>, expected_result: Array2<f64>, objective_function: Objective, batch_size: usize, learning_rate: f64) {
         assert_eq!(training_set.rows(), expected_result.rows(), "Training set should have same amount of rows as expected results");
         assert!(batch_size > 0, "Batch size must be greater than zero");
+        self.train_batches(training_set, &expected_result, objective_function, batch_size, learning_rate);
+    }
+
+    // Same as train(), with errors instead of panics, returns the warnings of check_training()
+    pub fn try_train(&mut self, training_set: &Array2<f64>, expected_result: &Array2<f64>, objective_function: Objective, batch_size: usize, learning_rate: f64) -> Result<Vec<Warning>> {
+        if batch_size == 0 {
+            return Err(Error::ZeroBatchSize);
+        }
+        let warnings = self.check_training(training_set, expected_result, &objective_function)?;
+        self.train_batches(training_set, expected_result, objective_function, batch_size, learning_rate);
+        Ok(warnings)
+    }
 
+    fn train_batches(&mut self, training_set: &Array2<f64>, expected_result: &Array2<f64>, objective_function: Objective, batch_size: usize, learning_rate: f64) {
         let mut i = 0;
 
         while i < training_set.rows() {
 
             let current_max_row = training_set.rows().min(batch_size + i);
 
-            let mut data = training_set.slice(s![i..current_max_row, ..]).to_owned();
-            let mut expected_result_slice = expected_result.slice(s![i..current_max_row, ..]).to_owned();
+            let data = training_set.slice(s![i..current_max_row, ..]).to_owned();
+            let expected_result_slice = expected_result.slice(s![i..current_max_row, ..]).to_owned();
 
             let network_result = self.feed_forward(&data);
             assert_eq!(expected_result.cols(), network_result.cols(), "Expected result and actual result do not have the same amount of columns");
 
             let total_error = objective_function.calculate_error(&network_result, &expected_result_slice);
 
-            self.backpropagation(&data, &network_result, &expected_result_slice, &objective_function, learning_rate);
+            let mut gradients = self.backpropagation(&data, &network_result, &expected_result_slice, &objective_function);
+            if let Some(clipping) = self.gradient_clipping {
+                gradients.clip(clipping);
+            }
+            self.apply_gradients(&gradients, learning_rate);
 
             println!("Iteration {}; error: {}", i / batch_size, total_error);
             i += batch_size;
         }
     }
 
-    fn backpropagation(&mut self, input: &Array2<f64>, actual: &Array2<f64>, ideal: &Array2<f64>, objective_function: &Objective, learning_rate: f64) {
+    // One epoch over the batches of the loader, returns the mean error of the epoch
+    pub fn train_epoch<D: Dataset>(&mut self, loader: &mut DataLoader<D>, objective_function: Objective, learning_rate: f64) -> io::Result<f64> {
+        let mut total_error = 0.0;
+        let mut rows = 0;
+
+        for batch in loader.epoch() {
+            let (data, expected_result_slice) = batch?;
+
+            let (error, mut gradients) = self.compute_error_and_gradients(&data, &expected_result_slice, &objective_function);
+            if let Some(clipping) = self.gradient_clipping {
+                gradients.clip(clipping);
+            }
+            self.apply_gradients(&gradients, learning_rate);
+
+            total_error += error * data.rows() as f64;
+            rows += data.rows();
+        }
+
+        Ok(if rows == 0 { 0.0 } else { total_error / rows as f64 })
+    }
 
+    // Gradients of the error for the given batch, parameters are left untouched
+    pub fn compute_gradients(&mut self, input: &Array2<f64>, expected_output: &Array2<f64>, objective_function: &Objective) -> Gradients {
+        self.compute_error_and_gradients(input, expected_output, objective_function).1
+    }
+
+    // Same as compute_gradients(), also returning the error of the batch
+    pub fn compute_error_and_gradients(&mut self, input: &Array2<f64>, expected_output: &Array2<f64>, objective_function: &Objective) -> (f64, Gradients) {
+        let network_result = self.feed_forward(input);
+        assert_eq!(expected_output.cols(), network_result.cols(), "Expected result and actual result do not have the same amount of columns");
+
+        let error = objective_function.calculate_error(&network_result, expected_output) + self.penalty();
+        (error, self.backpropagation(input, &network_result, expected_output, objective_function))
+    }
+
+    // Average of the gradients of every micro batch, weighted by their amount of rows
+    pub fn accumulate_gradients(&mut self, input: &Array2<f64>, expected_output: &Array2<f64>, objective_function: &Objective, micro_batch_size: usize) -> Gradients {
+        assert!(micro_batch_size > 0, "Micro batch size must be greater than zero");
+
+        let mut total: Option<Gradients> = None;
+        let mut i = 0;
+
+        while i < input.rows() {
+            let current_max_row = input.rows().min(micro_batch_size + i);
+            let data = input.slice(s![i..current_max_row, ..]).to_owned();
+            let expected_result_slice = expected_output.slice(s![i..current_max_row, ..]).to_owned();
+
+            let mut gradients = self.compute_gradients(&data, &expected_result_slice, objective_function);
+            gradients.scale((current_max_row - i) as f64 / input.rows() as f64);
+
+            match total {
+                Some(ref mut total) => total.add(&gradients),
+                None => total = Some(gradients),
+            }
+            i += micro_batch_size;
+        }
+
+        total.expect("No rows to compute gradients on")
+    }
+
+    // Plain gradient descent step, other optimizers can update parameters_mut() themselves
+    pub fn apply_gradients(&mut self, gradients: &Gradients, learning_rate: f64) {
+        assert_eq!(gradients.layers.len(), self.layers.len(), "Gradients do not match the network layers");
+        for (layer, layer_gradients) in self.layers.iter_mut().zip(&gradients.layers) {
+            layer.apply_gradients(layer_gradients, learning_rate);
+        }
+    }
+
+    // Activities of the last layer computed by the last feed_forward() call
+    pub fn output(&self) -> &Array2<f64> {
+        self.layers.last().unwrap().activities()
+    }
+
+    // Columns of the input matrix
+    pub fn input_size(&self) -> usize {
+        self.layers[0].inputs()
+    }
+
+    // Regularization penalty of every layer, part of the error minimized by training
+    pub fn penalty(&self) -> f64 {
+        self.layers.iter().map(|layer| layer.penalty()).sum()
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn parameters(&self) -> Vec<Vec<&Array2<f64>>> {
+        self.layers.iter().map(|layer| layer.parameters()).collect()
+    }
+
+    pub fn parameters_mut(&mut self) -> Vec<Vec<&mut Array2<f64>>> {
+        self.layers.iter_mut().map(|layer| layer.parameters_mut()).collect()
+    }
+
+    // Uses the values cached by the last feed_forward() call
+    fn backpropagation(&self, input: &Array2<f64>, actual: &Array2<f64>, ideal: &Array2<f64>, objective_function: &Objective) -> Gradients {
+        self.backward(input, objective_function.compute_derivative(actual, ideal)).1
+    }
+
+    // Gradient of an output (e.g. one neuron) with regard to the input, output_gradient being its gradient with regard to the network output.
+    // Nothing is kept in the network, so it can be shared
+    pub fn input_gradient(&self, input: &Array2<f64>, output_gradient: &Array2<f64>) -> Array2<f64> {
+        let mut cache = ForwardCache::new();
+        let output = self.forward_with_cache(input, &mut cache);
+        assert_eq!(output.dim(), output_gradient.dim(), "Output gradient does not have the shape of the output");
+        self.backward_with_cache(input, &cache, output_gradient.clone()).0
+    }
+
+    // Layers must hold the activities of the last feed_forward() of input
+    fn backward(&self, input: &Array2<f64>, output_delta: Array2<f64>) -> (Array2<f64>, Gradients) {
+        self.backpropagate(output_delta, |i, delta| {
+            let layer_input = if i == 0 { input } else { self.layers[i - 1].activities() };
+            self.layers[i].compute_gradients(layer_input, delta)
+        })
+    }
+
+    // Same as backward(), cache being filled by forward_with_cache() of input
+    fn backward_with_cache(&self, input: &Array2<f64>, cache: &ForwardCache, output_delta: Array2<f64>) -> (Array2<f64>, Gradients) {
+        self.backpropagate(output_delta, |i, delta| {
+            let layer_input = if i == 0 { input } else { &cache.activities[i - 1] };
+            self.layers[i].backward(&cache.layers[i], layer_input, delta)
+        })
+    }
+
+    // From the last layer to the first one, compute_layer giving the delta of the previous layer and the gradients of a layer
+    fn backpropagate<F>(&self, output_delta: Array2<f64>, compute_layer: F) -> (Array2<f64>, Gradients)
+        where F: Fn(usize, &Array2<f64>) -> (Array2<f64>, Vec<Array2<f64>>)
+    {
         let number_of_layers = self.layers.len();
-        let mut result = objective_function.compute_derivative(&actual, &ideal);
+        let mut result = output_delta;
+        let mut gradients = Vec::with_capacity(number_of_layers);
 
         for i in (0..number_of_layers).rev() {
+            let (previous_delta, layer_gradients) = compute_layer(i, &result);
+            result = previous_delta;
+            gradients.push(layer_gradients);
+        }
 
-            result = self.layers[i].activation_function.compute_loss(&result, &self.layers[i].output);
+        gradients.reverse();
+        (result, Gradients::new(gradients))
+    }
+
+    // Compare gradients given by backpropagation with central finite differences for every weight and bias
+    pub fn gradient_check(&mut self, input: &Array2<f64>, expected_output: &Array2<f64>, objective_function: &Objective, epsilon: f64) -> Vec<GradientCheck> {
+        let analytical_gradients = self.compute_gradients(input, expected_output, objective_function);
+
+        let mut result = Vec::with_capacity(self.layers.len());
+        for (i, layer_gradients) in analytical_gradients.layers.iter().enumerate() {
+            let mut difference = 0.0;
+            let mut analytical_norm = 0.0;
+            let mut numerical_norm = 0.0;
+            let mut parameters = 0;
 
-            let diff_weight = if i == 0 {
-                input.t().dot(&result)
-            } else {
-                self.layers[i - 1].activities.t().dot(&result)
-            };
+            for (p, gradient) in layer_gradients.iter().enumerate() {
+                for ((row, col), analytical) in gradient.indexed_iter() {
+                    let original = self.layers[i].parameters()[p][[row, col]];
 
-            let mut diff_bias = Array2::<f64>::zeros((1, result.cols()));
-            for j in 0..result.cols() {
-                diff_bias[[0, j]] = result.slice(s![.., j]).scalar_sum() / result.rows() as f64;
+                    self.layers[i].parameters_mut()[p][[row, col]] = original + epsilon;
+                    let error_plus = objective_function.calculate_error(&self.feed_forward(input), expected_output) + self.penalty();
+                    self.layers[i].parameters_mut()[p][[row, col]] = original - epsilon;
+                    let error_minus = objective_function.calculate_error(&self.feed_forward(input), expected_output) + self.penalty();
+                    self.layers[i].parameters_mut()[p][[row, col]] = original;
+
+                    let numerical = (error_plus - error_minus) / (2.0 * epsilon);
+
+                    difference += (analytical - numerical).powi(2);
+                    analytical_norm += analytical.powi(2);
+                    numerical_norm += numerical.powi(2);
+                    parameters += 1;
+                }
             }
 
+            let norms = analytical_norm.sqrt() + numerical_norm.sqrt();
+            result.push(GradientCheck {
+                layer: i,
+                parameters,
+                relative_error: if norms > 0.0 { difference.sqrt() / norms } else { 0.0 },
+            });
+        }
 
-            if i > 0 {
-                result = result.dot(&self.layers[i].weights.t());
+        result
+    }
+
+    // Input giving the expected output, layer after layer from the last one : the activation is reversed,
+    // then the least squares solution of input × weights = activities - bias is taken with the pseudo-inverse of the weights.
+    // The reconstruction is exact when the output is reachable and no layer has less neurons than inputs.
+    pub fn get_expected_input(&self, expected_output: &Array2<f64>) -> io::Result<Array2<f64>> {
+        let mut result = expected_output.clone();
+
+        for layer in self.layers.iter().rev() {
+            match *layer {
+                Layer::Dense(ref layer) => {
+                    result = layer.activation_function.compute_reverse(&result)?;
+                    result -= &layer.bias;
+                    result = result.dot(&pseudo_inverse(&layer.weights)?);
+                },
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Only dense layers can be reversed")),
             }
+        }
+
+        Ok(result)
+    }
+}
 
-            //println!("Layer {}", i);
-            //println!("Diff weight : {:?}", diff_weight);
-            //println!("Diff bias : {:?}", diff_bias);
 
+// Moore-Penrose pseudo-inverse of a full rank matrix, (AᵀA)⁻¹Aᵀ for tall matrices and Aᵀ(AAᵀ)⁻¹ for wide ones
+fn pseudo_inverse(matrix: &Array2<f64>) -> io::Result<Array2<f64>> {
+    if matrix.rows() >= matrix.cols() {
+        Ok(inverse(&matrix.t().dot(matrix))?.dot(&matrix.t()))
+    } else {
+        Ok(matrix.t().dot(&inverse(&matrix.dot(&matrix.t()))?))
+    }
+}
 
-            // Update weights and bias
-            self.layers[i].bias = &self.layers[i].bias + &(&diff_bias * learning_rate);     // TODO : should learning rate for bias be different ?
-            self.layers[i].weights = &self.layers[i].weights + &(&diff_weight * learning_rate);
+// Gauss-Jordan elimination with partial pivoting
+fn inverse(matrix: &Array2<f64>) -> io::Result<Array2<f64>> {
+    let size = matrix.rows();
+    let scale = matrix.iter().fold(0.0_f64, |maximum, v| maximum.max(v.abs()));
+    let mut left = matrix.clone();
+    let mut right = Array2::<f64>::eye(size);
+
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|a, b| left[[*a, column]].abs().partial_cmp(&left[[*b, column]].abs()).unwrap())
+            .unwrap();
+        if left[[pivot, column]].abs() <= 1e-12 * scale {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Weights are not of full rank, they can not be reversed"));
+        }
+        for j in 0..size {
+            left.swap([column, j], [pivot, j]);
+            right.swap([column, j], [pivot, j]);
+        }
+
+        let value = left[[column, column]];
+        left.row_mut(column).mapv_inplace(|v| v / value);
+        right.row_mut(column).mapv_inplace(|v| v / value);
+        for row in 0..size {
+            let factor = left[[row, column]];
+            if row != column && factor != 0.0 {
+                let (left_pivot, right_pivot) = (left.row(column).to_owned(), right.row(column).to_owned());
+                left.row_mut(row).scaled_add(-factor, &left_pivot);
+                right.row_mut(row).scaled_add(-factor, &right_pivot);
+            }
         }
     }
 
-    pub fn get_expected_input(&self, expected_output: &Array2<f64>) -> Array2<f64> {
-        let mut result = expected_output.clone();
+    Ok(right)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use attention::AttentionMask;
+    use layer::{Initializer, Regularization};
+    use super::*;
+
+    fn activations() -> Vec<Activation> {
+        vec![
+            Activation::Identity,
+            Activation::Binary(0.5),
+            Activation::Sigmoid,
+            Activation::TanH,
+            Activation::ReLU,
+            Activation::LeakyReLU(0.3),
+            Activation::Softmax,
+            Activation::LogSoftmax,
+        ]
+    }
 
-        for i in (0..self.layers.len()).rev() {
-            result = self.layers[i].activation_function.compute_reverse(&result);
-            result = result - &self.layers[i].bias;
-            result = result.dot(&self.layers[i].weights.t());
+    fn objectives() -> Vec<Objective> {
+        vec![
+            Objective::Log,
+            Objective::Focal(2.0),
+            Objective::Exponential,
+            Objective::Hinge,
+            Objective::CrossEntropy,
+            Objective::SumSquaredError,
+            Objective::MeanSquaredError,
+            Objective::MeanAbsoluteError,
+            Objective::Huber(1.0),
+            Objective::LogCosh,
+            Objective::Quantile(0.3),
+            Objective::Likelihood,
+        ]
+    }
+
+    // Probability based objectives need outputs between 0 and 1
+    fn output_activation(activation: Activation, objective: Objective) -> Activation {
+        match (objective, activation) {
+            (Objective::Log, Activation::Sigmoid)
+            | (Objective::Log, Activation::Softmax)
+            | (Objective::Focal(_), Activation::Sigmoid)
+            | (Objective::Focal(_), Activation::Softmax)
+            | (Objective::CrossEntropy, Activation::Sigmoid)
+            | (Objective::CrossEntropy, Activation::Softmax) => activation,
+            (Objective::Log, _) | (Objective::Focal(_), _) | (Objective::CrossEntropy, _) => Activation::Sigmoid,
+            // Binary outputs do not depend on the weights, test it on the hidden layer only
+            (_, Activation::Binary(_)) => Activation::Identity,
+            _ => activation,
         }
+    }
 
-        result
+    fn scale_weights(network: &mut NeuralNetwork, scale: f64) {
+        for layer in &mut network.layers {
+            for parameter in layer.parameters_mut() {
+                *parameter *= scale;
+            }
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn gradient_check_every_activation_and_objective() {
+        let input = arr2(&[[0.2, -0.4, 0.7], [0.9, 0.1, -0.3]]);
+        let expected_output = arr2(&[[0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]);
+
+        for objective in objectives() {
+            for activation in activations() {
+                let mut network = NeuralNetworkBuilder::new(3)
+                    .layer(4, activation)
+                    .layer(3, output_activation(activation, objective))
+                    .build();
+                scale_weights(&mut network, 0.5);
+
+                for check in network.gradient_check(&input, &expected_output, &objective, 1e-6) {
+                    assert!(check.relative_error < 1e-5, "Layer {} relative error {}", check.layer, check.relative_error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn predict_from_several_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let input = arr2(&[[0.2, -0.4, 0.7, 0.1, 0.9, 0.1, -0.3, 0.5], [0.3, 0.8, -0.1, 0.6, -0.2, 0.4, 0.0, 0.0]]);
+        let mut network = NeuralNetworkBuilder::new(8)
+            .transformer_encoder(4, 2, 3, AttentionMask { causal: false, padding: true })
+            .layer(2, Activation::Softmax)
+            .build();
+        let expected_result = network.feed_forward(&input);
+
+        let network = Arc::new(network);
+        let threads: Vec<_> = (0..4).map(|_| {
+            let network = network.clone();
+            let input = input.clone();
+            thread::spawn(move || network.predict(&input))
+        }).collect();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), expected_result);
+        }
+    }
+
+    #[test]
+    fn accumulated_gradients_match_full_batch() {
+        let input = arr2(&[[0.2, -0.4, 0.7], [0.9, 0.1, -0.3], [0.5, 0.5, 0.1]]);
+        let expected_output = arr2(&[[0.0, 1.0], [1.0, 0.0], [1.0, 0.0]]);
+
+        let mut network = NeuralNetworkBuilder::new(3)
+            .layer(4, Activation::TanH)
+            .layer(2, Activation::Softmax)
+            .build();
+
+        let full_batch = network.compute_gradients(&input, &expected_output, &Objective::CrossEntropy);
+        let accumulated = network.accumulate_gradients(&input, &expected_output, &Objective::CrossEntropy, 2);
+
+        for (full, micro) in full_batch.layers.iter().flat_map(|l| l.iter()).zip(accumulated.layers.iter().flat_map(|l| l.iter())) {
+            assert!(full.all_close(micro, 1e-12));
+        }
+    }
+
+    #[test]
+    fn gradient_check_transformer() {
+        let input = arr2(&[[0.2, -0.4, 0.7, 0.1, 0.9, 0.1, -0.3, 0.5], [0.3, 0.8, -0.1, 0.6, -0.2, 0.4, 0.0, 0.0]]);
+        let expected_output = arr2(&[[0.0, 1.0], [1.0, 0.0]]);
+        let mask = AttentionMask { causal: true, padding: true };
+
+        let mut network = NeuralNetworkBuilder::new(8)
+            .transformer_encoder(4, 2, 3, mask)
+            .attention(4, 1, AttentionMask::default())
+            .layer(2, Activation::Softmax)
+            .build();
+        scale_weights(&mut network, 0.5);
+
+        for check in network.gradient_check(&input, &expected_output, &Objective::CrossEntropy, 1e-6) {
+            assert!(check.relative_error < 1e-5, "Layer {} relative error {}", check.layer, check.relative_error);
+        }
+    }
+
+    #[test]
+    fn gradient_check_regularization() {
+        let input = arr2(&[[0.2, -0.4, 0.7], [0.9, 0.1, -0.3]]);
+        let expected_output = arr2(&[[0.0, 1.0], [1.0, 0.0]]);
+        let mut network = NeuralNetworkBuilder::new(3)
+            .initializer(Initializer::Xavier)
+            .regularization(Regularization { l1: 0.01, l2: 0.1 })
+            .layer(4, Activation::TanH)
+            .layer(2, Activation::Softmax)
+            .build();
+
+        let unregularized = Objective::CrossEntropy.calculate_error(&network.predict(&input), &expected_output);
+        assert!(network.penalty() > 0.0);
+        assert_eq!(network.compute_error_and_gradients(&input, &expected_output, &Objective::CrossEntropy).0, unregularized + network.penalty());
+        for check in network.gradient_check(&input, &expected_output, &Objective::CrossEntropy, 1e-6) {
+            assert!(check.relative_error < 1e-5, "Layer {} relative error {}", check.layer, check.relative_error);
+        }
+    }
+
+    #[test]
+    fn expected_input_reconstructs_the_input() {
+        let input = arr2(&[[0.2, -0.4], [0.7, 0.1]]);
+        let network = NeuralNetworkBuilder::new(2)
+            .layer(3, Activation::TanH)
+            .layer(3, Activation::Sigmoid)
+            .build();
+        let reconstructed = network.get_expected_input(&network.predict(&input)).unwrap();
+        assert!(reconstructed.all_close(&input, 1e-6));
+
+        // More inputs than neurons, the closest output is reached instead
+        let network = NeuralNetworkBuilder::new(3).layer(2, Activation::Identity).build();
+        let expected_output = arr2(&[[0.5, -1.0]]);
+        let reconstructed = network.get_expected_input(&expected_output).unwrap();
+        assert!(network.predict(&reconstructed).all_close(&expected_output, 1e-9));
+
+        let network = NeuralNetworkBuilder::new(2).layer(2, Activation::Binary(0.0)).build();
+        assert!(network.get_expected_input(&input).is_err());
+    }
+
+    #[test]
+    fn fallible_build_feed_forward_and_train() {
+        match NeuralNetworkBuilder::new(2).try_build() {
+            Err(Error::NoLayers) => (),
+            _ => panic!("A network without layers was built"),
+        }
+        match NeuralNetworkBuilder::new(0).layer(2, Activation::TanH).try_build() {
+            Err(Error::NoInputs) => (),
+            _ => panic!("A network without inputs was built"),
+        }
+        match NeuralNetworkBuilder::new(2).layer(6, Activation::TanH).attention(4, 1, AttentionMask::default()).try_build() {
+            Err(Error::SequenceLength { layer: 1, inputs: 6, sequence_length: 4 }) => (),
+            _ => panic!("Inputs were split into uneven tokens"),
+        }
+        // Only the first invalid layer is reported
+        match NeuralNetworkBuilder::new(6).attention(3, 0, AttentionMask::default()).layer(0, Activation::TanH).try_build() {
+            Err(Error::Heads { layer: 0, model_size: 2, heads: 0 }) => (),
+            _ => panic!("Attention without heads was built"),
+        }
+        match NeuralNetworkBuilder::new(6).transformer_encoder(3, 2, 0, AttentionMask::default()).try_build() {
+            Err(Error::ZeroSize { layer: 0 }) => (),
+            _ => panic!("Transformer encoder without feed forward network was built"),
+        }
+
+        let mut network = NeuralNetworkBuilder::new(2)
+            .layer(3, Activation::TanH)
+            .layer(2, Activation::Softmax)
+            .try_build()
+            .unwrap();
+        let input = arr2(&[[0.2, -0.4], [0.7, 0.1]]);
+        let expected_output = arr2(&[[1.0, 0.0], [0.0, 1.0]]);
+
+        match network.try_feed_forward(&arr2(&[[0.2, -0.4, 0.1]])) {
+            Err(Error::InputWidth { expected: 2, found: 3 }) => (),
+            _ => panic!("Wrong input width accepted"),
+        }
+        assert_eq!(network.try_predict(&input).unwrap().dim(), (2, 2));
+
+        match network.try_train(&input, &arr2(&[[1.0, 0.0]]), Objective::CrossEntropy, 1, 0.1) {
+            Err(Error::RowCount { inputs: 2, targets: 1 }) => (),
+            _ => panic!("Wrong row count accepted"),
+        }
+        match network.try_train(&input, &arr2(&[[1.0], [0.0]]), Objective::CrossEntropy, 1, 0.1) {
+            Err(Error::OutputWidth { expected: 2, found: 1 }) => (),
+            _ => panic!("Wrong output width accepted"),
+        }
+        match network.try_train(&input, &expected_output, Objective::CrossEntropy, 0, 0.1) {
+            Err(Error::ZeroBatchSize) => (),
+            _ => panic!("Empty batches accepted"),
+        }
+
+        // Softmax with a regression objective trains, with a warning
+        assert!(network.try_train(&input, &expected_output, Objective::CrossEntropy, 1, 0.1).unwrap().is_empty());
+        assert_eq!(network.try_train(&input, &expected_output, Objective::SumSquaredError, 1, 0.1).unwrap().len(), 1);
+
+        let mut network = NeuralNetworkBuilder::new(2).layer(2, Activation::Binary(0.5)).build();
+        match network.try_train(&input, &expected_output, Objective::SumSquaredError, 1, 0.1) {
+            Err(Error::NotTrainable { layer: 0, .. }) => (),
+            _ => panic!("Binary activation trained"),
+        }
+    }
+}
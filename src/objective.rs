@@ -2,86 +2,158 @@
 
 use ndarray::{Array2, Zip};
 
-#[derive(Copy, Clone)]
+use error::{Error, Result};
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Objective {
     // Classification : predicts a label
-    Log,    // Related to Cross Entropy ?
-    Focal,
+    Log,    // Binary cross entropy, expects outputs and labels between 0 and 1
+    Focal(f64),     // Focusing parameter gamma
     Exponential,
     Hinge,
     CrossEntropy,
 
     // Regression : predicts a quantity
-    SumSquaredError,    // Currently testing this one
+    SumSquaredError,
     MeanSquaredError,
     MeanAbsoluteError,
-    Huber,
+    Huber(f64),     // Threshold between quadratic and linear loss
     LogCosh,
-    Quantile,
+    Quantile(f64),  // Quantile to predict, between 0 and 1
 
-    // ?????
+    // Negative log likelihood, expects log probabilities as outputs (see Activation::LogSoftmax)
     Likelihood,
 }
 
+// Epsilon is used to prevent NaN when trying ln(0.0)
+const EPSILON: f64 = 1e-15;
+
+// Labels are given as 0 or 1, margin based objectives need -1 or 1
+fn sign_label(expected: f64) -> f64 {
+    if expected > 0.5 { 1.0 } else { -1.0 }
+}
+
 impl Objective {
+    pub fn name(&self) -> String {
+        match *self {
+            Objective::Log => "log".to_owned(),
+            Objective::Focal(gamma) => format!("focal({})", gamma),
+            Objective::Exponential => "exponential".to_owned(),
+            Objective::Hinge => "hinge".to_owned(),
+            Objective::CrossEntropy => "cross_entropy".to_owned(),
+            Objective::SumSquaredError => "sum_squared_error".to_owned(),
+            Objective::MeanSquaredError => "mean_squared_error".to_owned(),
+            Objective::MeanAbsoluteError => "mean_absolute_error".to_owned(),
+            Objective::Huber(threshold) => format!("huber({})", threshold),
+            Objective::LogCosh => "log_cosh".to_owned(),
+            Objective::Quantile(quantile) => format!("quantile({})", quantile),
+            Objective::Likelihood => "likelihood".to_owned(),
+        }
+    }
+
+    // Predicts a quantity rather than a label
+    pub fn is_regression(&self) -> bool {
+        matches!(*self, Objective::SumSquaredError | Objective::MeanSquaredError | Objective::MeanAbsoluteError
+            | Objective::Huber(_) | Objective::LogCosh | Objective::Quantile(_))
+    }
+
+    // Classification objectives are summed over columns, regression ones are averaged over every value
+    // Both are then averaged over the rows
     pub fn calculate_error(&self, output: &Array2<f64>, expected_output: &Array2<f64>) -> f64 {
         assert_eq!(output.rows(), expected_output.rows());
         assert_eq!(output.cols(), expected_output.cols());
-        match *self {
-            Objective::SumSquaredError => {
-                let mut squared_diffs: Array2<f64> = Array2::zeros(expected_output.dim());
-                Zip::from(&mut squared_diffs)
-                    .and(output.view())
-                    .and(expected_output.view())
-                    .apply(|d, expected, approx| *d = (expected - approx).powi(2));
-
 
-                let mut error = Array2::<f64>::zeros((output.rows(), 1));
-                for i in 0..error.rows() {
-                    error[[i, 0]] = 0.5 * squared_diffs.slice(s![i, ..]).scalar_sum();
-                }
+        let mut errors: Array2<f64> = Array2::zeros(expected_output.dim());
+        Zip::from(&mut errors)
+            .and(output)
+            .and(expected_output)
+            .apply(|error, &approx, &expected| *error = self.error(approx, expected));
 
-                error.scalar_sum() / error.rows() as f64
-            },
-            Objective::CrossEntropy => {
-                // Trying https://www.youtube.com/watch?v=PHP8beSz5o4
-                let mut result = 0.0;
-                // Epsilon is used to prevent NaN when trying ln(0.0)
-                let epsilon = 0.000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001;
-                for i in 0..output.rows() {
-                    for j in 0..output.cols() {
-                        //println!("Expected result : {:?}", expected_output[[i, j]]);
-                        //println!("Calculated result : {:?}", output[[i, j]]);
-                        //println!("Calculated result ln : {:?}", output[[i, j]].ln());
-                        //println!("Formula : {:?}", expected_output[[i, j]] * output[[i, j]].ln());
-
-                        result += expected_output[[i, j]] * (output[[i, j]] + epsilon).ln();
-                        //println!("Temp result : {:?}", result);
-                    }
-                }
+        errors.scalar_sum() / self.normalization(output)
+    }
 
-                (-1.0 / output.rows() as f64) * result
-            }
-            _ => unreachable!(),
+    // Same as calculate_error(), with an error instead of a panic when the shapes differ
+    pub fn try_calculate_error(&self, output: &Array2<f64>, expected_output: &Array2<f64>) -> Result<f64> {
+        if output.rows() != expected_output.rows() {
+            return Err(Error::RowCount { inputs: output.rows(), targets: expected_output.rows() });
         }
+        if output.cols() != expected_output.cols() {
+            return Err(Error::OutputWidth { expected: output.cols(), found: expected_output.cols() });
+        }
+        Ok(self.calculate_error(output, expected_output))
     }
 
+    // Gradient of calculate_error() with regard to the output
     pub fn compute_derivative(&self, output: &Array2<f64>, expected_output: &Array2<f64>) -> Array2<f64> {
         assert_eq!(output.rows(), expected_output.rows());
         assert_eq!(output.cols(), expected_output.cols());
 
+        let mut derivative: Array2<f64> = Array2::zeros(expected_output.dim());
+        Zip::from(&mut derivative)
+            .and(output)
+            .and(expected_output)
+            .apply(|d, &approx, &expected| *d = self.derivative(approx, expected));
+
+        derivative / self.normalization(output)
+    }
+
+    fn normalization(&self, output: &Array2<f64>) -> f64 {
         match *self {
-            Objective::SumSquaredError => {
-                2.0 * (expected_output - output)    // TODO : probably not good, look it up
-            },
-            Objective::CrossEntropy => {
-                // Trying https://www.youtube.com/watch?v=PHP8beSz5o4
-                //output - expected_output
-                expected_output - output
+            Objective::MeanSquaredError
+            | Objective::MeanAbsoluteError
+            | Objective::Huber(_)
+            | Objective::LogCosh
+            | Objective::Quantile(_) => (output.rows() * output.cols()) as f64,
+            _ => output.rows() as f64,
+        }
+    }
+
+    fn error(&self, approx: f64, expected: f64) -> f64 {
+        let diff = expected - approx;
+        match *self {
+            Objective::Log => -(expected * (approx + EPSILON).ln() + (1.0 - expected) * (1.0 - approx + EPSILON).ln()),
+            Objective::Focal(gamma) => -expected * (1.0 - approx).powf(gamma) * (approx + EPSILON).ln(),
+            Objective::Exponential => (-sign_label(expected) * approx).exp(),
+            Objective::Hinge => (1.0 - sign_label(expected) * approx).max(0.0),
+            Objective::CrossEntropy => -expected * (approx + EPSILON).ln(),
+            Objective::SumSquaredError => 0.5 * diff.powi(2),
+            Objective::MeanSquaredError => diff.powi(2),
+            Objective::MeanAbsoluteError => diff.abs(),
+            Objective::Huber(delta) => {
+                if diff.abs() <= delta {
+                    0.5 * diff.powi(2)
+                } else {
+                    delta * (diff.abs() - 0.5 * delta)
+                }
             },
-            _ => unreachable!(),
+            Objective::LogCosh => diff.cosh().ln(),
+            Objective::Quantile(quantile) => (quantile * diff).max((quantile - 1.0) * diff),
+            Objective::Likelihood => -expected * approx,
         }
+    }
 
+    fn derivative(&self, approx: f64, expected: f64) -> f64 {
+        let diff = approx - expected;
+        match *self {
+            Objective::Log => (1.0 - expected) / (1.0 - approx + EPSILON) - expected / (approx + EPSILON),
+            Objective::Focal(gamma) => {
+                expected * (gamma * (1.0 - approx).powf(gamma - 1.0) * (approx + EPSILON).ln()
+                    - (1.0 - approx).powf(gamma) / (approx + EPSILON))
+            },
+            Objective::Exponential => -sign_label(expected) * (-sign_label(expected) * approx).exp(),
+            Objective::Hinge => {
+                if 1.0 - sign_label(expected) * approx > 0.0 { -sign_label(expected) } else { 0.0 }
+            },
+            Objective::CrossEntropy => -expected / (approx + EPSILON),
+            Objective::SumSquaredError => diff,
+            Objective::MeanSquaredError => 2.0 * diff,
+            Objective::MeanAbsoluteError => if diff > 0.0 { 1.0 } else if diff < 0.0 { -1.0 } else { 0.0 },
+            Objective::Huber(delta) => diff.max(-delta).min(delta),
+            Objective::LogCosh => diff.tanh(),
+            Objective::Quantile(quantile) => if diff < 0.0 { -quantile } else { 1.0 - quantile },
+            Objective::Likelihood => -expected,
+        }
     }
 }
 
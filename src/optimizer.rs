@@ -0,0 +1,145 @@
+// Optimizers update the parameters of a network from its gradients
+
+use ndarray::Array2;
+
+use network::NeuralNetwork;
+use gradients::Gradients;
+
+
+#[derive(Copy, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Optimizer {
+    #[default]
+    #[serde(rename = "sgd")]
+    StochasticGradientDescent,
+    Momentum(f64),
+    #[serde(rename = "rmsprop")]
+    RMSProp { decay: f64, epsilon: f64 },
+    Adam { beta1: f64, beta2: f64, epsilon: f64 },
+}
+
+// Values kept between two steps, one moment matrix per parameter
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct OptimizerState {
+    pub steps: u64,
+    pub first_moments: Vec<Vec<Array2<f64>>>,
+    pub second_moments: Vec<Vec<Array2<f64>>>,
+}
+
+impl Optimizer {
+    pub fn adam() -> Self {
+        Optimizer::Adam { beta1: 0.9, beta2: 0.999, epsilon: 1e-8 }
+    }
+
+    pub fn step(&self, state: &mut OptimizerState, network: &mut NeuralNetwork, gradients: &Gradients, learning_rate: f64) {
+        if state.first_moments.is_empty() {
+            state.first_moments = zeros_like(gradients);
+            state.second_moments = zeros_like(gradients);
+        }
+        state.steps += 1;
+        let steps = state.steps as i32;
+
+        for (i, parameters) in network.parameters_mut().into_iter().enumerate() {
+            for (j, parameter) in parameters.into_iter().enumerate() {
+                let gradient = &gradients.layers[i][j];
+                let first_moment = &mut state.first_moments[i][j];
+                let second_moment = &mut state.second_moments[i][j];
+
+                match *self {
+                    Optimizer::StochasticGradientDescent => {
+                        parameter.scaled_add(-learning_rate, gradient);
+                    },
+                    Optimizer::Momentum(momentum) => {
+                        *first_moment *= momentum;
+                        first_moment.scaled_add(learning_rate, gradient);
+                        *parameter -= &*first_moment;
+                    },
+                    Optimizer::RMSProp { decay, epsilon } => {
+                        *second_moment *= decay;
+                        second_moment.scaled_add(1.0 - decay, &gradient.map(|v| v * v));
+                        *parameter -= &(gradient / &second_moment.map(|v| v.sqrt() + epsilon) * learning_rate);
+                    },
+                    Optimizer::Adam { beta1, beta2, epsilon } => {
+                        *first_moment *= beta1;
+                        first_moment.scaled_add(1.0 - beta1, gradient);
+                        *second_moment *= beta2;
+                        second_moment.scaled_add(1.0 - beta2, &gradient.map(|v| v * v));
+
+                        // Bias correction of the moments, both start at zero
+                        let first_correction = 1.0 - beta1.powi(steps);
+                        let second_correction = 1.0 - beta2.powi(steps);
+                        let update = first_moment.map(|v| v / first_correction)
+                            / second_moment.map(|v| (v / second_correction).sqrt() + epsilon);
+                        parameter.scaled_add(-learning_rate, &update);
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn zeros_like(gradients: &Gradients) -> Vec<Vec<Array2<f64>>> {
+    gradients.layers.iter()
+        .map(|layer| layer.iter().map(|gradient| Array2::<f64>::zeros(gradient.dim())).collect())
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use activation::Activation;
+    use super::*;
+
+    // Weight of a single neuron starting at 1.0 after each step with the given gradients, learning rate 0.1
+    fn weights(optimizer: Optimizer, gradients: &[f64]) -> Vec<f64> {
+        let mut network = NeuralNetworkBuilder::new(1).layer(1, Activation::Identity).build();
+        for parameter in network.parameters_mut().remove(0) {
+            parameter.fill(1.0);
+        }
+        let mut state = OptimizerState::default();
+
+        gradients.iter().map(|gradient| {
+            let gradients = Gradients::new(vec![vec![arr2(&[[*gradient]]), arr2(&[[*gradient]])]]);
+            optimizer.step(&mut state, &mut network, &gradients, 0.1);
+            network.parameters()[0][0][[0, 0]]
+        }).collect()
+    }
+
+    fn assert_close(found: Vec<f64>, expected: &[f64]) {
+        assert_eq!(found.len(), expected.len());
+        for (found, expected) in found.iter().zip(expected) {
+            assert!((found - expected).abs() < 1e-12, "{} instead of {}", found, expected);
+        }
+    }
+
+    #[test]
+    fn sgd_and_momentum() {
+        assert_close(weights(Optimizer::StochasticGradientDescent, &[0.5, -0.2]), &[0.95, 0.97]);
+        // Velocity 0.1 × 0.5 = 0.05, then 0.9 × 0.05 + 0.1 × -0.2 = 0.025
+        assert_close(weights(Optimizer::Momentum(0.9), &[0.5, -0.2]), &[0.95, 0.925]);
+    }
+
+    #[test]
+    fn rmsprop() {
+        // Mean square 0.1 × 0.25 = 0.025, then 0.9 × 0.025 + 0.1 × 0.04 = 0.0265
+        let first = 1.0 - 0.1 * 0.5 / (0.025f64.sqrt() + 1e-8);
+        let second = first + 0.1 * 0.2 / (0.0265f64.sqrt() + 1e-8);
+        assert_close(weights(Optimizer::RMSProp { decay: 0.9, epsilon: 1e-8 }, &[0.5, -0.2]), &[first, second]);
+        assert!((second - 0.80663126980).abs() < 1e-10);
+    }
+
+    #[test]
+    fn adam_corrects_the_bias_of_its_moments() {
+        // Corrected moments of the first step are the gradient and its square, so the step is the learning rate
+        let first = 1.0 - 0.1 * 0.5 / (0.5 + 1e-8);
+        // Moments 0.9 × 0.05 + 0.1 × -0.2 = 0.025 and 0.999 × 0.00025 + 0.001 × 0.04 = 0.00028975
+        let first_moment = 0.025 / (1.0 - 0.9 * 0.9);
+        let second_moment: f64 = 0.00028975 / (1.0 - 0.999 * 0.999);
+        let second = first - 0.1 * first_moment / (second_moment.sqrt() + 1e-8);
+        assert_close(weights(Optimizer::adam(), &[0.5, -0.2]), &[first, second]);
+        assert!((first - 0.9).abs() < 1e-8);
+        assert!((second - 0.86543941812).abs() < 1e-10);
+    }
+}
@@ -0,0 +1,316 @@
+// Scalers and encoders fitted on training data and reused on validation, test and inference data
+//
+// Scalers work column by column, their statistics are 1×columns matrices broadcast over the rows.
+// Categories are sorted so that fitting the same values always gives the same encoding.
+
+use std::io;
+
+use ndarray::{Array2, Axis};
+
+use network::NeuralNetwork;
+use classification::argmax;
+
+
+// Fitted scaler of any kind, e.g. to be saved with the network
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Scaler {
+    Standard(StandardScaler),
+    MinMax(MinMaxScaler),
+    Robust(RobustScaler),
+}
+
+impl Scaler {
+    pub fn transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        match *self {
+            Scaler::Standard(ref scaler) => scaler.transform(data),
+            Scaler::MinMax(ref scaler) => scaler.transform(data),
+            Scaler::Robust(ref scaler) => scaler.transform(data),
+        }
+    }
+
+    pub fn inverse_transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        match *self {
+            Scaler::Standard(ref scaler) => scaler.inverse_transform(data),
+            Scaler::MinMax(ref scaler) => scaler.inverse_transform(data),
+            Scaler::Robust(ref scaler) => scaler.inverse_transform(data),
+        }
+    }
+}
+
+
+// Zero mean and unit variance
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StandardScaler {
+    pub mean: Array2<f64>,
+    pub deviation: Array2<f64>,
+}
+
+impl StandardScaler {
+    pub fn fit(data: &Array2<f64>) -> Self {
+        let mean = column_statistic(data, |column| column.iter().sum::<f64>() / column.len() as f64);
+        let deviation = column_statistic(data, |column| {
+            let mean = column.iter().sum::<f64>() / column.len() as f64;
+            (column.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / column.len() as f64).sqrt()
+        });
+        Self {
+            mean,
+            deviation: non_zero(deviation),
+        }
+    }
+
+    pub fn transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        check_columns(data, &self.mean);
+        (data - &self.mean) / &self.deviation
+    }
+
+    pub fn inverse_transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        check_columns(data, &self.mean);
+        data * &self.deviation + &self.mean
+    }
+}
+
+
+// Minimum of the fitted data to range.0, maximum to range.1
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MinMaxScaler {
+    pub minimum: Array2<f64>,
+    pub maximum: Array2<f64>,
+    pub range: (f64, f64),
+}
+
+impl MinMaxScaler {
+    pub fn fit(data: &Array2<f64>) -> Self {
+        Self::fit_range(data, (0.0, 1.0))
+    }
+
+    pub fn fit_range(data: &Array2<f64>, range: (f64, f64)) -> Self {
+        Self {
+            minimum: column_statistic(data, |column| column.iter().cloned().fold(f64::INFINITY, f64::min)),
+            maximum: column_statistic(data, |column| column.iter().cloned().fold(f64::NEG_INFINITY, f64::max)),
+            range,
+        }
+    }
+
+    pub fn transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        check_columns(data, &self.minimum);
+        (data - &self.minimum) / &self.extent() * (self.range.1 - self.range.0) + self.range.0
+    }
+
+    pub fn inverse_transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        check_columns(data, &self.minimum);
+        (data - self.range.0) / (self.range.1 - self.range.0) * &self.extent() + &self.minimum
+    }
+
+    fn extent(&self) -> Array2<f64> {
+        non_zero(&self.maximum - &self.minimum)
+    }
+}
+
+
+// Median and interquartile range, so outliers barely change the scaling
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RobustScaler {
+    pub median: Array2<f64>,
+    pub interquartile_range: Array2<f64>,
+}
+
+impl RobustScaler {
+    pub fn fit(data: &Array2<f64>) -> Self {
+        Self {
+            median: column_statistic(data, |column| quantile(column, 0.5)),
+            interquartile_range: non_zero(column_statistic(data, |column| quantile(column, 0.75) - quantile(column, 0.25))),
+        }
+    }
+
+    pub fn transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        check_columns(data, &self.median);
+        (data - &self.median) / &self.interquartile_range
+    }
+
+    pub fn inverse_transform(&self, data: &Array2<f64>) -> Array2<f64> {
+        check_columns(data, &self.median);
+        data * &self.interquartile_range + &self.median
+    }
+}
+
+
+// The scalers are saved with the network, so a loaded network preprocesses exactly like during training
+impl NeuralNetwork {
+    // Inputs as the network expects them, to call before feed_forward(), predict(), evaluate()...
+    pub fn preprocess(&self, input: &Array2<f64>) -> Array2<f64> {
+        match self.input_scaler {
+            Some(ref scaler) => scaler.transform(input),
+            None => input.clone(),
+        }
+    }
+
+    // Targets as the network is trained to output them
+    pub fn preprocess_targets(&self, targets: &Array2<f64>) -> Array2<f64> {
+        match self.target_scaler {
+            Some(ref scaler) => scaler.transform(targets),
+            None => targets.clone(),
+        }
+    }
+
+    // Outputs of the network back in the scale of the targets
+    pub fn postprocess(&self, output: &Array2<f64>) -> Array2<f64> {
+        match self.target_scaler {
+            Some(ref scaler) => scaler.inverse_transform(output),
+            None => output.clone(),
+        }
+    }
+}
+
+
+// Category to its index, e.g. to feed an embedding layer or to decode predicted classes
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LabelEncoder {
+    pub categories: Vec<String>,
+}
+
+impl LabelEncoder {
+    pub fn fit<S: AsRef<str>>(values: &[S]) -> Self {
+        Self {
+            categories: fit_categories(values),
+        }
+    }
+
+    pub fn index(&self, value: &str) -> Option<usize> {
+        self.categories.iter().position(|category| category == value)
+    }
+
+    // One column holding the index of every value
+    pub fn transform<S: AsRef<str>>(&self, values: &[S]) -> io::Result<Array2<f64>> {
+        let mut encoded = Array2::<f64>::zeros((values.len(), 1));
+        for (i, value) in values.iter().enumerate() {
+            encoded[[i, 0]] = category_index(&self.categories, value.as_ref())? as f64;
+        }
+        Ok(encoded)
+    }
+
+    pub fn inverse_transform(&self, indices: &[usize]) -> Vec<&str> {
+        indices.iter().map(|index| self.categories[*index].as_str()).collect()
+    }
+}
+
+
+// Category to a row with a 1 in the column of the category
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OneHotEncoder {
+    pub categories: Vec<String>,
+}
+
+impl OneHotEncoder {
+    pub fn fit<S: AsRef<str>>(values: &[S]) -> Self {
+        Self {
+            categories: fit_categories(values),
+        }
+    }
+
+    pub fn index(&self, value: &str) -> Option<usize> {
+        self.categories.iter().position(|category| category == value)
+    }
+
+    pub fn transform<S: AsRef<str>>(&self, values: &[S]) -> io::Result<Array2<f64>> {
+        let mut encoded = Array2::<f64>::zeros((values.len(), self.categories.len()));
+        for (i, value) in values.iter().enumerate() {
+            encoded[[i, category_index(&self.categories, value.as_ref())?]] = 1.0;
+        }
+        Ok(encoded)
+    }
+
+    // Category of the greatest column of every row, so it also decodes probabilities
+    pub fn inverse_transform(&self, encoded: &Array2<f64>) -> Vec<&str> {
+        encoded.axis_iter(Axis(0)).map(|row| self.categories[argmax(row)].as_str()).collect()
+    }
+}
+
+
+fn column_statistic<F: Fn(&[f64]) -> f64>(data: &Array2<f64>, statistic: F) -> Array2<f64> {
+    assert!(data.rows() > 0, "No rows to fit on");
+    let mut result = Array2::<f64>::zeros((1, data.cols()));
+    for (j, column) in data.gencolumns().into_iter().enumerate() {
+        result[[0, j]] = statistic(&column.to_vec());
+    }
+    result
+}
+
+// Constant columns are only shifted instead of divided by zero
+fn non_zero(mut scale: Array2<f64>) -> Array2<f64> {
+    scale.mapv_inplace(|v| if v == 0.0 { 1.0 } else { v });
+    scale
+}
+
+// Linear interpolation between the closest ranks
+fn quantile(values: &[f64], q: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+fn check_columns(data: &Array2<f64>, statistic: &Array2<f64>) {
+    assert_eq!(data.cols(), statistic.cols(), "Data does not have the amount of columns the scaler was fitted on");
+}
+
+fn fit_categories<S: AsRef<str>>(values: &[S]) -> Vec<String> {
+    let mut categories: Vec<String> = values.iter().map(|value| value.as_ref().to_owned()).collect();
+    categories.sort();
+    categories.dedup();
+    categories
+}
+
+fn category_index(categories: &[String], value: &str) -> io::Result<usize> {
+    categories.iter().position(|category| category == value)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Unknown category {:?}", value)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use super::*;
+
+    #[test]
+    fn scalers() {
+        let data = arr2(&[[1.0, 10.0, 5.0], [2.0, 20.0, 5.0], [3.0, 30.0, 5.0], [4.0, 1000.0, 5.0]]);
+
+        let standard = StandardScaler::fit(&data);
+        let scaled = standard.transform(&data);
+        assert!(scaled.sum_axis(Axis(0)).iter().all(|sum| sum.abs() < 1e-12));
+        assert!((scaled.column(0).iter().map(|v| v * v).sum::<f64>() / 4.0 - 1.0).abs() < 1e-12);
+        assert!(standard.inverse_transform(&scaled).all_close(&data, 1e-12));
+
+        let min_max = MinMaxScaler::fit_range(&data, (-1.0, 1.0));
+        let scaled = min_max.transform(&data);
+        assert!(scaled.column(0).all_close(&arr2(&[[-1.0, -1.0 / 3.0, 1.0 / 3.0, 1.0]]).row(0), 1e-12));
+        assert_eq!(scaled.column(2).to_vec(), vec![-1.0; 4]);
+        assert!(min_max.inverse_transform(&scaled).all_close(&data, 1e-12));
+
+        // The outlier of the second column does not change its median nor its interquartile range much
+        let robust = RobustScaler::fit(&data);
+        assert_eq!(robust.median, arr2(&[[2.5, 25.0, 5.0]]));
+        assert_eq!(robust.interquartile_range, arr2(&[[1.5, 272.5 - 17.5, 1.0]]));
+        assert!(robust.inverse_transform(&robust.transform(&data)).all_close(&data, 1e-12));
+    }
+
+    #[test]
+    fn categorical_encoders() {
+        let values = ["red", "green", "red", "blue"];
+
+        let labels = LabelEncoder::fit(&values);
+        assert_eq!(labels.categories, vec!["blue", "green", "red"]);
+        assert_eq!(labels.transform(&values).unwrap(), arr2(&[[2.0], [1.0], [2.0], [0.0]]));
+        assert_eq!(labels.inverse_transform(&[1, 0]), vec!["green", "blue"]);
+
+        let one_hot = OneHotEncoder::fit(&values);
+        let encoded = one_hot.transform(&["green", "blue"]).unwrap();
+        assert_eq!(encoded, arr2(&[[0.0, 1.0, 0.0], [1.0, 0.0, 0.0]]));
+        assert_eq!(one_hot.inverse_transform(&arr2(&[[0.1, 0.3, 0.6], [0.7, 0.2, 0.1]])), vec!["red", "blue"]);
+
+        assert!(labels.transform(&["purple"]).is_err());
+        assert!(one_hot.transform(&["purple"]).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+// Learning rate as a function of the number of optimizer steps already done
+
+use std::f64::consts::PI;
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LearningRateSchedule {
+    Constant(f64),
+    // Multiply by decay every steps
+    StepDecay { initial: f64, decay: f64, steps: u64 },
+    // Multiply by decay after each step
+    ExponentialDecay { initial: f64, decay: f64 },
+    // Half cosine from initial to minimum over steps, then stays at minimum
+    CosineAnnealing { initial: f64, minimum: f64, steps: u64 },
+}
+
+impl LearningRateSchedule {
+    pub fn learning_rate(&self, step: u64) -> f64 {
+        match *self {
+            LearningRateSchedule::Constant(learning_rate) => learning_rate,
+            LearningRateSchedule::StepDecay { initial, decay, steps } => {
+                initial * decay.powi((step / steps.max(1)) as i32)
+            },
+            LearningRateSchedule::ExponentialDecay { initial, decay } => {
+                initial * decay.powf(step as f64)
+            },
+            LearningRateSchedule::CosineAnnealing { initial, minimum, steps } => {
+                let progress = step.min(steps) as f64 / steps.max(1) as f64;
+                minimum + 0.5 * (initial - minimum) * (1.0 + (PI * progress).cos())
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_rates(schedule: LearningRateSchedule, expected: &[(u64, f64)]) {
+        for &(step, learning_rate) in expected {
+            let found = schedule.learning_rate(step);
+            assert!((found - learning_rate).abs() < 1e-12, "Step {}: {} instead of {}", step, found, learning_rate);
+        }
+    }
+
+    #[test]
+    fn learning_rates() {
+        assert_rates(LearningRateSchedule::Constant(0.1), &[(0, 0.1), (1000, 0.1)]);
+        assert_rates(LearningRateSchedule::StepDecay { initial: 0.1, decay: 0.5, steps: 10 },
+                     &[(0, 0.1), (9, 0.1), (10, 0.05), (25, 0.025)]);
+        // 0.1 × 0.99^10
+        assert_rates(LearningRateSchedule::ExponentialDecay { initial: 0.1, decay: 0.99 },
+                     &[(0, 0.1), (1, 0.099), (10, 0.090438207500880)]);
+        // Half way is the mean of initial and minimum, a quarter is 0.1 + 0.45 × (1 + cos(π / 4))
+        assert_rates(LearningRateSchedule::CosineAnnealing { initial: 1.0, minimum: 0.1, steps: 100 },
+                     &[(0, 1.0), (25, 0.868198051533946), (50, 0.55), (100, 0.1), (200, 0.1)]);
+    }
+}
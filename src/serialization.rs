@@ -0,0 +1,388 @@
+// Save and load networks, architecture and parameters
+//
+// Both formats store the same SavedModel :
+// - JSON, human readable
+// - binary, MAGIC bytes followed by the bincode encoded model
+//
+// Files of another format version are rejected.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use ndarray::Array2;
+use serde::de::DeserializeOwned;
+use serde_json;
+use bincode;
+
+use network::NeuralNetwork;
+use layer::{Layer, Dense, Regularization};
+use activation::Activation;
+use attention::{AttentionMask, MultiHeadAttention, TransformerEncoder};
+use preprocessing::Scaler;
+use tabular::TabularEncoder;
+
+
+// Increase when SavedModel changes
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"NNET";
+
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedModel {
+    pub format_version: u32,
+    pub layers: Vec<SavedLayer>,
+    pub labels: Option<Vec<String>>,
+    pub input_scaler: Option<Scaler>,
+    pub target_scaler: Option<Scaler>,
+    pub encoder: Option<TabularEncoder>,
+}
+
+// Parameters are stored in the order of Layer::parameters()
+#[derive(Serialize, Deserialize)]
+pub enum SavedLayer {
+    Dense {
+        activation_function: Activation,
+        weights: Array2<f64>,
+        bias: Array2<f64>,
+        regularization: Regularization,
+    },
+    MultiHeadAttention {
+        sequence_length: usize,
+        model_size: usize,
+        heads: usize,
+        mask: AttentionMask,
+        parameters: Vec<Array2<f64>>,
+    },
+    TransformerEncoder {
+        sequence_length: usize,
+        model_size: usize,
+        heads: usize,
+        feed_forward_size: usize,
+        mask: AttentionMask,
+        parameters: Vec<Array2<f64>>,
+    },
+}
+
+impl SavedLayer {
+    pub fn from_layer(layer: &Layer) -> Self {
+        let parameters = layer.parameters().into_iter().cloned().collect();
+        match *layer {
+            Layer::Dense(ref layer) => SavedLayer::Dense {
+                activation_function: layer.activation_function,
+                weights: layer.weights.clone(),
+                bias: layer.bias.clone(),
+                regularization: layer.regularization,
+            },
+            Layer::MultiHeadAttention(ref layer) => SavedLayer::MultiHeadAttention {
+                sequence_length: layer.sequence_length(),
+                model_size: layer.model_size(),
+                heads: layer.heads(),
+                mask: layer.mask,
+                parameters,
+            },
+            Layer::TransformerEncoder(ref layer) => SavedLayer::TransformerEncoder {
+                sequence_length: layer.attention.sequence_length(),
+                model_size: layer.attention.model_size(),
+                heads: layer.attention.heads(),
+                feed_forward_size: layer.feed_forward_hidden.weights.cols(),
+                mask: layer.attention.mask,
+                parameters,
+            },
+        }
+    }
+
+    pub fn to_layer(&self) -> io::Result<Layer> {
+        let (mut layer, parameters) = match *self {
+            SavedLayer::Dense { activation_function, ref weights, ref bias, regularization } => {
+                let mut dense = Dense::new(weights.cols(), weights.rows(), activation_function);
+                dense.regularization = regularization;
+                (Layer::Dense(dense), vec![weights.clone(), bias.clone()])
+            },
+            SavedLayer::MultiHeadAttention { sequence_length, model_size, heads, mask, ref parameters } => {
+                check_attention(sequence_length, model_size, heads)?;
+                let layer = Layer::MultiHeadAttention(MultiHeadAttention::new(sequence_length, model_size, heads, mask));
+                (layer, parameters.clone())
+            },
+            SavedLayer::TransformerEncoder { sequence_length, model_size, heads, feed_forward_size, mask, ref parameters } => {
+                check_attention(sequence_length, model_size, heads)?;
+                if feed_forward_size == 0 {
+                    return Err(invalid_data("Feed forward network without neurons".to_owned()));
+                }
+                let layer = Layer::TransformerEncoder(TransformerEncoder::new(sequence_length, model_size, heads, feed_forward_size, mask));
+                (layer, parameters.clone())
+            },
+        };
+
+        {
+            let mut targets = layer.parameters_mut();
+            if targets.len() != parameters.len() {
+                return Err(invalid_data(format!("Expected {} parameters, found {}", targets.len(), parameters.len())));
+            }
+            for (target, parameter) in targets.iter_mut().zip(parameters) {
+                if target.dim() != parameter.dim() {
+                    return Err(invalid_data(format!("Expected parameter of shape {:?}, found {:?}", target.dim(), parameter.dim())));
+                }
+                **target = parameter;
+            }
+        }
+
+        Ok(layer)
+    }
+
+    fn inputs(&self) -> usize {
+        match *self {
+            SavedLayer::Dense { ref weights, .. } => weights.rows(),
+            SavedLayer::MultiHeadAttention { sequence_length, model_size, .. }
+            | SavedLayer::TransformerEncoder { sequence_length, model_size, .. } => sequence_length * model_size,
+        }
+    }
+
+    fn outputs(&self) -> usize {
+        match *self {
+            SavedLayer::Dense { ref weights, .. } => weights.cols(),
+            _ => self.inputs(),
+        }
+    }
+}
+
+impl SavedModel {
+    pub fn from_network(network: &NeuralNetwork) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            layers: network.layers().iter().map(SavedLayer::from_layer).collect(),
+            labels: network.labels.clone(),
+            input_scaler: network.input_scaler.clone(),
+            target_scaler: network.target_scaler.clone(),
+            encoder: network.encoder.clone(),
+        }
+    }
+
+    pub fn to_network(&self) -> io::Result<NeuralNetwork> {
+        if self.format_version != FORMAT_VERSION {
+            return Err(unsupported_version(self.format_version));
+        }
+        if self.layers.is_empty() {
+            return Err(invalid_data("No layers defined".to_owned()));
+        }
+        for (i, pair) in self.layers.windows(2).enumerate() {
+            if pair[0].outputs() != pair[1].inputs() {
+                return Err(invalid_data(format!("Layer {} has {} outputs but layer {} expects {} inputs", i, pair[0].outputs(), i + 1, pair[1].inputs())));
+            }
+        }
+
+        let mut layers = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            layers.push(layer.to_layer()?);
+        }
+        let mut network = NeuralNetwork::new(layers);
+        network.labels = self.labels.clone();
+        network.input_scaler = self.input_scaler.clone();
+        network.target_scaler = self.target_scaler.clone();
+        network.encoder = self.encoder.clone();
+        Ok(network)
+    }
+
+    pub fn write_json<W: Write>(&self, writer: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn read_json<R: Read>(reader: R) -> io::Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(writer, self).map_err(|e| invalid_data(e.to_string()))
+    }
+
+    pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("Not a binary model file".to_owned()));
+        }
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        // Checked before decoding, the other fields of another version do not match
+        let version = read_version(&bytes)?;
+        if version != FORMAT_VERSION {
+            return Err(unsupported_version(version));
+        }
+        decode(&bytes)
+    }
+}
+
+impl NeuralNetwork {
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        SavedModel::from_network(self).write_json(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load_json<P: AsRef<Path>>(path: P) -> io::Result<NeuralNetwork> {
+        SavedModel::read_json(BufReader::new(File::open(path)?))?.to_network()
+    }
+
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        SavedModel::from_network(self).write_binary(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load_binary<P: AsRef<Path>>(path: P) -> io::Result<NeuralNetwork> {
+        SavedModel::read_binary(BufReader::new(File::open(path)?))?.to_network()
+    }
+}
+
+
+// Version written first by bincode, before the other fields
+pub fn read_version(bytes: &[u8]) -> io::Result<u32> {
+    decode(bytes)
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> io::Result<T> {
+    bincode::deserialize(bytes).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn unsupported_version(version: u32) -> io::Error {
+    invalid_data(format!("Unsupported format version {}, only version {} can be read", version, FORMAT_VERSION))
+}
+
+// The constructors expect valid sizes
+fn check_attention(sequence_length: usize, model_size: usize, heads: usize) -> io::Result<()> {
+    if sequence_length == 0 || model_size == 0 {
+        return Err(invalid_data(format!("Attention over {} tokens of size {}", sequence_length, model_size)));
+    }
+    if heads == 0 || !model_size.is_multiple_of(heads) {
+        return Err(invalid_data(format!("Model size {} can not be split into {} heads", model_size, heads)));
+    }
+    Ok(())
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use preprocessing::{StandardScaler, RobustScaler};
+    use tabular::{CsvLoader, ColumnType};
+    use super::*;
+
+    fn network() -> NeuralNetwork {
+        NeuralNetworkBuilder::new(8)
+            .transformer_encoder(4, 2, 3, AttentionMask { causal: true, padding: false })
+            .attention(2, 2, AttentionMask::default())
+            .layer(5, Activation::LeakyReLU(0.3))
+            .layer(3, Activation::Softmax)
+            .build()
+    }
+
+    fn input() -> Array2<f64> {
+        arr2(&[[0.2, -0.4, 0.7, 0.1, 0.9, 0.1, -0.3, 0.5], [0.3, 0.8, -0.1, 0.6, -0.2, 0.4, 0.0, 0.0]])
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let mut network = network();
+        let path = env::temp_dir().join("neural_network_json_round_trip.json");
+
+        network.save_json(&path).unwrap();
+        let mut loaded = NeuralNetwork::load_json(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(network.feed_forward(&input()), loaded.feed_forward(&input()));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut network = network();
+        let path = env::temp_dir().join("neural_network_binary_round_trip.bin");
+
+        network.save_binary(&path).unwrap();
+        let mut loaded = NeuralNetwork::load_binary(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(network.feed_forward(&input()), loaded.feed_forward(&input()));
+    }
+
+    #[test]
+    fn scalers_are_saved() {
+        let mut network = NeuralNetworkBuilder::new(2).layer(1, Activation::Identity).build();
+        network.input_scaler = Some(Scaler::Standard(StandardScaler::fit(&arr2(&[[1.0, 5.0], [3.0, 9.0]]))));
+        network.target_scaler = Some(Scaler::Robust(RobustScaler::fit(&arr2(&[[10.0], [20.0], [40.0]]))));
+        let path = env::temp_dir().join("neural_network_scalers.bin");
+
+        network.save_binary(&path).unwrap();
+        let loaded = NeuralNetwork::load_binary(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let input = arr2(&[[2.0, 7.0], [4.0, 3.0]]);
+        assert_eq!(network.preprocess(&input), loaded.preprocess(&input));
+        assert_eq!(network.postprocess(&input.slice(s![.., ..1]).to_owned()), loaded.postprocess(&input.slice(s![.., ..1]).to_owned()));
+    }
+
+    #[test]
+    fn column_encoding_is_saved() {
+        let data = "size,color,price\n1.5,red,10\n2.0,blue,20\n";
+        let loaded = CsvLoader::new()
+            .feature("size", ColumnType::Numeric)
+            .feature("color", ColumnType::OneHot)
+            .target("price", ColumnType::Numeric)
+            .load_from(data.as_bytes())
+            .unwrap();
+        let mut network = NeuralNetworkBuilder::new(3).layer(1, Activation::Identity).build();
+        network.encoder = Some(loaded.encoder);
+        let path = env::temp_dir().join("neural_network_column_encoding.bin");
+
+        network.save_binary(&path).unwrap();
+        let loaded = NeuralNetwork::load_binary(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let new_data = "size,color,price\n3.0,red,30\n";
+        let inputs = loaded.encoder.unwrap().transform_from(new_data.as_bytes()).unwrap();
+        assert_eq!(inputs, network.encoder.unwrap().transform_from(new_data.as_bytes()).unwrap());
+        assert_eq!(inputs, arr2(&[[3.0, 0.0, 1.0]]));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut model = SavedModel::from_network(&network());
+        model.format_version = FORMAT_VERSION + 1;
+
+        let mut buffer = Vec::new();
+        model.write_json(&mut buffer).unwrap();
+        assert!(SavedModel::read_json(&buffer[..]).unwrap().to_network().is_err());
+
+        let mut buffer = Vec::new();
+        model.write_binary(&mut buffer).unwrap();
+        let error = SavedModel::read_binary(&buffer[..]).err().unwrap();
+        assert!(error.to_string().contains(&format!("Unsupported format version {}", FORMAT_VERSION + 1)));
+    }
+
+    #[test]
+    fn rejects_corrupted_layers() {
+        let mut buffer = Vec::new();
+        SavedModel::from_network(&network()).write_json(&mut buffer).unwrap();
+        let json = String::from_utf8(buffer).unwrap();
+        assert!(SavedModel::read_json(json.as_bytes()).unwrap().to_network().is_ok());
+
+        for (field, corrupted) in &[("\"feed_forward_size\": 3", "\"feed_forward_size\": 0"),
+                                    ("\"sequence_length\": 4", "\"sequence_length\": 0"),
+                                    ("\"model_size\": 2", "\"model_size\": 0"),
+                                    ("\"heads\": 2", "\"heads\": 0")] {
+            assert!(json.contains(field));
+            let model = SavedModel::read_json(json.replacen(field, corrupted, 1).as_bytes()).unwrap();
+            assert_eq!(model.to_network().err().unwrap().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+// Train/validation/test splits and k-fold cross-validation
+//
+// Stratified variants keep the proportion of every class (see metrics::to_classes()) in each part.
+
+use std::fmt;
+use std::io;
+
+use ndarray::{Array2, Axis};
+use rand::Rng;
+
+use network::NeuralNetwork;
+use training::{Trainer, TrainingConfig};
+use metrics::{Metric, Evaluation, to_classes};
+use data::seeded_rng;
+use error::Warning;
+
+
+pub struct Subset {
+    pub inputs: Array2<f64>,
+    pub targets: Array2<f64>,
+}
+
+impl Subset {
+    fn select(inputs: &Array2<f64>, targets: &Array2<f64>, indices: &[usize]) -> Self {
+        Self {
+            inputs: inputs.select(Axis(0), indices),
+            targets: targets.select(Axis(0), indices),
+        }
+    }
+}
+
+pub struct Split {
+    pub training: Subset,
+    pub validation: Subset,
+    pub test: Subset,
+    pub warnings: Vec<Warning>,
+}
+
+// Rows of every part of a split
+struct SplitIndices {
+    training: Vec<usize>,
+    validation: Vec<usize>,
+    test: Vec<usize>,
+    warnings: Vec<Warning>,
+}
+
+// Fractions of the rows given to validation and test, the remaining rows are for training
+pub fn train_validation_test_split(inputs: &Array2<f64>, targets: &Array2<f64>, validation: f64, test: f64, seed: u64) -> Split {
+    select_split(inputs, targets, split_indices(&[(0..inputs.rows()).collect()], validation, test, seed))
+}
+
+pub fn stratified_split(inputs: &Array2<f64>, targets: &Array2<f64>, validation: f64, test: f64, seed: u64) -> Split {
+    select_split(inputs, targets, split_indices(&class_indices(targets), validation, test, seed))
+}
+
+fn select_split(inputs: &Array2<f64>, targets: &Array2<f64>, indices: SplitIndices) -> Split {
+    assert_eq!(inputs.rows(), targets.rows(), "Inputs should have same amount of rows as targets");
+    Split {
+        training: Subset::select(inputs, targets, &indices.training),
+        validation: Subset::select(inputs, targets, &indices.validation),
+        test: Subset::select(inputs, targets, &indices.test),
+        warnings: indices.warnings,
+    }
+}
+
+// Every group of rows (a class, or all rows) is split with the same fractions,
+// keeping at least one row of every group for training
+fn split_indices(groups: &[Vec<usize>], validation: f64, test: f64, seed: u64) -> SplitIndices {
+    assert!(validation >= 0.0 && test >= 0.0 && validation + test < 1.0, "Validation and test fractions must leave rows for training");
+
+    let mut rng = seeded_rng(seed);
+    let (mut training_indices, mut validation_indices, mut test_indices) = (Vec::new(), Vec::new(), Vec::new());
+    let mut warnings = Vec::new();
+
+    for (class, group) in groups.iter().enumerate().filter(|&(_, group)| !group.is_empty()) {
+        let mut group = group.clone();
+        rng.shuffle(&mut group);
+
+        let mut test_rows = (group.len() as f64 * test).round() as usize;
+        let mut validation_rows = (group.len() as f64 * validation).round() as usize;
+        if test_rows + validation_rows >= group.len() {
+            warnings.push(Warning::SmallClass { class, rows: group.len() });
+            test_rows = test_rows.min(group.len() - 1);
+            validation_rows = validation_rows.min(group.len() - 1 - test_rows);
+        }
+        test_indices.extend_from_slice(&group[..test_rows]);
+        validation_indices.extend_from_slice(&group[test_rows..test_rows + validation_rows]);
+        training_indices.extend_from_slice(&group[test_rows + validation_rows..]);
+    }
+
+    // Do not leave the rows sorted by class
+    rng.shuffle(&mut training_indices);
+    rng.shuffle(&mut validation_indices);
+    rng.shuffle(&mut test_indices);
+    SplitIndices {
+        training: training_indices,
+        validation: validation_indices,
+        test: test_indices,
+        warnings,
+    }
+}
+
+fn class_indices(targets: &Array2<f64>) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (row, class) in to_classes(targets).into_iter().enumerate() {
+        if groups.len() <= class {
+            groups.resize(class + 1, Vec::new());
+        }
+        groups[class].push(row);
+    }
+    groups
+}
+
+
+pub struct KFold {
+    pub folds: usize,
+    pub stratified: bool,
+    pub seed: u64,
+}
+
+impl KFold {
+    pub fn new(folds: usize) -> Self {
+        assert!(folds >= 2, "At least two folds are needed");
+        Self {
+            folds,
+            stratified: false,
+            seed: 0,
+        }
+    }
+
+    pub fn stratified(mut self) -> Self {
+        self.stratified = true;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Rows held out by every fold, each row is held out by exactly one fold
+    pub fn indices(&self, targets: &Array2<f64>) -> Vec<Vec<usize>> {
+        assert!(targets.rows() >= self.folds, "Less rows than folds");
+        let groups = if self.stratified { class_indices(targets) } else { vec![(0..targets.rows()).collect()] };
+
+        let mut rng = seeded_rng(self.seed);
+        let mut folds = vec![Vec::new(); self.folds];
+        let mut next = 0;
+        for group in groups {
+            let mut group = group;
+            rng.shuffle(&mut group);
+            // Dealt like cards, so every fold gets its share of every class
+            for row in group {
+                folds[next].push(row);
+                next = (next + 1) % self.folds;
+            }
+        }
+        folds
+    }
+
+    // Trains a fresh network from build() on all folds but one, then evaluates it on the held out fold
+    pub fn cross_validate<F>(&self, inputs: &Array2<f64>, targets: &Array2<f64>, config: &TrainingConfig, metrics: &[Metric], mut build: F) -> io::Result<CrossValidation>
+        where F: FnMut() -> NeuralNetwork
+    {
+        assert_eq!(inputs.rows(), targets.rows(), "Inputs should have same amount of rows as targets");
+
+        let folds = self.indices(targets);
+        let mut results = Vec::with_capacity(self.folds);
+
+        for (i, held_out) in folds.iter().enumerate() {
+            let training_indices: Vec<usize> = folds.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .flat_map(|(_, fold)| fold.iter().cloned())
+                .collect();
+            let training = Subset::select(inputs, targets, &training_indices);
+            let validation = Subset::select(inputs, targets, held_out);
+
+            let mut network = build();
+            Trainer::new(config.clone()).fit(&mut network, &training.inputs, &training.targets)?;
+
+            let evaluation = network.evaluate(&validation.inputs, &validation.targets, &config.objective, metrics)?;
+            results.push(Fold {
+                training: training_indices,
+                held_out: held_out.clone(),
+                evaluation,
+            });
+        }
+
+        Ok(CrossValidation { folds: results })
+    }
+}
+
+
+pub struct Fold {
+    // Rows the network of the fold was trained on, then evaluated on
+    pub training: Vec<usize>,
+    pub held_out: Vec<usize>,
+    pub evaluation: Evaluation,
+}
+
+pub struct CrossValidation {
+    pub folds: Vec<Fold>,
+}
+
+impl CrossValidation {
+    // Mean and standard deviation over the folds
+    pub fn loss(&self) -> (f64, f64) {
+        mean_and_deviation(self.folds.iter().map(|fold| fold.evaluation.loss))
+    }
+
+    pub fn metric(&self, name: &str) -> Option<(f64, f64)> {
+        let mut values = Vec::with_capacity(self.folds.len());
+        for fold in &self.folds {
+            values.push(fold.evaluation.metric(name)?);
+        }
+        Some(mean_and_deviation(values.into_iter()))
+    }
+}
+
+impl fmt::Display for CrossValidation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (mean, deviation) = self.loss();
+        write!(f, "loss: {} ± {}", mean, deviation)?;
+        if let Some(fold) = self.folds.first() {
+            for &(metric, _) in &fold.evaluation.metrics {
+                if let Some((mean, deviation)) = self.metric(&metric.name()) {
+                    write!(f, "; {}: {} ± {}", metric.name(), mean, deviation)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mean_and_deviation<I: Iterator<Item = f64>>(values: I) -> (f64, f64) {
+    let values: Vec<f64> = values.collect();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    (mean, variance.sqrt())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use builder::NeuralNetworkBuilder;
+    use activation::Activation;
+    use objective::Objective;
+    use super::*;
+
+    // Ten rows of class 0, five of class 1
+    fn data() -> (Array2<f64>, Array2<f64>) {
+        let inputs = Array2::from_shape_fn((15, 2), |(i, j)| (i * 2 + j) as f64 / 30.0);
+        let targets = Array2::from_shape_fn((15, 2), |(i, j)| if (i < 10) == (j == 0) { 1.0 } else { 0.0 });
+        (inputs, targets)
+    }
+
+    #[test]
+    fn splits_keep_every_row_once() {
+        let (inputs, targets) = data();
+
+        let split = train_validation_test_split(&inputs, &targets, 0.2, 0.2, 1);
+        assert_eq!((split.training.inputs.rows(), split.validation.inputs.rows(), split.test.inputs.rows()), (9, 3, 3));
+
+        let split = stratified_split(&inputs, &targets, 0.2, 0.2, 1);
+        assert!(split.warnings.is_empty());
+        for subset in &[&split.training, &split.validation, &split.test] {
+            // Two rows of class 0 for every row of class 1
+            let class_1 = subset.targets.column(1).scalar_sum();
+            assert_eq!(subset.targets.rows() as f64, class_1 * 3.0);
+        }
+
+        let mut rows: Vec<f64> = [&split.training, &split.validation, &split.test].iter()
+            .flat_map(|subset| subset.inputs.column(0).to_vec())
+            .collect();
+        rows.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(rows, inputs.column(0).to_vec());
+        assert_eq!(split.training.inputs.column(0).to_vec(), stratified_split(&inputs, &targets, 0.2, 0.2, 1).training.inputs.column(0).to_vec());
+
+        // The only row of class 1 would be a test row
+        let targets = Array2::from_shape_fn((15, 2), |(i, j)| if (i < 14) == (j == 0) { 1.0 } else { 0.0 });
+        let split = stratified_split(&inputs, &targets, 0.0, 0.5, 1);
+        assert_eq!(split.training.targets.column(1).scalar_sum(), 1.0);
+        assert_eq!((split.training.inputs.rows(), split.test.inputs.rows()), (8, 7));
+        assert_eq!(split.warnings.len(), 1);
+        assert_eq!(split.warnings[0].to_string(), "class 1 only has 1 rows, one of them is kept for training");
+    }
+
+    #[test]
+    fn stratified_k_fold() {
+        let (inputs, targets) = data();
+        let k_fold = KFold::new(5).stratified().seed(7);
+
+        let folds = k_fold.indices(&targets);
+        let mut all: Vec<usize> = folds.iter().flat_map(|fold| fold.iter().cloned()).collect();
+        all.sort();
+        assert_eq!(all, (0..15).collect::<Vec<_>>());
+        for fold in &folds {
+            assert_eq!(fold.iter().filter(|row| **row >= 10).count(), 1);
+        }
+
+        let build = || NeuralNetworkBuilder::new(2).layer(2, Activation::Softmax).build();
+        let config = TrainingConfig::new(Objective::CrossEntropy, 4, 2, 0.1);
+        let result = k_fold.cross_validate(&inputs, &targets, &config, &[Metric::Accuracy], build).unwrap();
+        assert_eq!(result.folds.len(), 5);
+
+        let mut held_out: Vec<usize> = result.folds.iter().flat_map(|fold| fold.held_out.iter().cloned()).collect();
+        held_out.sort();
+        assert_eq!(held_out, (0..15).collect::<Vec<_>>());
+        for fold in &result.folds {
+            // Trained on every other row, never on a held out one
+            assert_eq!(fold.training.len() + fold.held_out.len(), 15);
+            assert!(fold.training.iter().all(|row| !fold.held_out.contains(row)));
+            // Two rows of class 0 for every row of class 1 on both sides
+            assert_eq!(fold.held_out.iter().filter(|row| **row >= 10).count(), 1);
+            assert_eq!(fold.training.iter().filter(|row| **row >= 10).count(), 4);
+            assert!(fold.evaluation.metric("accuracy").is_some());
+        }
+
+        let (mean, deviation) = result.loss();
+        let losses: Vec<f64> = result.folds.iter().map(|fold| fold.evaluation.loss).collect();
+        assert!((mean - losses.iter().sum::<f64>() / 5.0).abs() < 1e-12 && deviation > 0.0);
+        assert!(result.metric("f1_macro").is_none());
+    }
+}
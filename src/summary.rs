@@ -0,0 +1,149 @@
+// Layer by layer description of a network, to check an architecture before training it
+//
+// println!("{}", network.summary()) prints a table similar to the summary of Keras.
+
+use std::fmt;
+use std::mem;
+
+use network::NeuralNetwork;
+use layer::Layer;
+
+
+pub struct LayerSummary {
+    pub kind: &'static str,
+    pub inputs: usize,
+    pub outputs: usize,
+    // Only dense layers have a single activation
+    pub activation: Option<String>,
+    // Weights and bias
+    pub parameters: usize,
+}
+
+pub struct Summary {
+    pub layers: Vec<LayerSummary>,
+}
+
+impl Summary {
+    // Every parameter is updated by backpropagation
+    pub fn trainable_parameters(&self) -> usize {
+        self.layers.iter().map(|layer| layer.parameters).sum()
+    }
+
+    // Bytes taken by the parameters, without the activities and the state of the optimizer
+    pub fn memory(&self) -> usize {
+        self.trainable_parameters() * mem::size_of::<f64>()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<7}{:<22}{:<16}{:<16}{:<12}{:>12}", "Layer", "Type", "Input", "Output", "Activation", "Parameters")?;
+        writeln!(f, "{}", "=".repeat(85))?;
+        for (i, layer) in self.layers.iter().enumerate() {
+            writeln!(f, "{:<7}{:<22}{:<16}{:<16}{:<12}{:>12}",
+                     i,
+                     layer.kind,
+                     format!("(batch, {})", layer.inputs),
+                     format!("(batch, {})", layer.outputs),
+                     layer.activation.as_ref().map_or("-", |activation| activation.as_str()),
+                     group_digits(layer.parameters))?;
+        }
+        writeln!(f, "{}", "=".repeat(85))?;
+        writeln!(f, "Trainable parameters: {}", group_digits(self.trainable_parameters()))?;
+        write!(f, "Memory: {}", format_bytes(self.memory()))
+    }
+}
+
+impl NeuralNetwork {
+    pub fn summary(&self) -> Summary {
+        let layers = self.layers().iter().map(|layer| LayerSummary {
+            kind: layer.name(),
+            inputs: layer.inputs(),
+            outputs: layer.outputs(),
+            activation: match *layer {
+                Layer::Dense(ref layer) => Some(layer.activation_function.name()),
+                _ => None,
+            },
+            parameters: layer.parameters().iter().map(|parameter| parameter.len()).sum(),
+        }).collect();
+
+        Summary { layers }
+    }
+}
+
+// 407050 -> 407,050
+fn group_digits(value: usize) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() * 4 / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+fn format_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < units.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", size, units[unit])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use builder::NeuralNetworkBuilder;
+    use activation::Activation;
+    use attention::AttentionMask;
+    use super::*;
+
+    #[test]
+    fn mnist_summary() {
+        let network = NeuralNetworkBuilder::new(28 * 28)
+            .layer(512, Activation::ReLU)
+            .layer(10, Activation::Softmax)
+            .build();
+        let summary = network.summary();
+
+        let parameters: Vec<usize> = summary.layers.iter().map(|layer| layer.parameters).collect();
+        assert_eq!(parameters, vec![784 * 512 + 512, 512 * 10 + 10]);
+        assert_eq!(summary.trainable_parameters(), 407_050);
+        assert_eq!(summary.memory(), 3_256_400);
+
+        let text = summary.to_string();
+        assert!(text.contains("(batch, 784)"));
+        assert!(text.contains("softmax"));
+        assert!(text.contains("Trainable parameters: 407,050"));
+        assert!(text.ends_with("Memory: 3.11 MiB"));
+
+        // Layers without a single activation
+        let network = NeuralNetworkBuilder::new(8)
+            .attention(2, 2, AttentionMask::default())
+            .build();
+        let summary = network.summary();
+        assert_eq!(summary.layers[0].kind, "multi_head_attention");
+        assert_eq!((summary.layers[0].inputs, summary.layers[0].outputs), (8, 8));
+        assert!(summary.layers[0].activation.is_none());
+        assert_eq!(summary.trainable_parameters(), network.layers()[0].parameters().iter().map(|parameter| parameter.len()).sum::<usize>());
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(1000), "1,000");
+        assert_eq!(group_digits(1_234_567), "1,234,567");
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(2048), "2.00 KiB");
+    }
+}
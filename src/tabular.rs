@@ -0,0 +1,454 @@
+// CSV datasets, mapped to input and target matrices
+//
+// Columns are referenced by their header, or by their index ("0", "1", ...) when the file has no header.
+// Every numeric or categorical column is fitted on the loaded file, the fitted TabularEncoder
+// then encodes new files (e.g. at inference time) exactly the same way.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use ndarray::Array2;
+
+use preprocessing::{LabelEncoder, OneHotEncoder};
+
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum ColumnType {
+    Numeric,
+    // One column per category
+    OneHot,
+    // Index of the category, for an embedding layer
+    Index,
+}
+
+// Categorical columns are always filled with their most frequent category
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub enum MissingValues {
+    Mean,
+    Median,
+    Zero,
+    // Only when loading, new files are filled with the mean
+    DropRow,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ColumnEncoder {
+    Numeric { fill: f64 },
+    OneHot { encoder: OneHotEncoder, fill: String },
+    Index { encoder: LabelEncoder, fill: String },
+}
+
+impl ColumnEncoder {
+    // Columns of the matrix taken by the encoded column
+    pub fn width(&self) -> usize {
+        match *self {
+            ColumnEncoder::OneHot { ref encoder, .. } => encoder.categories.len(),
+            _ => 1,
+        }
+    }
+
+    fn encode(&self, value: Option<&str>, encoded: &mut Array2<f64>, row: usize, offset: usize) -> io::Result<()> {
+        match *self {
+            ColumnEncoder::Numeric { fill } => {
+                encoded[[row, offset]] = match value {
+                    Some(value) => parse_number(value)?,
+                    None => fill,
+                };
+            },
+            ColumnEncoder::OneHot { ref encoder, ref fill } => {
+                let value = value.unwrap_or(fill);
+                let index = encoder.index(value).ok_or_else(|| invalid_data(format!("Unknown category {:?}", value)))?;
+                encoded[[row, offset + index]] = 1.0;
+            },
+            ColumnEncoder::Index { ref encoder, ref fill } => {
+                let value = value.unwrap_or(fill);
+                let index = encoder.index(value).ok_or_else(|| invalid_data(format!("Unknown category {:?}", value)))?;
+                encoded[[row, offset]] = index as f64;
+            },
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FittedColumn {
+    pub name: String,
+    pub encoder: ColumnEncoder,
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TabularEncoder {
+    pub features: Vec<FittedColumn>,
+    pub targets: Vec<FittedColumn>,
+    delimiter: char,
+    headers: bool,
+    missing_markers: Vec<String>,
+}
+
+impl TabularEncoder {
+    pub fn transform<P: AsRef<Path>>(&self, path: P) -> io::Result<Array2<f64>> {
+        self.transform_from(File::open(path)?)
+    }
+
+    // Inputs of a file with the same format, target columns may be absent
+    pub fn transform_from<R: Read>(&self, reader: R) -> io::Result<Array2<f64>> {
+        let table = Table::read(reader, self.delimiter, self.headers, &self.missing_markers)?;
+        encode(&table, &self.features)
+    }
+
+    // Targets of a file with the same format, e.g. to evaluate the network on it
+    pub fn transform_targets<P: AsRef<Path>>(&self, path: P) -> io::Result<Array2<f64>> {
+        let table = Table::read(File::open(path)?, self.delimiter, self.headers, &self.missing_markers)?;
+        encode(&table, &self.targets)
+    }
+}
+
+pub struct TabularData {
+    pub inputs: Array2<f64>,
+    pub targets: Array2<f64>,
+    pub encoder: TabularEncoder,
+}
+
+
+pub struct CsvLoader {
+    delimiter: char,
+    headers: bool,
+    missing_values: MissingValues,
+    missing_markers: Vec<String>,
+    features: Vec<(String, ColumnType)>,
+    targets: Vec<(String, ColumnType)>,
+}
+
+impl Default for CsvLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvLoader {
+    pub fn new() -> Self {
+        Self {
+            delimiter: ',',
+            headers: true,
+            missing_values: MissingValues::Mean,
+            missing_markers: ["", "NA", "N/A", "NaN", "null", "?"].iter().map(|marker| marker.to_string()).collect(),
+            features: Vec::new(),
+            targets: Vec::new(),
+        }
+    }
+
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn headers(mut self, headers: bool) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn missing_values(mut self, missing_values: MissingValues) -> Self {
+        self.missing_values = missing_values;
+        self
+    }
+
+    // Fields equal to one of the markers are missing values
+    pub fn missing_markers(mut self, markers: &[&str]) -> Self {
+        self.missing_markers = markers.iter().map(|marker| marker.to_string()).collect();
+        self
+    }
+
+    pub fn feature(mut self, column: &str, column_type: ColumnType) -> Self {
+        self.features.push((column.to_owned(), column_type));
+        self
+    }
+
+    pub fn target(mut self, column: &str, column_type: ColumnType) -> Self {
+        self.targets.push((column.to_owned(), column_type));
+        self
+    }
+
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> io::Result<TabularData> {
+        self.load_from(File::open(path)?)
+    }
+
+    pub fn load_from<R: Read>(&self, reader: R) -> io::Result<TabularData> {
+        if self.features.is_empty() {
+            return Err(invalid_input("No feature columns defined".to_owned()));
+        }
+
+        let mut table = Table::read(reader, self.delimiter, self.headers, &self.missing_markers)?;
+
+        if let MissingValues::DropRow = self.missing_values {
+            let mut used = Vec::new();
+            for (name, _) in self.features.iter().chain(&self.targets) {
+                used.push(table.column(name)?);
+            }
+            table.rows.retain(|row| used.iter().all(|column| row[*column].is_some()));
+        }
+        if table.rows.is_empty() {
+            return Err(invalid_data("No rows to load".to_owned()));
+        }
+
+        let encoder = TabularEncoder {
+            features: self.fit(&table, &self.features)?,
+            targets: self.fit(&table, &self.targets)?,
+            delimiter: self.delimiter,
+            headers: self.headers,
+            missing_markers: self.missing_markers.clone(),
+        };
+
+        Ok(TabularData {
+            inputs: encode(&table, &encoder.features)?,
+            targets: encode(&table, &encoder.targets)?,
+            encoder,
+        })
+    }
+
+    fn fit(&self, table: &Table, columns: &[(String, ColumnType)]) -> io::Result<Vec<FittedColumn>> {
+        let mut fitted = Vec::with_capacity(columns.len());
+        for &(ref name, column_type) in columns {
+            let column = table.column(name)?;
+            let values: Vec<&str> = table.rows.iter().filter_map(|row| row[column].as_deref()).collect();
+
+            let encoder = match column_type {
+                ColumnType::Numeric => {
+                    let mut numbers = Vec::with_capacity(values.len());
+                    for value in &values {
+                        numbers.push(parse_number(value)?);
+                    }
+                    ColumnEncoder::Numeric { fill: self.fill(numbers) }
+                },
+                ColumnType::OneHot => ColumnEncoder::OneHot {
+                    encoder: OneHotEncoder::fit(&values),
+                    fill: most_frequent(name, &values)?,
+                },
+                ColumnType::Index => ColumnEncoder::Index {
+                    encoder: LabelEncoder::fit(&values),
+                    fill: most_frequent(name, &values)?,
+                },
+            };
+            fitted.push(FittedColumn { name: name.clone(), encoder });
+        }
+        Ok(fitted)
+    }
+
+    fn fill(&self, mut numbers: Vec<f64>) -> f64 {
+        if numbers.is_empty() {
+            return 0.0;
+        }
+        match self.missing_values {
+            MissingValues::Mean | MissingValues::DropRow => numbers.iter().sum::<f64>() / numbers.len() as f64,
+            MissingValues::Median => {
+                numbers.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let middle = numbers.len() / 2;
+                if numbers.len().is_multiple_of(2) { (numbers[middle - 1] + numbers[middle]) / 2.0 } else { numbers[middle] }
+            },
+            MissingValues::Zero => 0.0,
+        }
+    }
+}
+
+
+struct Table {
+    names: Vec<String>,
+    // None for missing values
+    rows: Vec<Vec<Option<String>>>,
+}
+
+impl Table {
+    fn read<R: Read>(reader: R, delimiter: char, headers: bool, missing_markers: &[String]) -> io::Result<Self> {
+        let mut names = None;
+        let mut rows = Vec::new();
+
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = parse_record(&line, delimiter).map_err(|e| invalid_data(format!("Line {}: {}", number + 1, e)))?;
+
+            if names.is_none() {
+                names = Some(if headers { fields.clone() } else { (0..fields.len()).map(|i| i.to_string()).collect() });
+                if headers {
+                    continue;
+                }
+            }
+            let width = names.as_ref().map_or(0, |names: &Vec<String>| names.len());
+            if fields.len() != width {
+                return Err(invalid_data(format!("Line {}: expected {} fields, found {}", number + 1, width, fields.len())));
+            }
+            rows.push(fields.into_iter().map(|field| if missing_markers.contains(&field) { None } else { Some(field) }).collect());
+        }
+
+        Ok(Self {
+            names: names.unwrap_or_default(),
+            rows,
+        })
+    }
+
+    fn column(&self, name: &str) -> io::Result<usize> {
+        self.names.iter().position(|column| column == name).ok_or_else(|| invalid_input(format!("Unknown column {:?}", name)))
+    }
+}
+
+fn encode(table: &Table, columns: &[FittedColumn]) -> io::Result<Array2<f64>> {
+    let width = columns.iter().map(|column| column.encoder.width()).sum();
+    let mut encoded = Array2::<f64>::zeros((table.rows.len(), width));
+
+    let mut offset = 0;
+    for column in columns {
+        let index = table.column(&column.name)?;
+        for (row, fields) in table.rows.iter().enumerate() {
+            column.encoder.encode(fields[index].as_deref(), &mut encoded, row, offset)
+                .map_err(|e| invalid_data(format!("Column {:?}: {}", column.name, e)))?;
+        }
+        offset += column.encoder.width();
+    }
+    Ok(encoded)
+}
+
+// Fields are trimmed, quoted fields may contain the delimiter and "" for a quote
+fn parse_record(line: &str, delimiter: char) -> io::Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut characters = line.trim_end_matches('\r').chars().peekable();
+
+    while let Some(character) = characters.next() {
+        if quoted {
+            if character != '"' {
+                field.push(character);
+            } else if characters.peek() == Some(&'"') {
+                field.push('"');
+                characters.next();
+            } else {
+                quoted = false;
+            }
+        } else if character == '"' {
+            quoted = true;
+        } else if character == delimiter {
+            fields.push(field.trim().to_owned());
+            field.clear();
+        } else {
+            field.push(character);
+        }
+    }
+    if quoted {
+        return Err(invalid_data("Unterminated quoted field".to_owned()));
+    }
+    fields.push(field.trim().to_owned());
+    Ok(fields)
+}
+
+// "nan" and "inf" are parsed by Rust, but would reach the network : add them to the missing markers instead
+fn parse_number(value: &str) -> io::Result<f64> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        Ok(_) => Err(invalid_data(format!("{:?} is not a finite number", value))),
+        Err(_) => Err(invalid_data(format!("{:?} is not a number", value))),
+    }
+}
+
+// Ties are broken by the first category in sorted order
+fn most_frequent(name: &str, values: &[&str]) -> io::Result<String> {
+    let mut sorted = values.to_vec();
+    sorted.sort();
+
+    let mut best: Option<(&str, usize)> = None;
+    let mut start = 0;
+    for end in 1..=sorted.len() {
+        if end == sorted.len() || sorted[end] != sorted[start] {
+            if best.is_none_or(|(_, count)| end - start > count) {
+                best = Some((sorted[start], end - start));
+            }
+            start = end;
+        }
+    }
+    best.map(|(value, _)| value.to_owned()).ok_or_else(|| invalid_data(format!("Column {:?} only has missing values", name)))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ndarray::arr2;
+    use super::*;
+
+    const HOUSES: &str = "\
+size, rooms, city, price
+120, 4, Paris, expensive
+80, NA, \"Lyon, France\", cheap
+, 2, Paris, cheap
+60, 3, ?, cheap
+";
+
+    #[test]
+    fn loads_headers_missing_values_and_categories() {
+        let data = CsvLoader::new()
+            .feature("size", ColumnType::Numeric)
+            .feature("rooms", ColumnType::Numeric)
+            .feature("city", ColumnType::OneHot)
+            .target("price", ColumnType::Index)
+            .load_from(HOUSES.as_bytes())
+            .unwrap();
+
+        // Missing size is the mean of 120, 80 and 60, missing rooms the mean of 4, 2 and 3, missing city is Paris
+        assert_eq!(data.inputs, arr2(&[
+            [120.0, 4.0, 0.0, 1.0],
+            [80.0, 3.0, 1.0, 0.0],
+            [260.0 / 3.0, 2.0, 0.0, 1.0],
+            [60.0, 3.0, 0.0, 1.0],
+        ]));
+        assert_eq!(data.targets, arr2(&[[1.0], [0.0], [0.0], [0.0]]));
+
+        // Reused at inference time, without the target column
+        let new_houses = "size,rooms,city\n100,,\"Lyon, France\"\n";
+        assert_eq!(data.encoder.transform_from(new_houses.as_bytes()).unwrap(), arr2(&[[100.0, 3.0, 1.0, 0.0]]));
+        assert!(data.encoder.transform_from("size,rooms,city\n1,1,Nice\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn drops_rows_without_headers() {
+        let data = CsvLoader::new()
+            .headers(false)
+            .delimiter(';')
+            .missing_values(MissingValues::DropRow)
+            .feature("0", ColumnType::Numeric)
+            .feature("1", ColumnType::Index)
+            .target("2", ColumnType::OneHot)
+            .load_from("1.5;b;yes\n;a;no\n2;a;no\n".as_bytes())
+            .unwrap();
+
+        assert_eq!(data.inputs, arr2(&[[1.5, 1.0], [2.0, 0.0]]));
+        assert_eq!(data.targets, arr2(&[[0.0, 1.0], [1.0, 0.0]]));
+
+        assert!(CsvLoader::new().feature("weight", ColumnType::Numeric).load_from(HOUSES.as_bytes()).is_err());
+        assert!(CsvLoader::new().feature("city", ColumnType::Numeric).load_from(HOUSES.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn non_finite_numbers() {
+        let csv = "x,y\n1,2\ninf,3\nnan,4\n";
+        let error = CsvLoader::default().feature("x", ColumnType::Numeric).target("y", ColumnType::Numeric).load_from(csv.as_bytes()).err().unwrap();
+        assert!(error.to_string().contains("\"inf\" is not a finite number"));
+
+        // Unless they are missing values
+        let data = CsvLoader::new()
+            .missing_markers(&["inf", "nan"])
+            .feature("x", ColumnType::Numeric)
+            .target("y", ColumnType::Numeric)
+            .load_from(csv.as_bytes())
+            .unwrap();
+        assert_eq!(data.inputs, arr2(&[[1.0], [1.0], [1.0]]));
+    }
+}
@@ -0,0 +1,526 @@
+// Training loop with optimizer, learning rate schedule and checkpoints
+//
+// A checkpoint stores everything needed to continue an interrupted run exactly where it stopped :
+// network parameters, optimizer moments, epoch and batch counters, row order of the epoch and random generator.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use ndarray::Array2;
+use rand::prng::XorShiftRng;
+use bincode;
+
+use network::NeuralNetwork;
+use objective::Objective;
+use optimizer::{Optimizer, OptimizerState};
+use schedule::LearningRateSchedule;
+use gradients::GradientClipping;
+use serialization::{self, SavedModel};
+use metrics::{Metric, RunningMetrics};
+use data::{Dataset, ArrayDataset, batch_count, epoch_order, read_batch, seeded_rng};
+use augmentation::Augmenter;
+use error::Error;
+
+
+const MAGIC: &[u8; 4] = b"NNCK";
+// Increase when Checkpoint or the SavedModel it contains changes
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+
+// Defaults only apply to missing fields of configuration files, see config::ModelConfig
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrainingConfig {
+    pub objective: Objective,
+    #[serde(default)]
+    pub optimizer: Optimizer,
+    pub schedule: LearningRateSchedule,
+    pub batch_size: usize,
+    pub epochs: usize,
+    #[serde(default = "default_shuffle")]
+    pub shuffle: bool,
+    // Skip the last batch of an epoch when it is smaller than batch_size
+    #[serde(default)]
+    pub drop_last: bool,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub gradient_clipping: Option<GradientClipping>,
+    // Computed at the end of every epoch on the outputs of its batches
+    #[serde(default)]
+    pub metrics: Vec<Metric>,
+}
+
+impl TrainingConfig {
+    pub fn new(objective: Objective, batch_size: usize, epochs: usize, learning_rate: f64) -> Self {
+        Self {
+            objective,
+            optimizer: Optimizer::StochasticGradientDescent,
+            schedule: LearningRateSchedule::Constant(learning_rate),
+            batch_size,
+            epochs,
+            shuffle: true,
+            drop_last: false,
+            seed: 0,
+            gradient_clipping: None,
+            metrics: Vec::new(),
+        }
+    }
+}
+
+fn default_shuffle() -> bool {
+    true
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CheckpointPolicy {
+    pub directory: PathBuf,
+    // Also save every given amount of batches, a checkpoint is always saved at the end of an epoch
+    pub every_batches: Option<usize>,
+    // Amount of most recent checkpoints kept on disk
+    pub keep_last: usize,
+    // Keep the checkpoint with the lowest error even when it is not one of the most recent ones
+    pub keep_best: bool,
+}
+
+impl CheckpointPolicy {
+    pub fn new<P: AsRef<Path>>(directory: P, keep_last: usize) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            every_batches: None,
+            keep_last,
+            keep_best: false,
+        }
+    }
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedCheckpoint {
+    pub path: PathBuf,
+    // Mean error of the batches done in the epoch when the checkpoint was saved
+    pub error: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrainingState {
+    pub epoch: usize,
+    // Next batch of the epoch
+    pub batch: usize,
+    pub optimizer: OptimizerState,
+    // Mean error of every finished epoch
+    pub history: Vec<f64>,
+    // Value of every TrainingConfig::metrics for every finished epoch
+    pub metric_history: Vec<Vec<f64>>,
+    pub checkpoints: Vec<SavedCheckpoint>,
+    pub best_checkpoint: Option<SavedCheckpoint>,
+    rng: XorShiftRng,
+    order: Vec<usize>,
+    epoch_error: f64,
+    epoch_rows: usize,
+    epoch_metrics: RunningMetrics,
+    // Its random generator goes on from one epoch to the next, like the one of the row order
+    augmenter: Option<Augmenter>,
+}
+
+impl TrainingState {
+    fn new(seed: u64, metrics: &[Metric]) -> Self {
+        Self {
+            epoch: 0,
+            batch: 0,
+            optimizer: OptimizerState::default(),
+            history: Vec::new(),
+            metric_history: Vec::new(),
+            checkpoints: Vec::new(),
+            best_checkpoint: None,
+            rng: seeded_rng(seed),
+            order: Vec::new(),
+            epoch_error: 0.0,
+            epoch_rows: 0,
+            epoch_metrics: RunningMetrics::new(metrics),
+            augmenter: None,
+        }
+    }
+
+    fn mean_error(&self) -> f64 {
+        if self.epoch_rows == 0 { 0.0 } else { self.epoch_error / self.epoch_rows as f64 }
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    format_version: u32,
+    model: SavedModel,
+    config: TrainingConfig,
+    policy: Option<CheckpointPolicy>,
+    state: TrainingState,
+}
+
+
+pub struct Trainer {
+    pub config: TrainingConfig,
+    pub policy: Option<CheckpointPolicy>,
+    state: TrainingState,
+}
+
+impl Trainer {
+    pub fn new(config: TrainingConfig) -> Self {
+        let state = TrainingState::new(config.seed, &config.metrics);
+        Self {
+            config,
+            policy: None,
+            state,
+        }
+    }
+
+    pub fn checkpoints(mut self, policy: CheckpointPolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    // Applied to the inputs of every batch, see DataLoader::augment()
+    pub fn augment(mut self, augmenter: Augmenter) -> Self {
+        self.state.augmenter = Some(augmenter);
+        self
+    }
+
+    pub fn state(&self) -> &TrainingState {
+        &self.state
+    }
+
+    // Train until config.epochs, starting from the current state
+    pub fn fit(&mut self, network: &mut NeuralNetwork, training_set: &Array2<f64>, expected_result: &Array2<f64>) -> io::Result<()> {
+        self.fit_dataset(network, &ArrayDataset::new(training_set, expected_result))
+    }
+
+    // Same as fit(), batches are read from the dataset when they are needed
+    pub fn fit_dataset<D: Dataset>(&mut self, network: &mut NeuralNetwork, dataset: &D) -> io::Result<()> {
+        if self.config.batch_size == 0 {
+            return Err(Error::ZeroBatchSize.into());
+        }
+
+        // A first row is enough to catch a dataset that does not fit the network before any epoch
+        if self.state.epoch == 0 && self.state.batch == 0 && dataset.len() > 0 {
+            let (data, expected_result) = dataset.get(&[0])?;
+            for warning in network.check_training(&data, &expected_result, &self.config.objective)? {
+                println!("Warning: {}", warning);
+            }
+        }
+
+        if let Some(ref policy) = self.policy {
+            fs::create_dir_all(&policy.directory)?;
+        }
+
+        let rows = dataset.len();
+        let batch_size = self.config.batch_size;
+        let batches = batch_count(rows, batch_size, self.config.drop_last);
+
+        while self.state.epoch < self.config.epochs {
+            if self.state.batch == 0 {
+                self.state.order = epoch_order(rows, batch_size, self.config.shuffle, self.config.drop_last, &mut self.state.rng);
+                self.state.epoch_error = 0.0;
+                self.state.epoch_rows = 0;
+                self.state.epoch_metrics = RunningMetrics::new(&self.config.metrics);
+            }
+
+            while self.state.batch < batches {
+                let (data, expected_result_slice) = read_batch(dataset, &self.state.order, self.state.batch, batch_size, self.state.augmenter.as_mut())?;
+
+                let (error, mut gradients) = network.compute_error_and_gradients(&data, &expected_result_slice, &self.config.objective);
+                if !self.config.metrics.is_empty() {
+                    self.state.epoch_metrics.update(network.output(), &expected_result_slice);
+                }
+                if let Some(clipping) = self.config.gradient_clipping {
+                    gradients.clip(clipping);
+                }
+                let learning_rate = self.config.schedule.learning_rate(self.state.optimizer.steps);
+                self.config.optimizer.step(&mut self.state.optimizer, network, &gradients, learning_rate);
+
+                self.state.epoch_error += error * data.rows() as f64;
+                self.state.epoch_rows += data.rows();
+                self.state.batch += 1;
+
+                let every_batches = self.policy.as_ref().and_then(|policy| policy.every_batches);
+                if let Some(every_batches) = every_batches {
+                    if self.state.batch.is_multiple_of(every_batches) && self.state.batch < batches {
+                        self.checkpoint(network)?;
+                    }
+                }
+            }
+
+            let error = self.state.mean_error();
+            let mut message = format!("Epoch {}; error: {}", self.state.epoch, error);
+            if !self.config.metrics.is_empty() {
+                // NAN for an empty dataset
+                let values = self.state.epoch_metrics.values();
+                for (metric, value) in self.config.metrics.iter().zip(&values) {
+                    message.push_str(&format!("; {}: {}", metric.name(), value));
+                }
+                self.state.metric_history.push(values);
+            }
+            println!("{}", message);
+            self.state.history.push(error);
+            self.state.epoch += 1;
+            self.state.batch = 0;
+
+            if self.policy.is_some() {
+                self.checkpoint(network)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Save a checkpoint in the policy directory, then remove the ones that are not retained anymore
+    pub fn checkpoint(&mut self, network: &NeuralNetwork) -> io::Result<PathBuf> {
+        let (path, keep_last, keep_best) = match self.policy {
+            Some(ref policy) => {
+                let name = format!("checkpoint-{:04}-{:06}.bin", self.state.epoch, self.state.batch);
+                (policy.directory.join(name), policy.keep_last, policy.keep_best)
+            },
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "No checkpoint policy defined")),
+        };
+
+        // The error of a checkpoint saved at the end of an epoch is the error of that epoch
+        let error = match (self.state.batch, self.state.history.last()) {
+            (0, Some(error)) => *error,
+            _ => self.state.mean_error(),
+        };
+        let saved = SavedCheckpoint { path: path.clone(), error };
+
+        let previous_checkpoints = self.state.checkpoints.clone();
+        let previous_best = self.state.best_checkpoint.clone();
+        if keep_best && previous_best.as_ref().is_none_or(|best| error < best.error) {
+            self.state.best_checkpoint = Some(saved.clone());
+        }
+        self.state.checkpoints.push(saved);
+
+        // The saved state only references the retained files, the other ones are removed once it is written
+        let best_path = self.state.best_checkpoint.as_ref().map(|best| best.path.clone());
+        let first_kept = self.state.checkpoints.len().saturating_sub(keep_last.max(1));
+        let mut removed: Vec<PathBuf> = self.state.checkpoints.drain(..first_kept).map(|checkpoint| checkpoint.path).collect();
+        if let Some(ref previous_best) = previous_best {
+            removed.push(previous_best.path.clone());
+        }
+
+        if let Err(e) = self.save_checkpoint(network, &path) {
+            self.state.checkpoints = previous_checkpoints;
+            self.state.best_checkpoint = previous_best;
+            return Err(e);
+        }
+
+        for removed_path in removed {
+            let is_recent = self.state.checkpoints.iter().any(|checkpoint| checkpoint.path == removed_path);
+            if Some(&removed_path) != best_path.as_ref() && !is_recent {
+                remove_checkpoint(&removed_path)?;
+            }
+        }
+        Ok(path)
+    }
+
+    pub fn save_checkpoint<P: AsRef<Path>>(&self, network: &NeuralNetwork, path: P) -> io::Result<()> {
+        let checkpoint = Checkpoint {
+            format_version: CHECKPOINT_VERSION,
+            model: SavedModel::from_network(network),
+            config: self.config.clone(),
+            policy: self.policy.clone(),
+            state: self.state.clone(),
+        };
+
+        // Written next to the file then renamed, an interrupted write never replaces a complete checkpoint
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary)?);
+            writer.write_all(MAGIC)?;
+            bincode::serialize_into(&mut writer, &checkpoint).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            writer.flush()?;
+        }
+        fs::rename(&temporary, path)
+    }
+
+    // Continue with fit() on the same data to finish the interrupted run
+    pub fn resume<P: AsRef<Path>>(path: P) -> io::Result<(Trainer, NeuralNetwork)> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a checkpoint file"));
+        }
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+
+        // Checked before decoding, the other fields of an older checkpoint do not match
+        let version = serialization::read_version(&bytes)?;
+        if version != CHECKPOINT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Checkpoint version {} can not be resumed, only version {} can", version, CHECKPOINT_VERSION)));
+        }
+        let checkpoint: Checkpoint = serialization::decode(&bytes)?;
+
+        let trainer = Trainer {
+            config: checkpoint.config,
+            policy: checkpoint.policy,
+            state: checkpoint.state,
+        };
+        Ok((trainer, checkpoint.model.to_network()?))
+    }
+}
+
+
+fn remove_checkpoint(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use ndarray::arr2;
+    use builder::NeuralNetworkBuilder;
+    use activation::Activation;
+    use augmentation::Augmentation;
+    use super::*;
+
+    fn data() -> (Array2<f64>, Array2<f64>) {
+        let input = arr2(&[[0.2, -0.4], [0.9, 0.1], [0.5, 0.5], [-0.3, 0.8], [0.0, -0.6], [0.7, 0.7], [-0.9, 0.2]]);
+        let expected_result = arr2(&[[0.0, 1.0], [1.0, 0.0], [1.0, 0.0], [0.0, 1.0], [0.0, 1.0], [1.0, 0.0], [0.0, 1.0]]);
+        (input, expected_result)
+    }
+
+    fn config() -> TrainingConfig {
+        let mut config = TrainingConfig::new(Objective::CrossEntropy, 2, 3, 0.0);
+        config.optimizer = Optimizer::adam();
+        config.schedule = LearningRateSchedule::ExponentialDecay { initial: 0.05, decay: 0.9 };
+        config.seed = 42;
+        config.metrics = vec![Metric::Accuracy, Metric::LogLoss];
+        config
+    }
+
+    fn parameters(network: &NeuralNetwork) -> Vec<Array2<f64>> {
+        network.parameters().into_iter().flat_map(|layer| layer.into_iter().cloned()).collect()
+    }
+
+    #[test]
+    fn resume_is_identical_to_uninterrupted_run() {
+        let (input, expected_result) = data();
+        let directory = env::temp_dir().join("neural_network_resume");
+        let _ = fs::remove_dir_all(&directory);
+
+        let mut network = NeuralNetworkBuilder::new(2)
+            .layer(3, Activation::TanH)
+            .layer(2, Activation::Softmax)
+            .build();
+        let mut policy = CheckpointPolicy::new(&directory, 100);
+        policy.every_batches = Some(3);
+        let mut trainer = Trainer::new(config()).checkpoints(policy);
+        trainer.fit(&mut network, &input, &expected_result).unwrap();
+
+        // Resume from a checkpoint saved in the middle of the second epoch
+        let (mut resumed_trainer, mut resumed_network) = Trainer::resume(directory.join("checkpoint-0001-000003.bin")).unwrap();
+        assert_eq!(resumed_trainer.state().epoch, 1);
+        assert_eq!(resumed_trainer.state().batch, 3);
+        resumed_trainer.fit(&mut resumed_network, &input, &expected_result).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(parameters(&network), parameters(&resumed_network));
+        assert_eq!(trainer.state().history, resumed_trainer.state().history);
+        assert_eq!(trainer.state().metric_history, resumed_trainer.state().metric_history);
+        assert_eq!(trainer.state().metric_history.len(), 3);
+    }
+
+    #[test]
+    fn retention_keeps_last_and_best() {
+        let (input, expected_result) = data();
+        let directory = env::temp_dir().join("neural_network_retention");
+        let _ = fs::remove_dir_all(&directory);
+
+        let mut network = NeuralNetworkBuilder::new(2)
+            .layer(2, Activation::Softmax)
+            .build();
+        let mut policy = CheckpointPolicy::new(&directory, 2);
+        policy.keep_best = true;
+        let mut trainer = Trainer::new(config()).checkpoints(policy);
+        trainer.fit(&mut network, &input, &expected_result).unwrap();
+
+        let mut files: Vec<PathBuf> = fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().path()).collect();
+        files.sort();
+        let mut expected_files: Vec<PathBuf> = trainer.state().checkpoints.iter().map(|checkpoint| checkpoint.path.clone()).collect();
+        let best = trainer.state().best_checkpoint.clone().unwrap();
+        if !expected_files.contains(&best.path) {
+            expected_files.push(best.path.clone());
+        }
+        expected_files.sort();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(trainer.state().checkpoints.len(), 2);
+        assert_eq!(files, expected_files);
+        assert!(trainer.state().history.iter().all(|error| *error >= best.error));
+    }
+
+    #[test]
+    fn rejects_other_checkpoint_versions() {
+        let network = NeuralNetworkBuilder::new(2)
+            .layer(2, Activation::Softmax)
+            .build();
+        let path = env::temp_dir().join("neural_network_checkpoint_version.bin");
+        Trainer::new(config()).save_checkpoint(&network, &path).unwrap();
+        assert!(Trainer::resume(&path).is_ok());
+
+        // Version written right after the magic bytes
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[4] = 2;
+        fs::write(&path, &bytes).unwrap();
+        let error = Trainer::resume(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(error.to_string().contains("Checkpoint version 2 can not be resumed"));
+    }
+
+    #[test]
+    fn empty_dataset_and_missing_policy() {
+        let mut network = NeuralNetworkBuilder::new(2)
+            .layer(2, Activation::Softmax)
+            .build();
+        let mut trainer = Trainer::new(config());
+        assert_eq!(trainer.checkpoint(&network).err().unwrap().kind(), io::ErrorKind::InvalidInput);
+
+        trainer.fit(&mut network, &Array2::zeros((0, 2)), &Array2::zeros((0, 2))).unwrap();
+        assert_eq!(trainer.state().metric_history.len(), 3);
+        assert!(trainer.state().metric_history[0].iter().all(|value| value.is_nan()));
+
+        let mut config = config();
+        config.batch_size = 0;
+        assert!(Trainer::new(config).fit(&mut network, &Array2::zeros((1, 2)), &Array2::zeros((1, 2))).is_err());
+    }
+
+    #[test]
+    fn batches_are_augmented_and_resumed() {
+        let (input, expected_result) = data();
+        let directory = env::temp_dir().join("neural_network_augmented_resume");
+        let _ = fs::remove_dir_all(&directory);
+        let build = || NeuralNetworkBuilder::new(2).layer(2, Activation::Softmax).build();
+        let augmenter = Augmenter::new(1, 2, 5).then(Augmentation::GaussianNoise(0.1));
+
+        let mut plain = build();
+        Trainer::new(config()).fit(&mut plain, &input, &expected_result).unwrap();
+
+        let mut network = build();
+        let mut policy = CheckpointPolicy::new(&directory, 100);
+        policy.every_batches = Some(2);
+        let mut trainer = Trainer::new(config()).augment(augmenter).checkpoints(policy);
+        trainer.fit(&mut network, &input, &expected_result).unwrap();
+        assert!(parameters(&plain) != parameters(&network));
+
+        // The augmenter continues with the same random numbers
+        let (mut resumed_trainer, mut resumed_network) = Trainer::resume(directory.join("checkpoint-0001-000002.bin")).unwrap();
+        resumed_trainer.fit(&mut resumed_network, &input, &expected_result).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(parameters(&network), parameters(&resumed_network));
+    }
+}
@@ -0,0 +1,193 @@
+// Heatmaps of vectors and weight matrices, saved as RGB PNG files
+//
+// Values are normalized with the minimum and maximum of what is rendered. Diverging colormaps are
+// centered on zero instead, so negative and positive values get opposite colors of the same intensity.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use ndarray::{Array2, ArrayView1, ArrayView2};
+use image::ColorType;
+use image::png::PNGEncoder;
+
+
+// Gray between tiles of a grid
+const SEPARATOR: [u8; 3] = [32, 32, 32];
+
+// Evenly spaced colors from 0 to 1, interpolated linearly
+const VIRIDIS: [[u8; 3]; 9] = [
+    [68, 1, 84], [71, 44, 122], [59, 82, 139], [44, 114, 142], [33, 145, 140],
+    [39, 173, 129], [92, 200, 99], [170, 220, 50], [253, 231, 37],
+];
+const COOLWARM: [[u8; 3]; 9] = [
+    [59, 76, 192], [98, 130, 234], [141, 176, 254], [184, 208, 249], [221, 221, 221],
+    [245, 196, 173], [244, 154, 123], [222, 96, 77], [180, 4, 38],
+];
+
+
+#[derive(Copy, Clone)]
+pub enum Colormap {
+    Grayscale,
+    // Sequential, dark purple to yellow
+    Viridis,
+    // Diverging, blue for negative values, gray for zero, red for positive values
+    Coolwarm,
+}
+
+impl Colormap {
+    pub fn is_diverging(&self) -> bool {
+        matches!(*self, Colormap::Coolwarm)
+    }
+
+    // Color of a normalized value between 0 and 1
+    pub fn color(&self, value: f64) -> [u8; 3] {
+        let value = if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) };
+        match *self {
+            Colormap::Grayscale => {
+                let gray = (value * 255.0).round() as u8;
+                [gray, gray, gray]
+            },
+            Colormap::Viridis => interpolate(&VIRIDIS, value),
+            Colormap::Coolwarm => interpolate(&COOLWARM, value),
+        }
+    }
+}
+
+
+// Values between 0 and 1, constant values are all 0.5
+pub fn normalize(values: ArrayView2<f64>, colormap: Colormap) -> Array2<f64> {
+    let minimum = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let maximum = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+
+    if colormap.is_diverging() {
+        let extent = minimum.abs().max(maximum.abs());
+        values.mapv(|v| if extent > 0.0 { 0.5 + v / (2.0 * extent) } else { 0.5 })
+    } else {
+        values.mapv(|v| if maximum > minimum { (v - minimum) / (maximum - minimum) } else { 0.5 })
+    }
+}
+
+// RGB bytes, row after row
+pub fn render(values: ArrayView2<f64>, colormap: Colormap) -> Vec<u8> {
+    normalize(values, colormap).iter().flat_map(|v| colormap.color(*v).to_vec()).collect()
+}
+
+// Every value of the matrix is one pixel
+pub fn save_heatmap<P: AsRef<Path>>(path: P, values: ArrayView2<f64>, colormap: Colormap) -> io::Result<()> {
+    if values.is_empty() {
+        return Err(invalid_input(format!("A {}×{} image can not be saved", values.rows(), values.cols())));
+    }
+    save_png(path, &render(values, colormap), values.cols(), values.rows())
+}
+
+// Vector of height × width values, e.g. one input row of image data
+pub fn save_vector_heatmap<P: AsRef<Path>>(path: P, vector: ArrayView1<f64>, height: usize, width: usize, colormap: Colormap) -> io::Result<()> {
+    if vector.len() != height * width {
+        return Err(invalid_input(format!("{} values can not be shown as {}×{}", vector.len(), height, width)));
+    }
+    let image = vector.to_owned().into_shape((height, width)).unwrap();
+    save_heatmap(path, image.view(), colormap)
+}
+
+// One height × width tile per neuron of a Dense layer (columns of its inputs × neurons weights),
+// each tile normalized on its own, tiles laid out in a square-ish grid separated by one pixel
+pub fn save_weight_grid<P: AsRef<Path>>(path: P, weights: &Array2<f64>, height: usize, width: usize, colormap: Colormap) -> io::Result<()> {
+    if height == 0 || width == 0 || weights.rows() != height * width {
+        return Err(invalid_input(format!("Neurons have {} inputs, they can not be shown as {}×{}", weights.rows(), height, width)));
+    }
+    if weights.cols() == 0 {
+        return Err(invalid_input("No neurons to show".to_owned()));
+    }
+
+    let neurons = weights.cols();
+    let grid_columns = (neurons as f64).sqrt().ceil() as usize;
+    let grid_rows = neurons.div_ceil(grid_columns);
+    let image_width = grid_columns * (width + 1) - 1;
+    let image_height = grid_rows * (height + 1) - 1;
+
+    let mut pixels: Vec<u8> = (0..image_width * image_height).flat_map(|_| SEPARATOR.to_vec()).collect();
+    for (neuron, column) in weights.gencolumns().into_iter().enumerate() {
+        let tile = column.to_owned().into_shape((height, width)).unwrap();
+        let colors = render(tile.view(), colormap);
+
+        let (top, left) = ((neuron / grid_columns) * (height + 1), (neuron % grid_columns) * (width + 1));
+        for y in 0..height {
+            let start = ((top + y) * image_width + left) * 3;
+            pixels[start..start + width * 3].copy_from_slice(&colors[y * width * 3..(y + 1) * width * 3]);
+        }
+    }
+
+    save_png(path, &pixels, image_width, image_height)
+}
+
+
+fn interpolate(colors: &[[u8; 3]], value: f64) -> [u8; 3] {
+    let position = value * (colors.len() - 1) as f64;
+    let index = (position.floor() as usize).min(colors.len() - 2);
+    let fraction = position - index as f64;
+
+    let mut color = [0; 3];
+    for channel in 0..3 {
+        let (from, to) = (colors[index][channel] as f64, colors[index + 1][channel] as f64);
+        color[channel] = (from + (to - from) * fraction).round() as u8;
+    }
+    color
+}
+
+fn save_png<P: AsRef<Path>>(path: P, pixels: &[u8], width: usize, height: usize) -> io::Result<()> {
+    let writer = BufWriter::new(File::create(path)?);
+    PNGEncoder::new(writer).encode(pixels, width as u32, height as u32, ColorType::RGB(8))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use ndarray::arr2;
+    use super::*;
+
+    #[test]
+    fn normalization_and_colors() {
+        // Negative values used to be normalized outside of 0..1
+        let values = arr2(&[[-2.0, 0.0], [1.0, 2.0]]);
+        assert_eq!(normalize(values.view(), Colormap::Viridis), arr2(&[[0.0, 0.5], [0.75, 1.0]]));
+        assert_eq!(normalize(values.view(), Colormap::Coolwarm), arr2(&[[0.0, 0.5], [0.75, 1.0]]));
+        assert_eq!(normalize(arr2(&[[0.0, 1.0]]).view(), Colormap::Coolwarm), arr2(&[[0.5, 1.0]]));
+        assert_eq!(normalize(arr2(&[[3.0, 3.0]]).view(), Colormap::Grayscale), arr2(&[[0.5, 0.5]]));
+
+        assert_eq!(Colormap::Viridis.color(0.0), VIRIDIS[0]);
+        assert_eq!(Colormap::Viridis.color(1.0), VIRIDIS[8]);
+        assert_eq!(Colormap::Coolwarm.color(0.5), [221, 221, 221]);
+        assert_eq!(Colormap::Grayscale.color(1.0), [255, 255, 255]);
+        assert_eq!(render(values.view(), Colormap::Grayscale).len(), 12);
+    }
+
+    #[test]
+    fn saves_heatmaps_and_grids() {
+        let directory = env::temp_dir().join("neural_network_visualization");
+        fs::create_dir_all(&directory).unwrap();
+
+        let vector = arr2(&[[0.1, -0.3, 0.5, 0.2, 0.0, 0.9]]);
+        save_vector_heatmap(directory.join("vector.png"), vector.row(0), 2, 3, Colormap::Viridis).unwrap();
+        assert!(save_vector_heatmap(directory.join("wrong.png"), vector.row(0), 4, 4, Colormap::Viridis).is_err());
+
+        // Five neurons of 2×3 inputs, in a 3×2 grid of tiles
+        let weights = Array2::from_shape_fn((6, 5), |(i, j)| i as f64 - j as f64);
+        save_weight_grid(directory.join("grid.png"), &weights, 2, 3, Colormap::Coolwarm).unwrap();
+        assert!(fs::metadata(directory.join("grid.png")).unwrap().len() > 0);
+
+        // Sizes that would underflow the size of the image
+        assert!(save_weight_grid(directory.join("wrong.png"), &Array2::zeros((0, 5)), 0, 3, Colormap::Viridis).is_err());
+        assert!(save_weight_grid(directory.join("wrong.png"), &Array2::zeros((6, 0)), 2, 3, Colormap::Viridis).is_err());
+        assert!(save_vector_heatmap(directory.join("wrong.png"), vector.slice(s![0, ..0]), 0, 4, Colormap::Viridis).is_err());
+        assert!(!directory.join("wrong.png").exists());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}